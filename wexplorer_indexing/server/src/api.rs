@@ -1,7 +1,13 @@
+use std::{pin::Pin, sync::Arc};
 
+use itertools::Itertools;
+use tokio_stream::{Stream, StreamExt, wrappers::{BroadcastStream, errors::BroadcastStreamRecvError}};
 use tonic::{Request, Response, Status};
+use tracing::warn;
+use url::Url;
 
-use crate::indexing::{Indexer, UrlProcessor};
+use crate::indexing::{self, Indexer, UrlProcessor, CrawlerTrapDetector};
+use crate::queue::{self, PageFilter, PageCursor};
 
 use self::indexing_api_server::IndexingApi;
 
@@ -9,6 +15,7 @@ tonic::include_proto! {"indexing"}
 
 pub struct IndexingApiImpl<U> {
     pub indexer: Indexer<U>,
+    pub crawler_trap_detector: Arc<CrawlerTrapDetector>,
 }
 
 #[tonic::async_trait]
@@ -16,20 +23,278 @@ impl<U> IndexingApi for IndexingApiImpl<U>
 where
     U: UrlProcessor + Clone + Send + Sync + 'static,
 {
+    type WatchIndexingStream = Pin<Box<dyn Stream<Item = Result<IndexingEvent, Status>> + Send>>;
+
     async fn index_web_site(&self, request: Request<IndexWebSiteRequest>) -> Result<Response<()>, Status> {
-        self.indexer.index_page(request.get_ref().origin.parse().map_err(|_| Status::invalid_argument("origin"))?).await;
+        let request = request.get_ref();
+        let origin = request.origin.parse::<Url>().map_err(|_| Status::invalid_argument("origin"))?;
+        let scope = match CrawlScope::try_from(request.scope).map_err(|_| Status::invalid_argument("scope"))? {
+            CrawlScope::SameOrigin => indexing::CrawlScope::SameOrigin,
+            CrawlScope::SameDomain => indexing::CrawlScope::SameDomain,
+            CrawlScope::PathPrefix if request.path_prefix.is_empty() =>
+                indexing::CrawlScope::PathPrefix(origin.path().to_string()),
+            CrawlScope::PathPrefix if request.path_prefix.starts_with('/') =>
+                indexing::CrawlScope::PathPrefix(request.path_prefix.clone()),
+            CrawlScope::PathPrefix => return Err(Status::invalid_argument("path_prefix")),
+        };
+        // Pages are crawled starting from the origin, so nothing would be crawled if it were out of the scope.
+        if !scope.contains(&origin, &origin) {
+            return Err(Status::invalid_argument("origin"));
+        }
+
+        let limits = indexing::CrawlLimits { max_depth: request.max_depth, max_pages: request.max_pages };
+        let ignored_directives = request.ignored_directives.clone().unwrap_or_default();
+        let directives = indexing::PageDirectiveSettings {
+            respect_canonical: !ignored_directives.canonical,
+            respect_meta_robots: !ignored_directives.meta_robots,
+            respect_x_robots_tag: !ignored_directives.x_robots_tag,
+            respect_nofollow: !ignored_directives.nofollow,
+        };
+
+        self.indexer.index_web_site(origin, scope, limits, directives).await;
         Ok(Response::new(()))
     }
 
-    async fn get_indexing_web_sites(&self, _: Request<()>) -> Result<Response<GetIndexingWebSitesResponse>, Status> {
-        Ok(Response::new(GetIndexingWebSitesResponse {
-            origins: vec![],
-        }))
+    async fn get_indexing_web_sites(&self, request: Request<GetIndexingWebSitesRequest>)
+        -> Result<Response<GetIndexingWebSitesResponse>, Status>
+    {
+        let request = request.get_ref();
+        let origin = get_origin_filter(&request.origin).map_err(|_| Status::invalid_argument("origin"))?;
+        let page_size = get_page_size(request.page_size);
+        let after_id = match request.page_token.as_str() {
+            "" => None,
+            page_token => Some(page_token.parse::<i64>().map_err(|_| Status::invalid_argument("page_token"))?),
+        };
+
+        let statuses = self.indexer.get_web_sites(origin.as_deref(), after_id, page_size).await;
+        let mut origin_page_counts = self.indexer
+            .get_origin_page_counts(statuses.iter().map(|s| s.web_site.id).collect())
+            .await
+            .into_iter()
+            .into_group_map_by(|c| c.site_id);
+        let next_page_token = match statuses.last() {
+            Some(last) if statuses.len() == page_size as usize => last.web_site.id.to_string(),
+            _ => String::new(),
+        };
+
+        let web_sites = statuses.into_iter()
+            .map(|status| {
+                let (scope, path_prefix) = match status.web_site.scope {
+                    indexing::CrawlScope::SameOrigin => (CrawlScope::SameOrigin, String::new()),
+                    indexing::CrawlScope::SameDomain => (CrawlScope::SameDomain, String::new()),
+                    indexing::CrawlScope::PathPrefix(path_prefix) => (CrawlScope::PathPrefix, path_prefix),
+                };
+
+                IndexingWebSite {
+                    origin: status.web_site.url.to_string(),
+                    scope: scope.into(),
+                    path_prefix,
+                    max_depth: status.web_site.limits.max_depth,
+                    max_pages: status.web_site.limits.max_pages,
+                    pages_count: status.pages_count,
+                    budget_exhausted: status.budget_exhausted,
+                    updated_pages_count: status.stats.updated_pages_count,
+                    unchanged_pages_count: status.stats.unchanged_pages_count,
+                    origins: origin_page_counts.remove(&status.web_site.id).unwrap_or_default().into_iter()
+                        .map(|c| OriginPageCounts {
+                            origin: c.origin,
+                            queued: c.queued,
+                            in_progress: c.in_progress,
+                            indexed: c.indexed,
+                            failed: c.failed,
+                        })
+                        .collect(),
+                    ignored_directives: Some(IgnoredPageDirectives {
+                        canonical: !status.web_site.directives.respect_canonical,
+                        meta_robots: !status.web_site.directives.respect_meta_robots,
+                        x_robots_tag: !status.web_site.directives.respect_x_robots_tag,
+                        nofollow: !status.web_site.directives.respect_nofollow,
+                    }),
+                }
+            })
+            .collect();
+
+        Ok(Response::new(GetIndexingWebSitesResponse { web_sites, next_page_token }))
+    }
+
+    async fn get_indexing_pages(&self, request: Request<GetIndexingPagesRequest>)
+        -> Result<Response<GetIndexingPagesResponse>, Status>
+    {
+        let request = request.get_ref();
+        let filter = PageFilter {
+            origin: get_origin_filter(&request.origin).map_err(|_| Status::invalid_argument("origin"))?,
+            status: match PageStatus::try_from(request.status).map_err(|_| Status::invalid_argument("status"))? {
+                PageStatus::Unspecified => None,
+                status => Some(to_queue_page_status(status)),
+            },
+        };
+        let page_size = get_page_size(request.page_size);
+        let cursor = match request.page_token.split_once(':') {
+            _ if request.page_token.is_empty() => None,
+            Some((status, url)) => {
+                let status = status.parse::<i32>().ok()
+                    .and_then(|s| PageStatus::try_from(s).ok())
+                    .filter(|s| *s != PageStatus::Unspecified)
+                    .ok_or_else(|| Status::invalid_argument("page_token"))?;
+                let url = url.parse::<Url>().map_err(|_| Status::invalid_argument("page_token"))?;
+                Some(PageCursor { url, status: to_queue_page_status(status) })
+            },
+            None => return Err(Status::invalid_argument("page_token")),
+        };
+
+        let pages = self.indexer.get_pages(&filter, cursor.as_ref(), page_size).await;
+        let next_page_token = match pages.last() {
+            Some(last) if pages.len() == page_size as usize =>
+                format!("{}:{}", from_queue_page_status(last.status) as i32, last.url),
+            _ => String::new(),
+        };
+
+        let pages = pages.into_iter()
+            .map(|page| IndexingPage {
+                url: page.url.to_string(),
+                status: from_queue_page_status(page.status).into(),
+                depth: page.depth,
+                attempts: page.attempts,
+                last_error: page.last_error.unwrap_or_default(),
+                last_indexed_timestamp: page.last_indexed_time.map(|t| t.timestamp_millis()),
+            })
+            .collect();
+
+        Ok(Response::new(GetIndexingPagesResponse { pages, next_page_token }))
+    }
+
+    async fn watch_indexing(&self, request: Request<WatchIndexingRequest>)
+        -> Result<Response<Self::WatchIndexingStream>, Status>
+    {
+        let origin = get_origin_filter(&request.get_ref().origin).map_err(|_| Status::invalid_argument("origin"))?;
+        let events = BroadcastStream::new(self.indexer.subscribe_to_events())
+            .filter_map(move |event| match event {
+                Ok(event) => origin.as_ref()
+                    .filter(|o| **o != event.url.origin().ascii_serialization())
+                    .is_none()
+                    .then(|| to_proto_indexing_event(event)),
+                Err(BroadcastStreamRecvError::Lagged(skipped_count)) => {
+                    warn!("Indexing watcher is too slow, {} events are skipped", skipped_count);
+                    None
+                },
+            })
+            .map(Ok);
+
+        Ok(Response::new(Box::pin(events)))
     }
 
-    async fn get_indexing_pages(&self, _: Request<()>) -> Result<Response<GetIndexingPagesResponse>, Status> {
-        Ok(Response::new(GetIndexingPagesResponse {
-            pages: vec![],
-        }))
+    async fn get_duplicate_pages(&self, request: Request<GetDuplicatePagesRequest>)
+        -> Result<Response<GetDuplicatePagesResponse>, Status>
+    {
+        let request = request.get_ref();
+        let origin = get_origin_filter(&request.origin).map_err(|_| Status::invalid_argument("origin"))?;
+        let page_size = get_page_size(request.page_size);
+        let after_url = match request.page_token.as_str() {
+            "" => None,
+            page_token => Some(page_token.parse::<Url>().map_err(|_| Status::invalid_argument("page_token"))?),
+        };
+
+        let duplicates = self.indexer.get_duplicates(origin.as_deref(), after_url.as_ref(), page_size).await;
+        let next_page_token = match duplicates.last() {
+            Some(last) if duplicates.len() == page_size as usize => last.url.to_string(),
+            _ => String::new(),
+        };
+
+        let pages = duplicates.into_iter()
+            .map(|d| DuplicatePage {
+                url: d.url.to_string(),
+                original_url: d.original_url.to_string(),
+                distance: d.distance,
+            })
+            .collect();
+
+        Ok(Response::new(GetDuplicatePagesResponse { pages, next_page_token }))
     }
-}
\ No newline at end of file
+
+    async fn get_crawler_traps(&self, request: Request<GetCrawlerTrapsRequest>)
+        -> Result<Response<GetCrawlerTrapsResponse>, Status>
+    {
+        let origin = get_origin_filter(&request.get_ref().origin).map_err(|_| Status::invalid_argument("origin"))?;
+        let traps = self.crawler_trap_detector.get_traps(origin.as_deref()).into_iter()
+            .map(|trap| CrawlerTrap {
+                origin: trap.origin,
+                path_glob: trap.path_glob,
+                kind: match trap.kind {
+                    indexing::CrawlerTrapKind::RepeatedPathSegments => CrawlerTrapKind::RepeatedPathSegments,
+                    indexing::CrawlerTrapKind::ExcessivePathDepth => CrawlerTrapKind::ExcessivePathDepth,
+                    indexing::CrawlerTrapKind::TooManyQueryCombinations => CrawlerTrapKind::TooManyQueryCombinations,
+                    indexing::CrawlerTrapKind::TooManyUrlsPerPattern => CrawlerTrapKind::TooManyUrlsPerPattern,
+                }.into(),
+                example_url: trap.example_url.to_string(),
+                rejected_urls_count: trap.rejected_urls_count,
+                detected_timestamp: trap.detected_time.timestamp_millis(),
+            })
+            .collect();
+
+        Ok(Response::new(GetCrawlerTrapsResponse { traps }))
+    }
+}
+
+const DEFAULT_PAGE_SIZE: u32 = 100;
+
+const MAX_PAGE_SIZE: u32 = 1000;
+
+fn get_page_size(page_size: u32) -> u32 {
+    if page_size == 0 { DEFAULT_PAGE_SIZE } else { page_size.min(MAX_PAGE_SIZE) }
+}
+
+fn get_origin_filter(origin: &str) -> Result<Option<String>, url::ParseError> {
+    if origin.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(origin.parse::<Url>()?.origin().ascii_serialization()))
+}
+
+fn to_queue_page_status(status: PageStatus) -> queue::PageStatus {
+    match status {
+        PageStatus::Unspecified | PageStatus::Queued => queue::PageStatus::Queued,
+        PageStatus::InProgress => queue::PageStatus::InProgress,
+        PageStatus::Indexed => queue::PageStatus::Indexed,
+        PageStatus::Failed => queue::PageStatus::Failed,
+    }
+}
+
+fn from_queue_page_status(status: queue::PageStatus) -> PageStatus {
+    match status {
+        queue::PageStatus::Queued => PageStatus::Queued,
+        queue::PageStatus::InProgress => PageStatus::InProgress,
+        queue::PageStatus::Indexed => PageStatus::Indexed,
+        queue::PageStatus::Failed => PageStatus::Failed,
+    }
+}
+
+fn to_proto_indexing_event(event: indexing::IndexingEvent) -> IndexingEvent {
+    let mut result = IndexingEvent {
+        url: event.url.to_string(),
+        timestamp: event.time.timestamp_millis(),
+        ..Default::default()
+    };
+
+    let event_type = match event.kind {
+        indexing::IndexingEventKind::Enqueued => IndexingEventType::Enqueued,
+        indexing::IndexingEventKind::Fetched => IndexingEventType::Fetched,
+        indexing::IndexingEventKind::NotModified => IndexingEventType::NotModified,
+        indexing::IndexingEventKind::Extracted { links_count } => {
+            result.links_count = links_count as u32;
+            IndexingEventType::Extracted
+        },
+        indexing::IndexingEventKind::Sent => IndexingEventType::Sent,
+        indexing::IndexingEventKind::Failed { error, will_retry } => {
+            result.error = error;
+            result.will_retry = will_retry;
+            IndexingEventType::Failed
+        },
+        indexing::IndexingEventKind::Skipped { reason } => {
+            result.skip_reason = reason;
+            IndexingEventType::Skipped
+        },
+    };
+    result.set_type(event_type);
+    result
+}
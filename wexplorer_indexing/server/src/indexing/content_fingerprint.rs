@@ -0,0 +1,152 @@
+use std::hash::Hasher;
+
+use serde::Deserialize;
+use siphasher::sip::SipHasher13;
+use thiserror::Error;
+use url::Url;
+
+// Count of words in the overlapping word sequences which make up the SimHash of a text.
+const SHINGLE_SIZE: usize = 3;
+
+// Near-duplicates are looked up only among pages with an equal 16-bit band of the SimHash. Hashes which differ in
+// fewer bits than the count of bands always have an equal band, more distant pages may be missed.
+pub const SIMHASH_BANDS_COUNT: usize = 4;
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateSettings {
+    // Max count of differing SimHash bits of near-duplicate pages.
+    pub max_distance: u32,
+    // Duplicates aren't sent to the searching service if it's set, otherwise they are only linked to their originals.
+    pub skip_duplicates: bool,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DuplicateSettingsError {
+    // More distant near-duplicates may have no equal SimHash band, so only some of them would be found.
+    #[error("max distance must be less than {}", SIMHASH_BANDS_COUNT)]
+    MaxDistanceTooLarge,
+}
+
+impl DuplicateSettings {
+    pub fn validate(&self) -> Result<(), DuplicateSettingsError> {
+        if self.max_distance as usize >= SIMHASH_BANDS_COUNT {
+            return Err(DuplicateSettingsError::MaxDistanceTooLarge);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ContentFingerprint {
+    // Hash of the normalized text, equal for texts which differ only in case, punctuation and whitespace.
+    pub hash: u64,
+    // Locality-sensitive hash, similar texts have hashes which differ in a few bits.
+    pub simhash: u64,
+}
+
+impl ContentFingerprint {
+    pub fn new(text: &str) -> Self {
+        let words = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_lowercase())
+            .collect::<Vec<_>>();
+
+        let mut bit_weights = [0i32; 64];
+        for shingle in words.windows(SHINGLE_SIZE.min(words.len()).max(1)) {
+            let shingle_hash = hash(&shingle.join(" "));
+            for (bit, weight) in bit_weights.iter_mut().enumerate() {
+                *weight += if shingle_hash >> bit & 1 == 1 { 1 } else { -1 };
+            }
+        }
+
+        let simhash = bit_weights.iter()
+            .enumerate()
+            .filter(|(_, weight)| **weight > 0)
+            .fold(0, |simhash, (bit, _)| simhash | 1 << bit);
+
+        Self { hash: hash(&words.join(" ")), simhash }
+    }
+
+    pub fn distance(&self, other: &ContentFingerprint) -> u32 {
+        if self.hash == other.hash { 0 } else { (self.simhash ^ other.simhash).count_ones() }
+    }
+
+    pub fn simhash_bands(&self) -> [u16; SIMHASH_BANDS_COUNT] {
+        std::array::from_fn(|i| (self.simhash >> (i * 16)) as u16)
+    }
+
+    pub fn has_common_simhash_band(&self, other: &ContentFingerprint) -> bool {
+        self.simhash_bands().iter().zip(other.simhash_bands()).any(|(band, other_band)| *band == other_band)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentDuplicate {
+    pub original_url: Url,
+    pub distance: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DuplicatePage {
+    pub url: Url,
+    pub original_url: Url,
+    pub distance: u32,
+}
+
+fn hash(text: &str) -> u64 {
+    let mut hasher = SipHasher13::new();
+    hasher.write(text.as_bytes());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod content_fingerprint_tests {
+    use super::*;
+
+    #[test]
+    fn should_measure_distance_between_similar_texts() {
+        // Arrange
+
+        let text = "Lifetimes are one of the features that make Rust stand out. They let the compiler verify \
+            that every reference is valid for as long as it is used, without a garbage collector and without \
+            runtime checks.";
+        let target = ContentFingerprint::new(text);
+
+        // Act
+
+        let result1 = target.distance(&ContentFingerprint::new(&text.to_uppercase().replace(' ', "\n ")));
+        let result2 = target.distance(&ContentFingerprint::new(&text.replace("garbage collector", "GC")));
+        let result3 = target.distance(&ContentFingerprint::new(
+            "Der Stadtrat hat den Bau eines neuen Radwegs entlang der Hauptstraße beschlossen, die Kosten \
+            von rund 2,1 Millionen Euro sollen zu zwei Dritteln aus Fördermitteln des Landes gedeckt werden."));
+
+        // Assert
+
+        assert_eq!(0, result1);
+        assert!(result2 > 0 && result2 <= 12, "{}", result2);
+        assert!(result3 > 20, "{}", result3);
+    }
+
+    #[test]
+    fn should_share_simhash_band_with_close_fingerprints() {
+        // Arrange
+
+        let target = ContentFingerprint { hash: 1, simhash: 0x1234_5678_9abc_def0 };
+
+        // Act
+
+        let with_simhash = |simhash| ContentFingerprint { hash: 2, simhash };
+        let result1 = target.has_common_simhash_band(&with_simhash(target.simhash ^ 0x0001_0001_0001));
+        let result2 = target.has_common_simhash_band(&with_simhash(target.simhash ^ 0x0001_0001_0001_0001));
+        let result3 = target.has_common_simhash_band(&with_simhash(!target.simhash));
+
+        // Assert
+
+        assert_eq!([0xdef0, 0x9abc, 0x5678, 0x1234], target.simhash_bands());
+        assert!(result1);
+        assert!(!result2);
+        assert!(!result3);
+    }
+}
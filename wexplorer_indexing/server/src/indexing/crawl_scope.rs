@@ -0,0 +1,128 @@
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CrawlScope {
+    SameOrigin,
+    SameDomain,
+    PathPrefix(String),
+}
+
+impl CrawlScope {
+    pub fn contains(&self, seed_url: &Url, url: &Url) -> bool {
+        match self {
+            CrawlScope::SameOrigin => is_same_origin(seed_url, url),
+            CrawlScope::SameDomain => {
+                let (Some(seed_host), Some(host)) = (seed_url.host_str(), url.host_str()) else { return false; };
+                match (get_registrable_domain(seed_host), get_registrable_domain(host)) {
+                    (Some(seed_domain), Some(domain)) => seed_domain == domain,
+                    // IP addresses and hosts without a public suffix (e.g. localhost) must match exactly.
+                    _ => seed_host.eq_ignore_ascii_case(host),
+                }
+            },
+            CrawlScope::PathPrefix(path_prefix) => is_same_origin(seed_url, url) && is_under_path(url, path_prefix),
+        }
+    }
+}
+
+// The prefix matches whole path segments, so "/docs" contains "/docs/page" but not "/docsearch".
+fn is_under_path(url: &Url, path_prefix: &str) -> bool {
+    match url.path().strip_prefix(path_prefix) {
+        Some(rest) => rest.is_empty() || path_prefix.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+// Origins of file URLs are opaque and never equal, so local files are considered to be of the same origin if their
+// hosts are equal.
+fn is_same_origin(seed_url: &Url, url: &Url) -> bool {
+    match (seed_url.scheme(), url.scheme()) {
+        ("file", "file") => seed_url.host() == url.host(),
+        _ => url.origin() == seed_url.origin(),
+    }
+}
+
+fn get_registrable_domain(host: &str) -> Option<String> {
+    let host = host.to_ascii_lowercase();
+    psl::domain_str(&host).map(|d| d.to_string())
+}
+
+#[cfg(test)]
+mod crawl_scope_tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn should_contain_only_urls_of_same_origin() {
+        // Arrange
+
+        let target = CrawlScope::SameOrigin;
+        let seed_url = url("https://docs.example.com/guide/");
+
+        // Act
+
+        let result1 = target.contains(&seed_url, &url("https://docs.example.com/api"));
+        let result2 = target.contains(&seed_url, &url("http://docs.example.com/api"));
+        let result3 = target.contains(&seed_url, &url("https://blog.example.com/"));
+        let result4 = CrawlScope::SameOrigin.contains(&url("file:///home/docs/"), &url("file:///home/docs/a.html"));
+
+        // Assert
+
+        assert!(result1);
+        assert!(!result2);
+        assert!(!result3);
+        assert!(result4);
+    }
+
+    #[test]
+    fn should_contain_urls_of_same_registrable_domain() {
+        // Arrange
+
+        let target = CrawlScope::SameDomain;
+        let seed_url = url("https://docs.example.co.uk/");
+
+        // Act
+
+        let result1 = target.contains(&seed_url, &url("http://blog.example.co.uk/post"));
+        let result2 = target.contains(&seed_url, &url("https://example.co.uk/"));
+        let result3 = target.contains(&seed_url, &url("https://other.co.uk/"));
+        let result4 = target.contains(&url("http://127.0.0.1/"), &url("http://127.0.0.1:8080/"));
+
+        // Assert
+
+        assert!(result1);
+        assert!(result2);
+        assert!(!result3);
+        assert!(result4);
+    }
+
+    #[test]
+    fn should_contain_urls_under_path_prefix() {
+        // Arrange
+
+        let target = CrawlScope::PathPrefix("/docs/".to_string());
+        let seed_url = url("https://example.com/docs/");
+
+        // Act
+
+        let result1 = target.contains(&seed_url, &url("https://example.com/docs/page?id=1"));
+        let result2 = target.contains(&seed_url, &url("https://example.com/blog/"));
+        let result3 = target.contains(&seed_url, &url("https://other.com/docs/page"));
+        let result4 = CrawlScope::PathPrefix("/docs".to_string())
+            .contains(&seed_url, &url("https://example.com/docs/page"));
+        let result5 = CrawlScope::PathPrefix("/docs".to_string()).contains(&seed_url, &url("https://example.com/docs"));
+        let result6 = CrawlScope::PathPrefix("/docs".to_string())
+            .contains(&seed_url, &url("https://example.com/docsearch"));
+
+        // Assert
+
+        assert!(result1);
+        assert!(!result2);
+        assert!(!result3);
+        assert!(result4);
+        assert!(result5);
+        assert!(!result6);
+    }
+}
@@ -0,0 +1,269 @@
+use std::{collections::{HashMap, HashSet}, hash::Hasher, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use hashlink::LruCache;
+use itertools::Itertools;
+use serde::Deserialize;
+use siphasher::sip::SipHasher13;
+use tracing::warn;
+use url::Url;
+
+use super::UrlFilter;
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrawlerTrapSettings {
+    // Max count of occurrences of the same segment in a path.
+    pub max_repeated_segments: usize,
+    pub max_path_depth: usize,
+    // Max count of distinct queries of the same path.
+    pub max_query_combinations: usize,
+    // Max count of distinct URLs matched by the same URL pattern, in which path segments with digits are wildcards.
+    pub max_urls_per_pattern: usize,
+    // Max count of paths and URL patterns whose queries and URLs are counted, and of reported traps. The least recently
+    // seen are forgotten.
+    pub max_tracked_paths: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CrawlerTrapKind {
+    RepeatedPathSegments,
+    ExcessivePathDepth,
+    TooManyQueryCombinations,
+    TooManyUrlsPerPattern,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrawlerTrap {
+    pub origin: String,
+    // Glob of paths of the trapped URLs, e.g. /calendar/*/*. Queries of the path vary if there are too many of them.
+    pub path_glob: String,
+    pub kind: CrawlerTrapKind,
+    pub example_url: Url,
+    pub rejected_urls_count: u64,
+    pub detected_time: DateTime<Utc>,
+}
+
+struct CrawlerTrapState {
+    // Hashes of distinct queries by origin and path, and of distinct URLs by URL pattern. Sets stop growing when
+    // they reach the limit, URLs which are already in them are still allowed.
+    path_queries: LruCache<String, HashSet<u64>>,
+    pattern_urls: LruCache<String, HashSet<u64>>,
+    traps: LruCache<(String, String, CrawlerTrapKind), CrawlerTrap>,
+}
+
+// Detects unbounded URL spaces, like calendars, faceted navigation and session ids in links, and rejects their URLs.
+// The state is kept in memory, so limits are counted from the start of the indexing service. URLs should be checked
+// after they are normalized and found within the scope of their web site, so that only crawled URLs are counted.
+pub struct CrawlerTrapDetector {
+    settings: CrawlerTrapSettings,
+    state: Mutex<CrawlerTrapState>,
+}
+
+impl CrawlerTrapDetector {
+    pub fn new(settings: CrawlerTrapSettings) -> Self {
+        let state = CrawlerTrapState {
+            path_queries: LruCache::new(settings.max_tracked_paths),
+            pattern_urls: LruCache::new(settings.max_tracked_paths),
+            traps: LruCache::new(settings.max_tracked_paths),
+        };
+        Self { settings, state: Mutex::new(state) }
+    }
+
+    pub fn get_traps(&self, origin: Option<&str>) -> Vec<CrawlerTrap> {
+        self.state.lock().unwrap().traps.iter()
+            .map(|(_, t)| t)
+            .filter(|t| origin.is_none_or(|o| t.origin == o))
+            .cloned()
+            .sorted_by(|a, b| (&a.origin, &a.path_glob, a.kind).cmp(&(&b.origin, &b.path_glob, b.kind)))
+            .collect()
+    }
+
+    fn detect_trap(&self, url: &Url, state: &mut CrawlerTrapState) -> Option<(String, CrawlerTrapKind)> {
+        let origin = url.origin().ascii_serialization();
+        let segments = url.path_segments().map(|s| s.filter(|s| !s.is_empty()).collect::<Vec<_>>())?;
+
+        if segments.len() > self.settings.max_path_depth {
+            let prefix = segments[..self.settings.max_path_depth].iter().map(|s| generalize_segment(s)).join("/");
+            return Some((format!("/{}/**", prefix), CrawlerTrapKind::ExcessivePathDepth));
+        }
+
+        let mut segment_counts = HashMap::new();
+        for (index, segment) in segments.iter().enumerate() {
+            let count = segment_counts.entry(segment).or_insert(0);
+            *count += 1;
+            if *count > self.settings.max_repeated_segments {
+                let prefix = segments[..index].iter().map(|s| generalize_segment(s)).join("/");
+                let path_glob = format!("/{}/**", prefix);
+                return Some((path_glob, CrawlerTrapKind::RepeatedPathSegments));
+            }
+        }
+
+        let path_key = format!("{}{}", origin, url.path());
+        let query_hash = url.query().map(hash);
+        let path_queries = state.path_queries.get(&path_key);
+        if query_hash.is_some_and(|h| is_over_limit(path_queries, h, self.settings.max_query_combinations)) {
+            return Some((url.path().to_string(), CrawlerTrapKind::TooManyQueryCombinations));
+        }
+
+        let path_glob = format!("/{}", segments.iter().map(|s| generalize_segment(s)).join("/"));
+        let pattern_key = format!("{}{}", origin, path_glob);
+        let url_hash = hash(url.as_str());
+        if is_over_limit(state.pattern_urls.get(&pattern_key), url_hash, self.settings.max_urls_per_pattern) {
+            return Some((path_glob, CrawlerTrapKind::TooManyUrlsPerPattern));
+        }
+
+        if let Some(query_hash) = query_hash {
+            insert_hash(&mut state.path_queries, path_key, query_hash);
+        }
+
+        insert_hash(&mut state.pattern_urls, pattern_key, url_hash);
+        None
+    }
+}
+
+impl UrlFilter for CrawlerTrapDetector {
+    fn is_match(&self, url: &Url) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some((path_glob, kind)) = self.detect_trap(url, &mut state) else { return true; };
+
+        let origin = url.origin().ascii_serialization();
+        let key = (origin.clone(), path_glob.clone(), kind);
+        match state.traps.get_mut(&key) {
+            Some(trap) => trap.rejected_urls_count += 1,
+            None => {
+                warn!("Crawler trap {:?} with path glob {} is detected at URL {}", kind, path_glob, url);
+                state.traps.insert(key, CrawlerTrap {
+                    origin,
+                    path_glob,
+                    kind,
+                    example_url: url.clone(),
+                    rejected_urls_count: 1,
+                    detected_time: Utc::now(),
+                });
+            },
+        }
+
+        false
+    }
+}
+
+fn is_over_limit(hashes: Option<&HashSet<u64>>, hash: u64, limit: usize) -> bool {
+    hashes.is_some_and(|h| h.len() >= limit && !h.contains(&hash))
+}
+
+fn insert_hash(hashes_by_key: &mut LruCache<String, HashSet<u64>>, key: String, hash: u64) {
+    match hashes_by_key.get_mut(&key) {
+        Some(hashes) => {
+            hashes.insert(hash);
+        },
+        None => {
+            hashes_by_key.insert(key, HashSet::from([hash]));
+        },
+    }
+}
+
+// Segments with digits are usually dates, page numbers or ids.
+fn generalize_segment(segment: &str) -> &str {
+    if segment.contains(|c: char| c.is_ascii_digit()) { "*" } else { segment }
+}
+
+fn hash(text: &str) -> u64 {
+    let mut hasher = SipHasher13::new();
+    hasher.write(text.as_bytes());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod crawler_trap_detector_tests {
+    use super::*;
+
+    fn create_target() -> CrawlerTrapDetector {
+        CrawlerTrapDetector::new(CrawlerTrapSettings {
+            max_repeated_segments: 2,
+            max_path_depth: 6,
+            max_query_combinations: 2,
+            max_urls_per_pattern: 3,
+            max_tracked_paths: 100,
+        })
+    }
+
+    #[test]
+    fn should_reject_urls_with_repeated_segments_or_deep_paths() {
+        // Arrange
+
+        let target = create_target();
+
+        // Act
+
+        let results = [
+            "https://localhost/a/b/a/b", "https://localhost/a/b/a/b/a/b", "https://localhost/a/b/a/b/a/c",
+            "https://localhost/1/2/3/4/5/6", "https://localhost/1/2/3/4/5/6/7", "https://localhost/a/1/a/2/a/3",
+        ].map(|u| target.is_match(&Url::parse(u).unwrap()));
+        let traps = target.get_traps(None);
+
+        // Assert
+
+        assert_eq!([true, false, false, true, false, false], results);
+        assert_eq!(
+            vec![
+                ("/*/*/*/*/*/*/**".to_string(), CrawlerTrapKind::ExcessivePathDepth, 1),
+                ("/a/*/a/*/**".to_string(), CrawlerTrapKind::RepeatedPathSegments, 1),
+                ("/a/b/a/b/**".to_string(), CrawlerTrapKind::RepeatedPathSegments, 2),
+            ],
+            traps.into_iter().map(|t| (t.path_glob, t.kind, t.rejected_urls_count)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn should_reject_new_urls_over_limits() {
+        // Arrange
+
+        let target = create_target();
+
+        // Act
+
+        let results = [
+            "https://localhost/search?q=a", "https://localhost/search?q=b", "https://localhost/search?q=c",
+            "https://localhost/search?q=a", "https://localhost/calendar/2023/01", "https://localhost/calendar/2023/02",
+            "https://localhost/calendar/2023/03", "https://localhost/calendar/2023/04",
+            "https://localhost/calendar/2023/01", "https://example.com/calendar/2023/04",
+        ].map(|u| target.is_match(&Url::parse(u).unwrap()));
+        let traps = target.get_traps(Some("https://localhost"));
+
+        // Assert
+
+        assert_eq!([true, true, false, true, true, true, true, false, true, true], results);
+        assert_eq!(
+            vec![
+                ("https://localhost", "/calendar/*/*", CrawlerTrapKind::TooManyUrlsPerPattern),
+                ("https://localhost", "/search", CrawlerTrapKind::TooManyQueryCombinations),
+            ],
+            traps.iter().map(|t| (t.origin.as_str(), t.path_glob.as_str(), t.kind)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn should_forget_least_recently_seen_paths() {
+        // Arrange
+
+        let target = CrawlerTrapDetector::new(CrawlerTrapSettings {
+            max_repeated_segments: 2,
+            max_path_depth: 6,
+            max_query_combinations: 1,
+            max_urls_per_pattern: 100,
+            max_tracked_paths: 1,
+        });
+
+        // Act
+
+        let results = [
+            "https://localhost/search?q=a", "https://localhost/search?q=b", "https://localhost/other?q=a",
+            "https://localhost/other?q=b", "https://localhost/search?q=b",
+        ].map(|u| target.is_match(&Url::parse(u).unwrap()));
+        let traps = target.get_traps(None);
+
+        // Assert
+
+        assert_eq!([true, false, true, false, true], results);
+        assert_eq!(vec!["/other"], traps.iter().map(|t| t.path_glob.as_str()).collect::<Vec<_>>());
+    }
+}
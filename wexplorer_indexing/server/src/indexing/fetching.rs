@@ -0,0 +1,399 @@
+use std::{collections::HashMap, fmt, fs, io::{ErrorKind, Read}, path::{Path, PathBuf}, sync::Arc, time::Duration};
+#[cfg(test)]
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use encoding_rs::{Encoding, UTF_8};
+use reqwest::{redirect::Policy, StatusCode};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, LAST_MODIFIED, IF_MODIFIED_SINCE};
+use url::Url;
+
+use super::CRAWLER_USER_AGENT;
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+// File served for a directory URL if the directory has it, otherwise a page with links to the directory entries is
+// generated.
+const DIRECTORY_INDEX_FILE: &str = "index.html";
+
+pub type DynFetcher = Arc<dyn Fetcher>;
+
+#[derive(Clone, Debug)]
+pub struct FetchResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    // URL of the response after redirects.
+    pub url: Url,
+    pub body: Vec<u8>,
+}
+
+impl FetchResponse {
+    pub fn new(status: StatusCode, url: Url) -> Self {
+        Self { status, headers: HeaderMap::new(), url, body: Vec::new() }
+    }
+
+    // Decodes the body with the charset of the Content-Type header, UTF-8 is used if it's not specified.
+    pub fn text(&self) -> String {
+        let encoding = self.headers.get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').find_map(|p| p.trim().strip_prefix("charset=")))
+            .and_then(|charset| Encoding::for_label(charset.trim_matches('"').as_bytes()))
+            .unwrap_or(UTF_8);
+        encoding.decode(&self.body).0.into_owned()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum FetchError {
+    // Timeouts, connection errors, server errors and throttling which may pass by the next attempt.
+    Transient { message: String, retry_after: Option<Duration> },
+    // Client errors and content which can't be indexed.
+    Permanent(String),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Transient { message, .. } | FetchError::Permanent(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() {
+            FetchError::Transient { message: err.to_string(), retry_after: None }
+        }
+        else {
+            FetchError::Permanent(err.to_string())
+        }
+    }
+}
+
+// Responses with any status are returned, errors are returned only if there is no response at all. Bodies larger
+// than the max size aren't downloaded to the end, a permanent error is returned for them.
+#[tonic::async_trait]
+pub trait Fetcher: Send + Sync {
+    async fn fetch(&self, url: &Url, headers: HeaderMap, max_size: u64) -> Result<FetchResponse, FetchError>;
+}
+
+#[tonic::async_trait]
+impl<T: Fetcher + ?Sized> Fetcher for Arc<T> {
+    async fn fetch(&self, url: &Url, headers: HeaderMap, max_size: u64) -> Result<FetchResponse, FetchError> {
+        (**self).fetch(url, headers, max_size).await
+    }
+}
+
+fn too_large_error(url: &Url, max_size: u64) -> FetchError {
+    FetchError::Permanent(format!("Response {} is larger than {} bytes", url, max_size))
+}
+
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+impl HttpFetcher {
+    pub fn new(timeout: Duration, max_redirects: usize) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(CRAWLER_USER_AGENT)
+            .timeout(timeout)
+            .redirect(Policy::limited(max_redirects))
+            .build()
+            .unwrap();
+
+        Self { client }
+    }
+}
+
+#[tonic::async_trait]
+impl Fetcher for HttpFetcher {
+    async fn fetch(&self, url: &Url, headers: HeaderMap, max_size: u64) -> Result<FetchResponse, FetchError> {
+        let mut response = self.client.get(url.clone()).headers(headers).send().await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let url = response.url().clone();
+        if response.content_length().is_some_and(|l| l > max_size) {
+            return Err(too_large_error(&url, max_size));
+        }
+
+        // Content-Length may be missing or wrong, so the size is checked while the body is downloaded.
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if (body.len() + chunk.len()) as u64 > max_size {
+                return Err(too_large_error(&url, max_size));
+            }
+
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(FetchResponse { status, headers, url, body })
+    }
+}
+
+// Serves file URLs from the local file system, like a static HTTP server does.
+pub struct FileFetcher {}
+
+#[tonic::async_trait]
+impl Fetcher for FileFetcher {
+    async fn fetch(&self, url: &Url, headers: HeaderMap, max_size: u64) -> Result<FetchResponse, FetchError> {
+        let path = url.to_file_path().map_err(|_| FetchError::Permanent(format!("Invalid file URL {}", url)))?;
+        let url = url.clone();
+
+        tokio::task::spawn_blocking(move || read_file(url, &path, &headers, max_size))
+            .await
+            .map_err(|err| FetchError::Transient { message: err.to_string(), retry_after: None })?
+    }
+}
+
+fn read_file(mut url: Url, path: &Path, request_headers: &HeaderMap, max_size: u64)
+    -> Result<FetchResponse, FetchError>
+{
+    let to_fetch_error = |err: std::io::Error| FetchError::Permanent(err.to_string());
+    let mut metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(FetchResponse::new(StatusCode::NOT_FOUND, url)),
+        Err(err) => return Err(to_fetch_error(err)),
+    };
+
+    let mut path = path.to_path_buf();
+    if metadata.is_dir() {
+        // Relative links of the directory page are resolved against the directory itself.
+        if !url.path().ends_with('/') {
+            let directory_path = format!("{}/", url.path());
+            url.set_path(&directory_path);
+        }
+
+        match fs::metadata(path.join(DIRECTORY_INDEX_FILE)) {
+            Ok(index_metadata) if index_metadata.is_file() => {
+                path.push(DIRECTORY_INDEX_FILE);
+                metadata = index_metadata;
+            },
+            _ => return create_directory_listing(url, &path).map_err(to_fetch_error),
+        }
+    }
+
+    let mut response = FetchResponse::new(StatusCode::OK, url);
+    if let Ok(modified_time) = metadata.modified() {
+        let modified_time = DateTime::<Utc>::from(modified_time);
+        let if_modified_since = request_headers.get(IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok());
+        if if_modified_since.is_some_and(|t| modified_time.timestamp() <= t.timestamp()) {
+            response.status = StatusCode::NOT_MODIFIED;
+            return Ok(response);
+        }
+
+        let last_modified = modified_time.format(HTTP_DATE_FORMAT).to_string();
+        response.headers.insert(LAST_MODIFIED, HeaderValue::from_str(&last_modified).unwrap());
+    }
+
+    if metadata.len() > max_size {
+        return Err(too_large_error(&response.url, max_size));
+    }
+
+    // Devices, pipes and growing files may be longer than their length, so one byte over the max size is read to
+    // find out if the file is too large.
+    let file = fs::File::open(&path).map_err(to_fetch_error)?;
+    file.take(max_size.saturating_add(1)).read_to_end(&mut response.body).map_err(to_fetch_error)?;
+    if response.body.len() as u64 > max_size {
+        return Err(too_large_error(&response.url, max_size));
+    }
+
+    response.headers.insert(CONTENT_TYPE, HeaderValue::from_static(get_content_type(&path)));
+    Ok(response)
+}
+
+fn create_directory_listing(url: Url, path: &Path) -> Result<FetchResponse, std::io::Error> {
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    entries.sort();
+
+    let escape = |text: &str| text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+    let links = entries.iter()
+        .filter_map(|entry| {
+            let name = entry.file_name()?.to_string_lossy();
+            let entry_url = Url::from_file_path(entry).ok()?;
+            Some(format!("<li><a href=\"{}\">{}</a></li>", escape(entry_url.as_str()), escape(&name)))
+        })
+        .collect::<String>();
+    let title = escape(url.path());
+
+    let mut response = FetchResponse::new(StatusCode::OK, url);
+    response.headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+    response.body = format!(
+        "<!doctype html><html><head><title>{}</title></head><body><ul>{}</ul></body></html>", title, links)
+        .into_bytes();
+    Ok(response)
+}
+
+fn get_content_type(path: &Path) -> &'static str {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "html" | "htm" => "text/html",
+        "xhtml" => "application/xhtml+xml",
+        "xml" => "application/xml",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+// Fetches URLs with the fetcher of their scheme.
+pub struct SchemeFetcher {
+    fetchers: HashMap<String, DynFetcher>,
+}
+
+impl SchemeFetcher {
+    pub fn new(fetchers: HashMap<String, DynFetcher>) -> Self {
+        Self { fetchers }
+    }
+
+    // HTTP(S) URLs are fetched from the network. File URLs are fetched from the local file system only if the file
+    // scheme is allowed, otherwise crawled sites could read local files with their sitemap URLs.
+    pub fn with_default_fetchers(timeout: Duration, max_redirects: usize, allowed_schemes: &[String]) -> Self {
+        let http_fetcher: DynFetcher = Arc::new(HttpFetcher::new(timeout, max_redirects));
+        let mut fetchers = HashMap::from([
+            ("http".to_string(), http_fetcher.clone()),
+            ("https".to_string(), http_fetcher),
+        ]);
+        if allowed_schemes.iter().any(|s| s.eq_ignore_ascii_case("file")) {
+            fetchers.insert("file".to_string(), Arc::new(FileFetcher {}));
+        }
+
+        Self::new(fetchers)
+    }
+}
+
+#[tonic::async_trait]
+impl Fetcher for SchemeFetcher {
+    async fn fetch(&self, url: &Url, headers: HeaderMap, max_size: u64) -> Result<FetchResponse, FetchError> {
+        match self.fetchers.get(url.scheme()) {
+            Some(fetcher) => fetcher.fetch(url, headers, max_size).await,
+            None => Err(FetchError::Permanent(format!("Unsupported URL scheme {}", url.scheme()))),
+        }
+    }
+}
+
+// Serves responses added in advance and Not Found for other URLs, so that crawling can be run without network.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FixtureFetcher {
+    responses: Mutex<HashMap<Url, Result<FetchResponse, FetchError>>>,
+    requested_urls: Mutex<Vec<Url>>,
+    // Delay of every response which simulates a network round trip.
+    latency: Duration,
+}
+
+#[cfg(test)]
+impl FixtureFetcher {
+    pub fn with_latency(latency: Duration) -> Self {
+        Self { latency, ..Default::default() }
+    }
+
+    pub fn add_response(&self, url: Url, response: Result<FetchResponse, FetchError>) {
+        self.responses.lock().unwrap().insert(url, response);
+    }
+
+    pub fn add_page(&self, url: Url, content_type: &'static str, body: &str) {
+        let mut response = FetchResponse::new(StatusCode::OK, url.clone());
+        response.headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        response.body = body.as_bytes().to_vec();
+        self.add_response(url, Ok(response));
+    }
+
+    pub fn requested_urls(&self) -> Vec<Url> {
+        self.requested_urls.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+#[tonic::async_trait]
+impl Fetcher for FixtureFetcher {
+    async fn fetch(&self, url: &Url, _headers: HeaderMap, max_size: u64) -> Result<FetchResponse, FetchError> {
+        self.requested_urls.lock().unwrap().push(url.clone());
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+
+        match self.responses.lock().unwrap().get(url).cloned() {
+            Some(Ok(response)) if response.body.len() as u64 > max_size => Err(too_large_error(url, max_size)),
+            Some(result) => result,
+            None => Ok(FetchResponse::new(StatusCode::NOT_FOUND, url.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod file_fetcher_tests {
+    use super::*;
+
+    const MAX_SIZE: u64 = 1024;
+
+    fn create_directory(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("wexplorer_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("docs")).unwrap();
+        fs::write(path.join("docs").join("index.html"), "<p>Docs</p>").unwrap();
+        fs::write(path.join("a & b.txt"), "Text").unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn should_fetch_files_and_directories() {
+        // Arrange
+
+        let path = create_directory("fetch");
+        let url = Url::from_directory_path(&path).unwrap();
+        let target = FileFetcher {};
+
+        // Act
+
+        let result1 = target.fetch(&url, HeaderMap::new(), MAX_SIZE).await.unwrap();
+        let result2 = target.fetch(&url.join("docs").unwrap(), HeaderMap::new(), MAX_SIZE).await.unwrap();
+        let result3 = target.fetch(&url.join("missing.html").unwrap(), HeaderMap::new(), MAX_SIZE).await.unwrap();
+        let result4 = target.fetch(&url.join("a%20&%20b.txt").unwrap(), HeaderMap::new(), 3).await;
+
+        // Assert
+
+        let listing = result1.text();
+        assert_eq!(StatusCode::OK, result1.status);
+        assert!(listing.contains(&format!("<a href=\"{}a%20&amp;%20b.txt\">a &amp; b.txt</a>", url)), "{}", listing);
+        assert!(listing.contains(&format!("<a href=\"{}docs\">docs</a>", url)), "{}", listing);
+        assert_eq!(StatusCode::OK, result2.status);
+        assert_eq!(url.join("docs/").unwrap(), result2.url);
+        assert_eq!("<p>Docs</p>", result2.text());
+        assert_eq!(Some(&HeaderValue::from_static("text/html")), result2.headers.get(CONTENT_TYPE));
+        assert_eq!(StatusCode::NOT_FOUND, result3.status);
+        assert!(matches!(result4, Err(FetchError::Permanent(_))));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_return_not_modified_for_unchanged_file() {
+        // Arrange
+
+        let path = create_directory("not_modified");
+        let url = Url::from_file_path(path.join("a & b.txt")).unwrap();
+        let target = FileFetcher {};
+        let response = target.fetch(&url, HeaderMap::new(), MAX_SIZE).await.unwrap();
+        let last_modified = response.headers[LAST_MODIFIED].clone();
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_MODIFIED_SINCE, last_modified);
+
+        // Act
+
+        let result = target.fetch(&url, headers, MAX_SIZE).await.unwrap();
+
+        // Assert
+
+        assert_eq!(StatusCode::NOT_MODIFIED, result.status);
+        assert!(result.body.is_empty());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -1,39 +1,198 @@
-use std::sync::{Arc, Mutex};
+use std::{collections::{BTreeMap, HashMap}, sync::{Arc, Mutex}};
 
 use chrono::{Utc, DateTime};
-use rusqlite::{Connection, params, OptionalExtension, ToSql};
-use sea_query::{Table, Iden, ColumnDef, Query, Value, SimpleExpr, Expr};
+use rusqlite::{params, OptionalExtension, ToSql};
+use sea_query::{Table, Iden, ColumnDef, Query, Value, SimpleExpr, Expr, Index, Order, Cond};
 use url::Url;
 
-use super::{SqliteSchemaStatementBuilder, SqliteQueryStatementWriter};
+use crate::queue::{IndexingPage, PageStatus};
+
+use super::{
+    SqliteSchemaStatementBuilder, SqliteQueryStatementWriter, ContentFingerprint, ContentDuplicate, DuplicatePage,
+    SqlitePool, SIMHASH_BANDS_COUNT};
 
 #[derive(Iden)]
-enum IndexedLinks {
+pub enum IndexedLinks {
     Table,
     Url,
+    Origin,
+    SiteId,
+    Depth,
     LastIndexedTimestamp,
+    ETag,
+    LastModified,
+    DocumentHash,
+    AnchorTextsHash,
+    ContentHash,
+    SimHash,
+    SimHashBand0,
+    SimHashBand1,
+    SimHashBand2,
+    SimHashBand3,
+    DuplicateOf,
+    DuplicateDistance,
+}
+
+pub const SIMHASH_BAND_COLUMNS: [IndexedLinks; SIMHASH_BANDS_COUNT] = [
+    IndexedLinks::SimHashBand0,
+    IndexedLinks::SimHashBand1,
+    IndexedLinks::SimHashBand2,
+    IndexedLinks::SimHashBand3,
+];
+
+#[derive(Debug, PartialEq)]
+pub struct IndexedLink {
+    pub url: Url,
+    pub site_id: i64,
+    pub depth: u32,
+}
+
+#[derive(Clone, Default)]
+pub struct CacheValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+pub type DynIndexedLinksStorage = Arc<dyn IndexedLinksStorage>;
+
+// Visited links of the crawler with their cache validators and content fingerprints.
+#[tonic::async_trait]
+pub trait IndexedLinksStorage: Send + Sync {
+    async fn get_last_indexed_time(&self, url: &Url) -> Result<Option<DateTime<Utc>>, rusqlite::Error>;
+
+    // Looks up all links of a page at once, links which weren't indexed are missing in the result.
+    async fn get_last_indexed_times(&self, urls: &[Url]) -> Result<HashMap<Url, DateTime<Utc>>, rusqlite::Error>;
+
+    async fn get_cache_validators(&self, url: &Url) -> Result<Option<CacheValidators>, rusqlite::Error>;
+
+    // Hash of the document which was sent to the searching service, if any.
+    async fn get_document_hash(&self, url: &Url) -> Result<Option<u64>, rusqlite::Error>;
+
+    // Hash of the inbound anchor texts of the page when it was indexed.
+    async fn get_anchor_texts_hash(&self, url: &Url) -> Result<Option<u64>, rusqlite::Error>;
+
+    #[allow(clippy::too_many_arguments)]
+    async fn add(
+        &self, url: &Url, site_id: i64, depth: u32, indexed_time: DateTime<Utc>, cache_validators: &CacheValidators,
+        document_hash: Option<u64>, anchor_texts_hash: Option<u64>)
+        -> Result<(), rusqlite::Error>;
+
+    async fn update_last_indexed_time(&self, url: &Url, indexed_time: DateTime<Utc>) -> Result<(), rusqlite::Error>;
+
+    // Should be called after the link is added, its content isn't known until then.
+    async fn set_content(&self, url: &Url, fingerprint: &ContentFingerprint, duplicate: Option<&ContentDuplicate>)
+        -> Result<(), rusqlite::Error>;
+
+    // Finds the closest page of the web site which has the same or a similar content.
+    async fn find_duplicate_original(
+        &self, site_id: i64, url: &Url, fingerprint: &ContentFingerprint, max_distance: u32)
+        -> Result<Option<ContentDuplicate>, rusqlite::Error>;
+
+    async fn get_duplicates(&self, origin: Option<&str>, after_url: Option<&Url>, limit: u32)
+        -> Result<Vec<DuplicatePage>, rusqlite::Error>;
+
+    async fn get_site_links_indexed_before(&self, origin: &str, time: DateTime<Utc>)
+        -> Result<Vec<IndexedLink>, rusqlite::Error>;
+
+    async fn get_other_sites_links_indexed_before(&self, excluded_origins: Vec<String>, time: DateTime<Utc>)
+        -> Result<Vec<IndexedLink>, rusqlite::Error>;
 }
 
-pub struct Storage {
-    connection: Arc<Mutex<Connection>>,
+pub struct SqliteIndexedLinksStorage {
+    pool: Arc<SqlitePool>,
     add_sql: String,
     get_last_indexed_timestamp_sql: String,
+    get_cache_validators_sql: String,
+    get_document_hash_sql: String,
+    get_anchor_texts_hash_sql: String,
+    update_last_indexed_timestamp_sql: String,
+    set_content_sql: String,
+    get_exact_duplicate_original_sql: String,
+    get_similar_simhashes_sql: String,
 }
 
-impl Storage {
-    pub fn new(connection: Arc<Mutex<Connection>>) -> Result<Self, rusqlite::Error> {
+impl SqliteIndexedLinksStorage {
+    pub fn new(pool: Arc<SqlitePool>) -> Result<Self, rusqlite::Error> {
         let create_table_sql = Table::create()
             .table(IndexedLinks::Table)
             .if_not_exists()
             .col(ColumnDef::new(IndexedLinks::Url).text().not_null().primary_key())
+            .col(ColumnDef::new(IndexedLinks::Origin).text().not_null())
+            .col(ColumnDef::new(IndexedLinks::SiteId).integer().not_null())
+            .col(ColumnDef::new(IndexedLinks::Depth).integer().not_null())
             .col(ColumnDef::new(IndexedLinks::LastIndexedTimestamp).integer().not_null())
+            .col(ColumnDef::new(IndexedLinks::ETag).text().null())
+            .col(ColumnDef::new(IndexedLinks::LastModified).text().null())
+            .col(ColumnDef::new(IndexedLinks::DocumentHash).integer().null())
+            .col(ColumnDef::new(IndexedLinks::AnchorTextsHash).integer().null())
+            .col(ColumnDef::new(IndexedLinks::ContentHash).integer().null())
+            .col(ColumnDef::new(IndexedLinks::SimHash).integer().null())
+            .col(ColumnDef::new(IndexedLinks::SimHashBand0).integer().null())
+            .col(ColumnDef::new(IndexedLinks::SimHashBand1).integer().null())
+            .col(ColumnDef::new(IndexedLinks::SimHashBand2).integer().null())
+            .col(ColumnDef::new(IndexedLinks::SimHashBand3).integer().null())
+            .col(ColumnDef::new(IndexedLinks::DuplicateOf).text().null())
+            .col(ColumnDef::new(IndexedLinks::DuplicateDistance).integer().null())
+            .to_sqlite_string();
+        let create_index_sql = Index::create()
+            .name("IX_IndexedLinks_Origin_LastIndexedTimestamp")
+            .table(IndexedLinks::Table)
+            .if_not_exists()
+            .col(IndexedLinks::Origin)
+            .col(IndexedLinks::LastIndexedTimestamp)
             .to_sqlite_string();
-        connection.lock().unwrap().execute(&create_table_sql, ())?;
+        let create_content_hash_index_sql = Index::create()
+            .name("IX_IndexedLinks_SiteId_ContentHash")
+            .table(IndexedLinks::Table)
+            .if_not_exists()
+            .col(IndexedLinks::SiteId)
+            .col(IndexedLinks::ContentHash)
+            .to_sqlite_string();
+        let create_simhash_band_indexes_sql = SIMHASH_BAND_COLUMNS.into_iter()
+            .enumerate()
+            .map(|(i, band_column)| Index::create()
+                .name(format!("IX_IndexedLinks_SiteId_SimHashBand{}", i))
+                .table(IndexedLinks::Table)
+                .if_not_exists()
+                .col(IndexedLinks::SiteId)
+                .col(band_column)
+                .to_sqlite_string())
+            .collect::<Vec<_>>();
+        pool.run_blocking(|connection| {
+            connection.execute(&create_table_sql, ())?;
+            connection.execute(&create_index_sql, ())?;
+            connection.execute(&create_content_hash_index_sql, ())?;
+            for create_simhash_band_index_sql in &create_simhash_band_indexes_sql {
+                connection.execute(create_simhash_band_index_sql, ())?;
+            }
+
+            Ok(())
+        })?;
 
         let add_sql = Query::insert()
             .into_table(IndexedLinks::Table)
-            .columns([IndexedLinks::Url, IndexedLinks::LastIndexedTimestamp])
-            .values_panic([SimpleExpr::Custom("?1".to_string()), SimpleExpr::Custom("?2".to_string())])
+            .columns([
+                IndexedLinks::Url,
+                IndexedLinks::Origin,
+                IndexedLinks::SiteId,
+                IndexedLinks::Depth,
+                IndexedLinks::LastIndexedTimestamp,
+                IndexedLinks::ETag,
+                IndexedLinks::LastModified,
+                IndexedLinks::DocumentHash,
+                IndexedLinks::AnchorTextsHash,
+            ])
+            .values_panic([
+                SimpleExpr::Custom("?1".to_string()),
+                SimpleExpr::Custom("?2".to_string()),
+                SimpleExpr::Custom("?3".to_string()),
+                SimpleExpr::Custom("?4".to_string()),
+                SimpleExpr::Custom("?5".to_string()),
+                SimpleExpr::Custom("?6".to_string()),
+                SimpleExpr::Custom("?7".to_string()),
+                SimpleExpr::Custom("?8".to_string()),
+                SimpleExpr::Custom("?9".to_string()),
+            ])
             .to_sqlite_string()
             .replace("INSERT", "REPLACE");
 
@@ -43,21 +202,533 @@ impl Storage {
             .and_where(Expr::col(IndexedLinks::Url).eq(SimpleExpr::Custom("?1".to_string())))
             .to_sqlite_string();
 
+        let get_cache_validators_sql = Query::select()
+            .columns([IndexedLinks::ETag, IndexedLinks::LastModified])
+            .from(IndexedLinks::Table)
+            .and_where(Expr::col(IndexedLinks::Url).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        let get_document_hash_sql = Query::select()
+            .column(IndexedLinks::DocumentHash)
+            .from(IndexedLinks::Table)
+            .and_where(Expr::col(IndexedLinks::Url).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        let get_anchor_texts_hash_sql = Query::select()
+            .column(IndexedLinks::AnchorTextsHash)
+            .from(IndexedLinks::Table)
+            .and_where(Expr::col(IndexedLinks::Url).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        let update_last_indexed_timestamp_sql = Query::update()
+            .table(IndexedLinks::Table)
+            .value(IndexedLinks::LastIndexedTimestamp, SimpleExpr::Custom("?2".to_string()))
+            .and_where(Expr::col(IndexedLinks::Url).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        let set_content_sql = Query::update()
+            .table(IndexedLinks::Table)
+            .values([
+                (IndexedLinks::ContentHash, SimpleExpr::Custom("?2".to_string())),
+                (IndexedLinks::SimHash, SimpleExpr::Custom("?3".to_string())),
+                (IndexedLinks::DuplicateOf, SimpleExpr::Custom("?4".to_string())),
+                (IndexedLinks::DuplicateDistance, SimpleExpr::Custom("?5".to_string())),
+                (IndexedLinks::SimHashBand0, SimpleExpr::Custom("?6".to_string())),
+                (IndexedLinks::SimHashBand1, SimpleExpr::Custom("?7".to_string())),
+                (IndexedLinks::SimHashBand2, SimpleExpr::Custom("?8".to_string())),
+                (IndexedLinks::SimHashBand3, SimpleExpr::Custom("?9".to_string())),
+            ])
+            .and_where(Expr::col(IndexedLinks::Url).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        // Pages are compared only with the originals, so duplicates don't make chains.
+        let get_exact_duplicate_original_sql = Query::select()
+            .column(IndexedLinks::Url)
+            .from(IndexedLinks::Table)
+            .and_where(Expr::col(IndexedLinks::SiteId).eq(SimpleExpr::Custom("?1".to_string())))
+            .and_where(Expr::col(IndexedLinks::ContentHash).eq(SimpleExpr::Custom("?2".to_string())))
+            .and_where(Expr::col(IndexedLinks::Url).ne(SimpleExpr::Custom("?3".to_string())))
+            .and_where(Expr::col(IndexedLinks::DuplicateOf).is_null())
+            .limit(1)
+            .to_sqlite_string();
+
+        // Only pages with an equal SimHash band are compared, each band is looked up by its index.
+        let get_similar_simhashes_sql = Query::select()
+            .columns([IndexedLinks::Url, IndexedLinks::ContentHash, IndexedLinks::SimHash])
+            .from(IndexedLinks::Table)
+            .and_where(Expr::col(IndexedLinks::SiteId).eq(SimpleExpr::Custom("?1".to_string())))
+            .and_where(Expr::col(IndexedLinks::Url).ne(SimpleExpr::Custom("?2".to_string())))
+            .and_where(Expr::col(IndexedLinks::SimHash).is_not_null())
+            .and_where(Expr::col(IndexedLinks::DuplicateOf).is_null())
+            .cond_where(Cond::any()
+                .add(Expr::col(IndexedLinks::SimHashBand0).eq(SimpleExpr::Custom("?3".to_string())))
+                .add(Expr::col(IndexedLinks::SimHashBand1).eq(SimpleExpr::Custom("?4".to_string())))
+                .add(Expr::col(IndexedLinks::SimHashBand2).eq(SimpleExpr::Custom("?5".to_string())))
+                .add(Expr::col(IndexedLinks::SimHashBand3).eq(SimpleExpr::Custom("?6".to_string()))))
+            .to_sqlite_string();
+
         Ok(Self {
-            connection,
+            pool,
             add_sql,
             get_last_indexed_timestamp_sql,
+            get_cache_validators_sql,
+            get_document_hash_sql,
+            get_anchor_texts_hash_sql,
+            update_last_indexed_timestamp_sql,
+            set_content_sql,
+            get_exact_duplicate_original_sql,
+            get_similar_simhashes_sql,
         })
     }
 
-    pub fn get_last_indexed_time(&self, url: &Url) -> Result<Option<DateTime<Utc>>, rusqlite::Error> {
-        self.connection.lock().unwrap()
-            .query_row(&self.get_last_indexed_timestamp_sql, [url], |row| row.get(0))
-            .optional()
+    // The statement is built before the query runs on the pool, sea-query expressions can't be sent to its threads.
+    async fn get_links_indexed_before(&self, time: DateTime<Utc>, sql: String)
+        -> Result<Vec<IndexedLink>, rusqlite::Error>
+    {
+        self.pool
+            .run(move |connection| {
+                let mut statement = connection.prepare(&sql)?;
+                let links = statement
+                    .query_map([time], |row| Ok(IndexedLink {
+                        url: row.get(0)?,
+                        site_id: row.get(1)?,
+                        depth: row.get(2)?,
+                    }))?
+                    .collect();
+                links
+            })
+            .await
+    }
+}
+
+#[tonic::async_trait]
+impl IndexedLinksStorage for SqliteIndexedLinksStorage {
+    async fn get_last_indexed_time(&self, url: &Url) -> Result<Option<DateTime<Utc>>, rusqlite::Error> {
+        let (sql, url) = (self.get_last_indexed_timestamp_sql.clone(), url.clone());
+        self.pool.run(move |connection| connection.query_row(&sql, [&url], |row| row.get(0)).optional()).await
+    }
+
+    async fn get_last_indexed_times(&self, urls: &[Url]) -> Result<HashMap<Url, DateTime<Utc>>, rusqlite::Error> {
+        let (sql, urls) = (self.get_last_indexed_timestamp_sql.clone(), urls.to_vec());
+        self.pool
+            .run(move |connection| {
+                let mut statement = connection.prepare_cached(&sql)?;
+                let mut last_indexed_times = HashMap::new();
+                for url in &urls {
+                    if let Some(last_indexed_time) = statement.query_row([url], |row| row.get(0)).optional()? {
+                        last_indexed_times.insert(url.clone(), last_indexed_time);
+                    }
+                }
+
+                Ok(last_indexed_times)
+            })
+            .await
+    }
+
+    async fn get_cache_validators(&self, url: &Url) -> Result<Option<CacheValidators>, rusqlite::Error> {
+        let (sql, url) = (self.get_cache_validators_sql.clone(), url.clone());
+        self.pool
+            .run(move |connection| connection
+                .query_row(&sql, [&url], |row| Ok(CacheValidators { etag: row.get(0)?, last_modified: row.get(1)? }))
+                .optional())
+            .await
+    }
+
+    async fn get_document_hash(&self, url: &Url) -> Result<Option<u64>, rusqlite::Error> {
+        let (sql, url) = (self.get_document_hash_sql.clone(), url.clone());
+        let document_hash = self.pool
+            .run(move |connection| connection.query_row(&sql, [&url], |row| row.get::<_, Option<i64>>(0)).optional())
+            .await?;
+        Ok(document_hash.flatten().map(|h| h as u64))
+    }
+
+    async fn get_anchor_texts_hash(&self, url: &Url) -> Result<Option<u64>, rusqlite::Error> {
+        let (sql, url) = (self.get_anchor_texts_hash_sql.clone(), url.clone());
+        let anchor_texts_hash = self.pool
+            .run(move |connection| connection.query_row(&sql, [&url], |row| row.get::<_, Option<i64>>(0)).optional())
+            .await?;
+        Ok(anchor_texts_hash.flatten().map(|h| h as u64))
+    }
+
+    async fn add(
+        &self, url: &Url, site_id: i64, depth: u32, indexed_time: DateTime<Utc>, cache_validators: &CacheValidators,
+        document_hash: Option<u64>, anchor_texts_hash: Option<u64>)
+        -> Result<(), rusqlite::Error>
+    {
+        let (sql, url, cache_validators) = (self.add_sql.clone(), url.clone(), cache_validators.clone());
+        self.pool
+            .run(move |connection| connection.execute(&sql, params![
+                url,
+                url.origin().ascii_serialization(),
+                site_id,
+                depth,
+                indexed_time,
+                cache_validators.etag,
+                cache_validators.last_modified,
+                document_hash.map(|h| h as i64),
+                anchor_texts_hash.map(|h| h as i64),
+            ]))
+            .await?;
+        Ok(())
+    }
+
+    async fn update_last_indexed_time(&self, url: &Url, indexed_time: DateTime<Utc>) -> Result<(), rusqlite::Error> {
+        let (sql, url) = (self.update_last_indexed_timestamp_sql.clone(), url.clone());
+        self.pool.run(move |connection| connection.execute(&sql, params![url, indexed_time])).await?;
+        Ok(())
+    }
+
+    async fn set_content(&self, url: &Url, fingerprint: &ContentFingerprint, duplicate: Option<&ContentDuplicate>)
+        -> Result<(), rusqlite::Error>
+    {
+        let (sql, url, fingerprint) = (self.set_content_sql.clone(), url.clone(), *fingerprint);
+        let (duplicate, bands) = (duplicate.cloned(), fingerprint.simhash_bands());
+        self.pool
+            .run(move |connection| connection.execute(&sql, params![
+                url,
+                fingerprint.hash as i64,
+                fingerprint.simhash as i64,
+                duplicate.as_ref().map(|d| &d.original_url),
+                duplicate.as_ref().map(|d| d.distance),
+                bands[0],
+                bands[1],
+                bands[2],
+                bands[3],
+            ]))
+            .await?;
+        Ok(())
+    }
+
+    async fn find_duplicate_original(
+        &self, site_id: i64, url: &Url, fingerprint: &ContentFingerprint, max_distance: u32)
+        -> Result<Option<ContentDuplicate>, rusqlite::Error>
+    {
+        let exact_original_sql = self.get_exact_duplicate_original_sql.clone();
+        let similar_simhashes_sql = self.get_similar_simhashes_sql.clone();
+        let (url, fingerprint) = (url.clone(), *fingerprint);
+        self.pool
+            .run(move |connection| {
+                let exact_original = connection
+                    .query_row(&exact_original_sql, params![site_id, fingerprint.hash as i64, url], |row| row.get(0))
+                    .optional()?;
+                if let Some(original_url) = exact_original {
+                    return Ok(Some(ContentDuplicate { original_url, distance: 0 }));
+                }
+
+                if max_distance == 0 {
+                    return Ok(None);
+                }
+
+                let bands = fingerprint.simhash_bands();
+                let mut statement = connection.prepare(&similar_simhashes_sql)?;
+                let mut rows = statement.query(params![site_id, url, bands[0], bands[1], bands[2], bands[3]])?;
+                let mut closest_original = None;
+                while let Some(row) = rows.next()? {
+                    let other_fingerprint = ContentFingerprint {
+                        hash: row.get::<_, i64>(1)? as u64,
+                        simhash: row.get::<_, i64>(2)? as u64,
+                    };
+                    let distance = fingerprint.distance(&other_fingerprint);
+                    let is_closer = closest_original.as_ref().is_none_or(|d: &ContentDuplicate| distance < d.distance);
+                    if distance <= max_distance && is_closer {
+                        closest_original = Some(ContentDuplicate { original_url: row.get(0)?, distance });
+                    }
+                }
+
+                Ok(closest_original)
+            })
+            .await
+    }
+
+    async fn get_duplicates(&self, origin: Option<&str>, after_url: Option<&Url>, limit: u32)
+        -> Result<Vec<DuplicatePage>, rusqlite::Error>
+    {
+        let sql = {
+            let mut query = Query::select();
+            query
+                .columns([IndexedLinks::Url, IndexedLinks::DuplicateOf, IndexedLinks::DuplicateDistance])
+                .from(IndexedLinks::Table)
+                .and_where(Expr::col(IndexedLinks::DuplicateOf).is_not_null());
+
+            if let Some(origin) = origin {
+                query.and_where(Expr::col(IndexedLinks::Origin).eq(origin));
+            }
+
+            if let Some(after_url) = after_url {
+                query.and_where(Expr::col(IndexedLinks::Url).gt(after_url.as_str()));
+            }
+
+            query
+                .order_by(IndexedLinks::Url, Order::Asc)
+                .limit(limit as u64)
+                .to_sqlite_string()
+        };
+
+        self.pool
+            .run(move |connection| {
+                let mut statement = connection.prepare(&sql)?;
+                let duplicates = statement
+                    .query_map((), |row| Ok(DuplicatePage {
+                        url: row.get(0)?,
+                        original_url: row.get(1)?,
+                        distance: row.get(2)?,
+                    }))?
+                    .collect();
+                duplicates
+            })
+            .await
+    }
+
+    async fn get_site_links_indexed_before(&self, origin: &str, time: DateTime<Utc>)
+        -> Result<Vec<IndexedLink>, rusqlite::Error>
+    {
+        let sql = create_links_indexed_before_sql(Expr::col(IndexedLinks::Origin).eq(origin));
+        self.get_links_indexed_before(time, sql).await
+    }
+
+    async fn get_other_sites_links_indexed_before(&self, excluded_origins: Vec<String>, time: DateTime<Utc>)
+        -> Result<Vec<IndexedLink>, rusqlite::Error>
+    {
+        let sql = create_links_indexed_before_sql(Expr::col(IndexedLinks::Origin).is_not_in(excluded_origins));
+        self.get_links_indexed_before(time, sql).await
+    }
+}
+
+fn create_links_indexed_before_sql(origin_condition: SimpleExpr) -> String {
+    Query::select()
+        .columns([IndexedLinks::Url, IndexedLinks::SiteId, IndexedLinks::Depth])
+        .from(IndexedLinks::Table)
+        .and_where(origin_condition)
+        .and_where(Expr::col(IndexedLinks::LastIndexedTimestamp).lt(SimpleExpr::Custom("?1".to_string())))
+        .to_sqlite_string()
+}
+
+struct InMemoryIndexedLink {
+    site_id: i64,
+    depth: u32,
+    indexed_time: DateTime<Utc>,
+    cache_validators: CacheValidators,
+    document_hash: Option<u64>,
+    anchor_texts_hash: Option<u64>,
+    fingerprint: Option<ContentFingerprint>,
+    duplicate: Option<ContentDuplicate>,
+}
+
+// Keeps indexed links in memory, so that indexing can be run without a database.
+#[allow(dead_code)]
+#[derive(Default)]
+pub struct InMemoryIndexedLinksStorage {
+    // Links are ordered by URL like rows of the SQLite storage.
+    links: Mutex<BTreeMap<Url, InMemoryIndexedLink>>,
+}
+
+#[allow(dead_code)]
+impl InMemoryIndexedLinksStorage {
+    pub fn get_indexed_pages(&self) -> Vec<IndexingPage> {
+        self.links.lock().unwrap().iter()
+            .map(|(url, link)| IndexingPage {
+                url: url.clone(),
+                site_id: link.site_id,
+                status: PageStatus::Indexed,
+                depth: link.depth,
+                attempts: 0,
+                last_error: None,
+                last_indexed_time: Some(link.indexed_time),
+            })
+            .collect()
+    }
+
+    fn get_links_indexed_before(&self, time: DateTime<Utc>, is_origin_included: impl Fn(&str) -> bool)
+        -> Vec<IndexedLink>
+    {
+        self.links.lock().unwrap().iter()
+            .filter(|(url, link)| link.indexed_time < time && is_origin_included(&url.origin().ascii_serialization()))
+            .map(|(url, link)| IndexedLink { url: url.clone(), site_id: link.site_id, depth: link.depth })
+            .collect()
+    }
+}
+
+#[tonic::async_trait]
+impl IndexedLinksStorage for InMemoryIndexedLinksStorage {
+    async fn get_last_indexed_time(&self, url: &Url) -> Result<Option<DateTime<Utc>>, rusqlite::Error> {
+        Ok(self.links.lock().unwrap().get(url).map(|l| l.indexed_time))
+    }
+
+    async fn get_last_indexed_times(&self, urls: &[Url]) -> Result<HashMap<Url, DateTime<Utc>>, rusqlite::Error> {
+        let links = self.links.lock().unwrap();
+        Ok(urls.iter().filter_map(|u| Some((u.clone(), links.get(u)?.indexed_time))).collect())
+    }
+
+    async fn get_cache_validators(&self, url: &Url) -> Result<Option<CacheValidators>, rusqlite::Error> {
+        Ok(self.links.lock().unwrap().get(url).map(|l| l.cache_validators.clone()))
+    }
+
+    async fn get_document_hash(&self, url: &Url) -> Result<Option<u64>, rusqlite::Error> {
+        Ok(self.links.lock().unwrap().get(url).and_then(|l| l.document_hash))
+    }
+
+    async fn get_anchor_texts_hash(&self, url: &Url) -> Result<Option<u64>, rusqlite::Error> {
+        Ok(self.links.lock().unwrap().get(url).and_then(|l| l.anchor_texts_hash))
+    }
+
+    // Replaces the link like the SQLite storage does, so its content has to be set again.
+    async fn add(
+        &self, url: &Url, site_id: i64, depth: u32, indexed_time: DateTime<Utc>, cache_validators: &CacheValidators,
+        document_hash: Option<u64>, anchor_texts_hash: Option<u64>)
+        -> Result<(), rusqlite::Error>
+    {
+        self.links.lock().unwrap().insert(url.clone(), InMemoryIndexedLink {
+            site_id,
+            depth,
+            indexed_time,
+            cache_validators: cache_validators.clone(),
+            document_hash,
+            anchor_texts_hash,
+            fingerprint: None,
+            duplicate: None,
+        });
+        Ok(())
+    }
+
+    async fn update_last_indexed_time(&self, url: &Url, indexed_time: DateTime<Utc>) -> Result<(), rusqlite::Error> {
+        if let Some(link) = self.links.lock().unwrap().get_mut(url) {
+            link.indexed_time = indexed_time;
+        }
+
+        Ok(())
     }
 
-    pub fn add(&self, url: &Url, indexed_time: DateTime<Utc>) -> Result<(), rusqlite::Error> {
-        self.connection.lock().unwrap().execute(&self.add_sql, params![url, indexed_time])?;
+    async fn set_content(&self, url: &Url, fingerprint: &ContentFingerprint, duplicate: Option<&ContentDuplicate>)
+        -> Result<(), rusqlite::Error>
+    {
+        if let Some(link) = self.links.lock().unwrap().get_mut(url) {
+            link.fingerprint = Some(*fingerprint);
+            link.duplicate = duplicate.cloned();
+        }
+
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn find_duplicate_original(
+        &self, site_id: i64, url: &Url, fingerprint: &ContentFingerprint, max_distance: u32)
+        -> Result<Option<ContentDuplicate>, rusqlite::Error>
+    {
+        let links = self.links.lock().unwrap();
+        let originals = links.iter()
+            .filter(|(other_url, link)| link.site_id == site_id && *other_url != url && link.duplicate.is_none())
+            .filter_map(|(other_url, link)| Some((other_url, link.fingerprint?)));
+
+        let closest_original = originals
+            .filter_map(|(other_url, other_fingerprint)| {
+                // Exact duplicates are found by the content hash, even if simhashes differ.
+                let distance = if other_fingerprint.hash == fingerprint.hash {
+                    0
+                }
+                else {
+                    let distance = fingerprint.distance(&other_fingerprint);
+                    // Near-duplicates are compared only if they share a SimHash band like in the SQLite storage.
+                    let is_compared = fingerprint.has_common_simhash_band(&other_fingerprint);
+                    if max_distance == 0 || distance > max_distance || !is_compared {
+                        return None;
+                    }
+
+                    distance
+                };
+                Some(ContentDuplicate { original_url: other_url.clone(), distance })
+            })
+            .min_by_key(|d| d.distance);
+
+        Ok(closest_original)
+    }
+
+    async fn get_duplicates(&self, origin: Option<&str>, after_url: Option<&Url>, limit: u32)
+        -> Result<Vec<DuplicatePage>, rusqlite::Error>
+    {
+        let duplicates = self.links.lock().unwrap().iter()
+            .filter(|(url, _)| origin.is_none_or(|o| url.origin().ascii_serialization() == o))
+            .filter(|(url, _)| after_url.is_none_or(|a| url.as_str() > a.as_str()))
+            .filter_map(|(url, link)| {
+                let duplicate = link.duplicate.as_ref()?;
+                Some(DuplicatePage {
+                    url: url.clone(),
+                    original_url: duplicate.original_url.clone(),
+                    distance: duplicate.distance,
+                })
+            })
+            .take(limit as usize)
+            .collect();
+        Ok(duplicates)
+    }
+
+    async fn get_site_links_indexed_before(&self, origin: &str, time: DateTime<Utc>)
+        -> Result<Vec<IndexedLink>, rusqlite::Error>
+    {
+        Ok(self.get_links_indexed_before(time, |o| o == origin))
+    }
+
+    async fn get_other_sites_links_indexed_before(&self, excluded_origins: Vec<String>, time: DateTime<Utc>)
+        -> Result<Vec<IndexedLink>, rusqlite::Error>
+    {
+        Ok(self.get_links_indexed_before(time, |o| !excluded_origins.iter().any(|e| e == o)))
+    }
+}
+
+#[cfg(test)]
+mod indexed_links_storage_tests {
+    use super::*;
+
+    async fn check_find_closest_original_of_same_site(target: DynIndexedLinksStorage) {
+        // Arrange
+
+        let add_page = |url: &str, site_id, simhash| {
+            let (target, url) = (target.clone(), Url::parse(url).unwrap());
+            async move {
+                target.add(&url, site_id, 0, Utc::now(), &CacheValidators::default(), None, None).await.unwrap();
+                target.set_content(&url, &ContentFingerprint { hash: simhash, simhash }, None).await.unwrap();
+                url
+            }
+        };
+        let original_url = add_page("https://localhost/original", 1, 0b1111_0000).await;
+        add_page("https://localhost/other", 1, 0b0000_1111).await;
+        add_page("https://example.com/same", 2, 0b1111_0001).await;
+        let url = add_page("https://localhost/print", 1, 0b1111_0001).await;
+        let duplicate = ContentDuplicate { original_url: original_url.clone(), distance: 1 };
+        target
+            .set_content(&url, &ContentFingerprint { hash: 1, simhash: 0b1111_0001 }, Some(&duplicate))
+            .await
+            .unwrap();
+
+        // Act
+
+        let result1 = target
+            .find_duplicate_original(1, &url, &ContentFingerprint { hash: 1, simhash: 0b1111_0001 }, 2)
+            .await
+            .unwrap();
+        let result2 = target
+            .find_duplicate_original(1, &url, &ContentFingerprint { hash: 2, simhash: 0b1100_0011 }, 2)
+            .await
+            .unwrap();
+        let result3 = target.get_duplicates(Some("https://localhost"), None, 10).await.unwrap();
+        let result4 = target.get_last_indexed_times(&[url.clone(), Url::parse("https://localhost/new").unwrap()])
+            .await
+            .unwrap();
+
+        // Assert
+
+        assert_eq!(Some(duplicate), result1);
+        assert_eq!(None, result2);
+        assert_eq!(vec![DuplicatePage { url: url.clone(), original_url, distance: 1 }], result3);
+        assert_eq!(vec![url], result4.into_keys().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn should_find_closest_original_of_same_site_with_sqlite() {
+        let pool = Arc::new(SqlitePool::open_in_memory().unwrap());
+        check_find_closest_original_of_same_site(Arc::new(SqliteIndexedLinksStorage::new(pool).unwrap())).await;
+    }
+
+    #[tokio::test]
+    async fn should_find_closest_original_of_same_site_in_memory() {
+        check_find_closest_original_of_same_site(Arc::new(InMemoryIndexedLinksStorage::default())).await;
+    }
+}
@@ -1,18 +1,27 @@
-use std::{sync::Arc, future::Future, pin::Pin, task::{Context, Poll}, time::Duration, rc::Rc};
+use std::{sync::Arc, future::Future, pin::Pin, task::{Context, Poll}, time::Duration, rc::Rc, hash::{Hash, Hasher}};
 
-use chrono::Utc;
+use chrono::{Utc, DateTime};
 use itertools::Itertools;
-use reqwest::redirect::Policy;
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, LAST_MODIFIED, IF_NONE_MATCH, IF_MODIFIED_SINCE, RETRY_AFTER, CONTENT_TYPE};
 use scraper::{Selector, Element};
-use tokio::{task::{JoinHandle, futures}, select, sync::oneshot};
+use siphasher::sip::SipHasher13;
+use tokio::{task::{JoinHandle, futures}, select, sync::{oneshot, broadcast}};
 use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
 use tracing::{info, Instrument, trace_span, info_span, span, Level, error_span, warn, debug};
 use url::Url;
-use wexplorer_searching_grpc_client::{searching_api_client::SearchingApiClient, AddPageRequest};
+use wexplorer_searching_grpc_client::{AddPageRequest, DeletePageRequest, Heading, SetPageRanksRequest, PageRank};
 
-use crate::queue::IndexingQueue;
+use crate::queue::{IndexingQueue, DynIndexingQueue, QueueItemPriority, OriginPageCounts, PageFilter, PageCursor, IndexingPage};
 
-use super::{url_processing::{UrlProcessor, UrlProcessorImpl, AllowedSchemeUrlFilter, UrlNormalizerBuilder, RemoveFragmentNormalizer}, text_extracting::TextExtractor, Storage};
+use super::{url_processing::UrlProcessor, text_extracting::{TextExtractor, PageDocument}, IndexedLinksStorage, DynIndexedLinksStorage, RobotsTxtCache, SitemapLoader, RecrawlPolicy, CacheValidators, WebSiteStorage, WebSite, CrawlScope, CrawlLimits, WebSiteStatus, IndexingEvents, IndexingEvent, IndexingEventKind, PageDirectiveSettings, PageDirectives, PageDirectivesExtractor, RobotsDirectives, X_ROBOTS_TAG, is_nofollow_link, DuplicateSettings, ContentFingerprint, ContentDuplicate, DuplicatePage, LinkGraphStorage, LinkEdge, PageRankSettings, compute_page_ranks, format_inline_text, Fetcher, DynFetcher, FetchError, SearchingService, DynSearchingService, CrawlerTrapDetector, UrlFilter};
+
+pub const CRAWLER_USER_AGENT: &str = "wexplorer";
+
+const PAGE_RANKS_BATCH_SIZE: usize = 1000;
+
+// Pages larger than this are rarely worth indexing and could exhaust the memory of the crawler.
+const MAX_PAGE_SIZE: u64 = 16 * 1024 * 1024;
 
 struct WithCancellation<'a, T> {
     inner: Pin<Box<T>>,
@@ -43,138 +52,748 @@ trait SendSyncUrlProcessor: UrlProcessor + Send + Sync {}
 impl<T: UrlProcessor + Send + Sync> SendSyncUrlProcessor for T {}
 
 pub struct Indexer<U> {
-    queue: Arc<IndexingQueue>,
-    indexed_links_storage: Arc<Storage>,
+    queue: DynIndexingQueue,
+    indexed_links_storage: DynIndexedLinksStorage,
+    web_site_storage: Arc<WebSiteStorage>,
+    link_graph_storage: Arc<LinkGraphStorage>,
+    robots_txt_cache: Arc<RobotsTxtCache>,
+    recrawl_policy: Arc<RecrawlPolicy>,
+    duplicate_settings: Arc<DuplicateSettings>,
+    page_rank_settings: Arc<PageRankSettings>,
+    events: IndexingEvents,
     processing_handles: Vec<JoinHandle<()>>,
     cancellation_token: CancellationToken,
     url_processor: U,
+    crawler_trap_detector: Arc<CrawlerTrapDetector>,
     text_extractor: TextExtractor,
+    sitemap_loader: SitemapLoader,
+    fetcher: DynFetcher,
+    searching_service: DynSearchingService,
 }
 
 impl<U> Indexer<U>
 where
-    U: UrlProcessor + Clone + Send + 'static
+    U: UrlProcessor + Clone + Send + Sync + 'static
 {
-    pub fn new(queue: IndexingQueue, indexed_links_storage: Storage, url_processor: U, text_extractor: TextExtractor) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        queue: DynIndexingQueue, indexed_links_storage: DynIndexedLinksStorage, web_site_storage: WebSiteStorage,
+        link_graph_storage: LinkGraphStorage, robots_txt_cache: RobotsTxtCache, recrawl_policy: RecrawlPolicy,
+        duplicate_settings: DuplicateSettings, page_rank_settings: PageRankSettings, url_processor: U,
+        crawler_trap_detector: Arc<CrawlerTrapDetector>, text_extractor: TextExtractor, sitemap_loader: SitemapLoader,
+        fetcher: DynFetcher, searching_service: DynSearchingService)
+        -> Self
+    {
         Self {
-            queue: Arc::new(queue),
-            indexed_links_storage: Arc::new(indexed_links_storage),
+            queue,
+            indexed_links_storage,
+            web_site_storage: Arc::new(web_site_storage),
+            link_graph_storage: Arc::new(link_graph_storage),
+            robots_txt_cache: Arc::new(robots_txt_cache),
+            recrawl_policy: Arc::new(recrawl_policy),
+            duplicate_settings: Arc::new(duplicate_settings),
+            page_rank_settings: Arc::new(page_rank_settings),
+            events: IndexingEvents::new(),
             processing_handles: Vec::new(),
             cancellation_token: CancellationToken::new(),
             url_processor,
+            crawler_trap_detector,
             text_extractor,
+            sitemap_loader,
+            fetcher,
+            searching_service,
         }
     }
 
-    pub async fn index_page(&self, url: Url) {
+    pub async fn index_web_site(
+        &self, url: Url, scope: CrawlScope, limits: CrawlLimits, directives: PageDirectiveSettings)
+    {
+        let Some(url) = self.url_processor.process_url(url) else {
+            info!("Web site URL is filtered out");
+            return;
+        };
+
+        let web_site = self.web_site_storage.add(url, scope, limits, directives).await.unwrap();
+        info!("Indexing web site {} with scope {:?} and limits {:?}", web_site.url, web_site.scope, web_site.limits);
+        self.index_page(&web_site, web_site.url.clone()).await;
+
+        let queue = self.queue.clone();
+        let indexed_links_storage = self.indexed_links_storage.clone();
+        let web_site_storage = self.web_site_storage.clone();
+        let robots_txt_cache = self.robots_txt_cache.clone();
+        let url_processor = self.url_processor.clone();
+        let crawler_trap_detector = self.crawler_trap_detector.clone();
+        let sitemap_loader = self.sitemap_loader.clone();
+        let events = self.events.clone();
+        let ct = self.cancellation_token.clone();
+
+        tokio::spawn(async move {
+            Indexer::ingest_sitemaps(
+                &web_site, &*queue, &*indexed_links_storage, &web_site_storage, &robots_txt_cache, &events,
+                &sitemap_loader, url_processor, &crawler_trap_detector)
+                .with_cancellation(&ct).await;
+        }.instrument(error_span!("sitemap_ingestion")));
+    }
+
+    pub async fn index_page(&self, web_site: &WebSite, url: Url) {
         if let Some(url) = self.url_processor.process_url(url) {
-            self.queue.enqueue(url).unwrap();
+            if !web_site.contains(&url) {
+                info!("URL {} is out of the web site scope", url);
+                return;
+            }
+
+            if !self.robots_txt_cache.is_allowed(&url).await.unwrap() {
+                info!("URL {} is disallowed by robots.txt", url);
+                return;
+            }
+
+            let priority = match self.indexed_links_storage.get_last_indexed_time(&url).await.unwrap() {
+                Some(_) => QueueItemPriority::RECRAWL,
+                None => QueueItemPriority::NEW,
+            };
+
+            let enqueue_result = enqueue_pages(
+                &*self.queue, &self.web_site_storage, &self.events, web_site.id, 0, vec![(url, priority)]).await;
+            if !enqueue_result.unwrap() {
+                info!("Page budget of web site {} is exhausted", web_site.url);
+            }
         }
     }
 
+    pub async fn get_web_sites(&self, origin: Option<&str>, after_id: Option<i64>, limit: u32) -> Vec<WebSiteStatus> {
+        self.web_site_storage.get_statuses(origin, after_id, limit).await.unwrap()
+    }
+
+    pub async fn get_origin_page_counts(&self, site_ids: Vec<i64>) -> Vec<OriginPageCounts> {
+        self.queue.get_indexing_origins(site_ids).await.unwrap()
+    }
+
+    pub async fn get_pages(&self, filter: &PageFilter, cursor: Option<&PageCursor>, limit: u32) -> Vec<IndexingPage> {
+        self.queue.get_indexing_pages(filter, cursor, limit).await.unwrap()
+    }
+
+    pub async fn get_duplicates(&self, origin: Option<&str>, after_url: Option<&Url>, limit: u32)
+        -> Vec<DuplicatePage>
+    {
+        self.indexed_links_storage.get_duplicates(origin, after_url, limit).await.unwrap()
+    }
+
+    pub fn subscribe_to_events(&self) -> broadcast::Receiver<IndexingEvent> {
+        self.events.subscribe()
+    }
+
     pub fn start_processing(&mut self, worker_count: u32) {
         self.processing_handles.clear();
 
         for i in 0..worker_count {
             let queue = self.queue.clone();
             let indexed_links_storage = self.indexed_links_storage.clone();
+            let web_site_storage = self.web_site_storage.clone();
+            let link_graph_storage = self.link_graph_storage.clone();
+            let robots_txt_cache = self.robots_txt_cache.clone();
+            let recrawl_policy = self.recrawl_policy.clone();
+            let duplicate_settings = self.duplicate_settings.clone();
             let url_processor = self.url_processor.clone();
+            let crawler_trap_detector = self.crawler_trap_detector.clone();
             let text_extractor = self.text_extractor.clone();
+            let fetcher = self.fetcher.clone();
+            let searching_service = self.searching_service.clone();
+            let events = self.events.clone();
             let ct = self.cancellation_token.clone();
 
             self.processing_handles.push(tokio::spawn(async move {
-                Indexer::process_queue(&queue, &indexed_links_storage, url_processor, text_extractor).with_cancellation(&ct).await;
+                Indexer::process_queue(
+                    &*queue, &*indexed_links_storage, &web_site_storage, &link_graph_storage, &robots_txt_cache,
+                    &recrawl_policy, &duplicate_settings, &events, url_processor, &crawler_trap_detector,
+                    text_extractor, &*fetcher, &*searching_service)
+                    .with_cancellation(&ct).await;
                 info!("Indexing worker stopped");
             }.instrument(error_span!("indexing_worker", worker = i))));
         }
+
+        let queue = self.queue.clone();
+        let indexed_links_storage = self.indexed_links_storage.clone();
+        let robots_txt_cache = self.robots_txt_cache.clone();
+        let recrawl_policy = self.recrawl_policy.clone();
+        let events = self.events.clone();
+        let ct = self.cancellation_token.clone();
+
+        self.processing_handles.push(tokio::spawn(async move {
+            schedule_recrawls(&*queue, &*indexed_links_storage, &robots_txt_cache, &recrawl_policy, &events)
+                .with_cancellation(&ct).await;
+            info!("Recrawl scheduler stopped");
+        }.instrument(error_span!("recrawl_scheduler"))));
+
+        let link_graph_storage = self.link_graph_storage.clone();
+        let page_rank_settings = self.page_rank_settings.clone();
+        let searching_service = self.searching_service.clone();
+        let ct = self.cancellation_token.clone();
+
+        self.processing_handles.push(tokio::spawn(async move {
+            update_page_ranks(&link_graph_storage, &page_rank_settings, &*searching_service)
+                .with_cancellation(&ct).await;
+            info!("Page rank updater stopped");
+        }.instrument(error_span!("page_rank_updater"))));
     }
 
-    async fn process_queue(queue: &IndexingQueue, indexed_links_storage: &Storage, url_processor: U, text_extractor: TextExtractor) {
-        let http_client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(60))
-            .redirect(Policy::limited(20))
-            .connection_verbose(true)
-            .build()
-            .unwrap();
+    #[allow(clippy::too_many_arguments)]
+    async fn ingest_sitemaps(
+        web_site: &WebSite, queue: &dyn IndexingQueue, indexed_links_storage: &dyn IndexedLinksStorage,
+        web_site_storage: &Arc<WebSiteStorage>, robots_txt_cache: &RobotsTxtCache, events: &IndexingEvents,
+        sitemap_loader: &SitemapLoader, url_processor: U, crawler_trap_detector: &CrawlerTrapDetector)
+    {
+        // Pages of sitemaps are considered to be linked from the seed page.
+        let depth = 1;
 
-        let mut searching_client = loop {
-            info!("Connecting to searching service...");
-            match SearchingApiClient::connect("http://localhost:8083").await {
-                Ok(client) => break client,
-                Err(err) => {
-                    warn!("Couldn't connect to searching service {}", err);
-                    tokio::time::sleep(Duration::from_secs(5)).await;
-                },
-            };
-        };
-        searching_client = searching_client.max_decoding_message_size(usize::MAX);
+        let mut sitemap_urls = robots_txt_cache.get(&web_site.url).await.unwrap().sitemaps().to_vec();
+        if let Ok(default_sitemap_url) = web_site.url.join("/sitemap.xml") {
+            if !sitemap_urls.contains(&default_sitemap_url) {
+                sitemap_urls.push(default_sitemap_url);
+            }
+        }
+
+        for sitemap_url in sitemap_urls {
+            let process_url = |url| url_processor.process_url(url).filter(|u| web_site.contains(u));
+            let entries = sitemap_loader.load_entries(&sitemap_url, process_url, robots_txt_cache, queue).await;
+            info!("Sitemap {} has {} URLs", sitemap_url, entries.len());
+
+            for entry in entries {
+                let Some(url) = url_processor.process_url(entry.url) else { continue; };
+                if !web_site.contains(&url) {
+                    debug!("Skip URL {} out of the web site scope", url);
+                    continue;
+                }
+
+                if !crawler_trap_detector.is_match(&url) {
+                    debug!("Skip URL {} of a crawler trap", url);
+                    continue;
+                }
+
+                let priority = match indexed_links_storage.get_last_indexed_time(&url).await.unwrap() {
+                    Some(last_indexed_time) => {
+                        let was_modified = entry.last_modified_time.is_some_and(|t| t > last_indexed_time);
+                        if !was_modified {
+                            debug!("Skip URL {} not modified since the last indexing", url);
+                            continue;
+                        }
+
+                        QueueItemPriority::RECRAWL
+                    },
+                    None => QueueItemPriority::NEW,
+                };
+
+                if !web_site.is_depth_allowed(depth) {
+                    info!("Skip sitemaps of web site {} exceeding its max depth", web_site.url);
+                    web_site_storage.set_budget_exhausted(web_site.id).await.unwrap();
+                    return;
+                }
+
+                if !robots_txt_cache.is_allowed(&url).await.unwrap() {
+                    debug!("Skip URL {} disallowed by robots.txt", url);
+                    continue;
+                }
 
+                let enqueue_result = enqueue_pages(
+                    queue, web_site_storage, events, web_site.id, depth, vec![(url, priority)]).await;
+                if !enqueue_result.unwrap() {
+                    info!("Page budget of web site {} is exhausted", web_site.url);
+                    return;
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn process_queue(
+        queue: &dyn IndexingQueue, indexed_links_storage: &dyn IndexedLinksStorage,
+        web_site_storage: &Arc<WebSiteStorage>, link_graph_storage: &LinkGraphStorage,
+        robots_txt_cache: &RobotsTxtCache, recrawl_policy: &RecrawlPolicy,
+        duplicate_settings: &DuplicateSettings, events: &IndexingEvents, url_processor: U,
+        crawler_trap_detector: &CrawlerTrapDetector, text_extractor: TextExtractor, fetcher: &dyn Fetcher,
+        searching_service: &dyn SearchingService)
+    {
         let link_selector = Selector::parse("a").unwrap();
         let base_selector = Selector::parse("base").unwrap();
+        let directives_extractor = PageDirectivesExtractor::new();
 
         loop {
             let queue_item = queue.peek().await.unwrap();
             info!("Processing {}", queue_item.url);
 
-            let html_text = match execute_request(&http_client, &queue_item.url).await {
-                Ok(text) => text,
-                Err(err) => {
-                    warn!("Request {} failed {}", queue_item.url, err);
+            let robots_txt = robots_txt_cache.get(&queue_item.url).await.unwrap();
+            queue.set_crawl_delay(&queue_item.host, robots_txt.crawl_delay(CRAWLER_USER_AGENT));
+
+            // Inbound anchor texts are sent with the page, so the page is fetched in full to be sent again if they
+            // changed since the last indexing.
+            let anchor_texts = link_graph_storage.get_inbound_anchor_texts(&queue_item.url).await.unwrap();
+            let anchor_texts_hash = hash_anchor_texts(&anchor_texts);
+            let cache_validators = match indexed_links_storage.get_anchor_texts_hash(&queue_item.url).await.unwrap() {
+                Some(indexed_anchor_texts_hash) if indexed_anchor_texts_hash == anchor_texts_hash =>
+                    indexed_links_storage.get_cache_validators(&queue_item.url).await.unwrap(),
+                _ => None,
+            };
+            let fetch_result = execute_request(fetcher, &queue_item.url, cache_validators).await;
+            let fetch_time = Utc::now();
+            let (html_text, response_url, cache_validators, header_directives) = match fetch_result {
+                Ok(FetchResult::Modified { text, url, cache_validators, robots_directives }) => {
+                    events.publish(&queue_item.url, IndexingEventKind::Fetched);
+                    (text, url, cache_validators, robots_directives)
+                },
+                Ok(FetchResult::NotModified) => {
+                    info!("Page {} was not modified since the last indexing", queue_item.url);
+                    queue.mark_processed(&queue_item).await.unwrap();
+                    indexed_links_storage.update_last_indexed_time(&queue_item.url, Utc::now()).await.unwrap();
+                    web_site_storage.count_unchanged_page(queue_item.site_id).await.unwrap();
+                    events.publish(&queue_item.url, IndexingEventKind::NotModified);
+                    continue;
+                },
+                // Skipped URLs are kept as indexed links without content, so they are fetched again only when they
+                // are outdated.
+                Ok(FetchResult::Skipped { reason, cache_validators }) => {
+                    info!("Page {} is skipped: {}", queue_item.url, reason);
+                    queue.mark_processed(&queue_item).await.unwrap();
+                    indexed_links_storage
+                        .add(
+                            &queue_item.url, queue_item.site_id, queue_item.depth, Utc::now(), &cache_validators,
+                            None, None)
+                        .await
+                        .unwrap();
+                    events.publish(&queue_item.url, IndexingEventKind::Skipped { reason });
+                    continue;
+                },
+                Err(FetchError::Transient { message, retry_after }) => {
+                    warn!("Request {} failed {}", queue_item.url, message);
+                    let will_retry = queue.retry_later(&queue_item, &message, retry_after).await.unwrap();
+                    events.publish(&queue_item.url, IndexingEventKind::Failed { error: message, will_retry });
+                    continue;
+                },
+                Err(FetchError::Permanent(message)) => {
+                    warn!("Request {} failed permanently {}", queue_item.url, message);
+                    queue.mark_failed(&queue_item, &message).await.unwrap();
+                    events.publish(&queue_item.url, IndexingEventKind::Failed { error: message, will_retry: false });
                     continue;
                 },
             };
 
-            let (links, text) = {
-                let html = scraper::Html::parse_document(&html_text);
-                if !html.errors.is_empty() {
-                    warn!("Html has {} errors", html.errors.len());
-                }
+            // Links inherit the web site of the page they were found on and are followed only within its scope
+            // and limits.
+            let web_site = web_site_storage.get(queue_item.site_id).await.unwrap();
+            let directive_settings = web_site.as_ref().map(|s| s.directives.clone()).unwrap_or_default();
 
-                let base_url = if let Some(base) = html.select(&base_selector).next() {
-                    queue_item.url.join(base.value().attr("href").unwrap_or("")).unwrap_or(queue_item.url.clone())
-                }
-                else {
-                    queue_item.url.clone()
-                };
+            // A redirected page is kept as an alternate of the page it was redirected to, which is crawled as a page
+            // of its own, so that the content and the links of a page are always stored under the same URL.
+            let redirect_url = (response_url != queue_item.url).then_some(response_url);
+            let (links, document, directives) = match redirect_url {
+                Some(_) => (Vec::new(), None, PageDirectives::default()),
+                None => {
+                    let html = scraper::Html::parse_document(&html_text);
+                    if !html.errors.is_empty() {
+                        warn!("Html has {} errors", html.errors.len());
+                    }
+
+                    let base_url = if let Some(base) = html.select(&base_selector).next() {
+                        queue_item.url.join(base.value().attr("href").unwrap_or("")).unwrap_or(queue_item.url.clone())
+                    }
+                    else {
+                        queue_item.url.clone()
+                    };
 
-                let links = html.select(&link_selector)
-                    .filter_map(|a| a.value().attr("href").and_then(|h| url_processor.parse_url(&base_url, h)))
-                    .collect::<Vec<_>>();
+                    let directives = directives_extractor
+                        .extract(&html, &base_url, header_directives, &directive_settings, &url_processor);
 
-                (links, text_extractor.extract_text(&html))
+                    // Links of a nofollow page are neither followed nor kept in the link graph.
+                    let links = if directives.robots.nofollow {
+                        Vec::new()
+                    }
+                    else {
+                        html.select(&link_selector)
+                            .filter_map(|a| {
+                                let href = a.value().attr("href")?;
+                                let target_url = url_processor.parse_url(&base_url, href)?;
+                                let link = LinkEdge {
+                                    target_url,
+                                    anchor_text: format_inline_text(a, |_| false),
+                                    rel: a.value().attr("rel").map(|r| r.to_string()),
+                                };
+                                Some((link, !is_nofollow_link(&a, &directive_settings)))
+                            })
+                            .collect::<Vec<_>>()
+                    };
+
+                    let document = Some(text_extractor.extract_document(&html, fetch_time))
+                        .filter(|d| !directives.robots.noindex && !d.is_empty());
+
+                    (links, document, directives)
+                },
             };
 
+            let (link_edges, is_followed): (Vec<_>, Vec<_>) = links.into_iter().unzip();
+            link_graph_storage.set_links(&queue_item.url, &link_edges).await.unwrap();
+            let links = link_edges.into_iter()
+                .zip(is_followed)
+                .filter_map(|(link, is_followed)| is_followed.then_some(link.target_url))
+                .collect::<Vec<_>>();
+
             info!("Html has {} links", links.len());
+            events.publish(&queue_item.url, IndexingEventKind::Extracted { links_count: links.len() });
+
+            // An alternate page isn't indexed, its canonical page is indexed instead. The canonical page is found on
+            // the alternate one like its links, so it's within the same limits. Canonical pages and redirects outside
+            // of the web site scope are ignored.
+            let canonical_url = redirect_url.or(directives.canonical_url)
+                .filter(|c| *c != queue_item.url && web_site.as_ref().is_some_and(|s| s.contains(c)));
+            let link_depth = queue_item.depth + 1;
+            let fingerprint = document.as_ref().and_then(|d| d.text.as_deref()).map(ContentFingerprint::new);
+            let (document, content) = match canonical_url {
+                Some(canonical_url) => {
+                    info!("Page {} is an alternate of {}", queue_item.url, canonical_url);
+                    let is_new = indexed_links_storage.get_last_indexed_time(&canonical_url).await.unwrap().is_none();
+                    if !is_new {
+                        debug!("Skip already indexed canonical URL {}", canonical_url);
+                    }
+                    else if !web_site.as_ref().is_some_and(|s| s.is_depth_allowed(link_depth)) {
+                        debug!("Skip canonical URL {} exceeding the max depth of the web site", canonical_url);
+                        web_site_storage.set_budget_exhausted(queue_item.site_id).await.unwrap();
+                    }
+                    else if !crawler_trap_detector.is_match(&canonical_url) {
+                        debug!("Skip canonical URL {} of a crawler trap", canonical_url);
+                    }
+                    else if robots_txt_cache.is_allowed(&canonical_url).await.unwrap() {
+                        let enqueue_result = enqueue_pages(
+                            queue, web_site_storage, events, queue_item.site_id, link_depth,
+                            vec![(canonical_url.clone(), QueueItemPriority::NEW)]).await;
+                        if !enqueue_result.unwrap() {
+                            info!("Page budget of the web site is exhausted, skip canonical URL {}", canonical_url);
+                        }
+                    }
 
+                    // The alternate page is kept as a duplicate of its canonical page, so other pages aren't taken
+                    // as its duplicates.
+                    let fingerprint = fingerprint.unwrap_or_else(|| ContentFingerprint::new(""));
+                    let duplicate = ContentDuplicate { original_url: canonical_url, distance: 0 };
+                    (None, Some((fingerprint, Some(duplicate))))
+                },
+                None => {
+                    let content = match fingerprint {
+                        Some(fingerprint) => {
+                            let duplicate = indexed_links_storage
+                                .find_duplicate_original(
+                                    queue_item.site_id, &queue_item.url, &fingerprint, duplicate_settings.max_distance)
+                                .await
+                                .unwrap();
+                            Some((fingerprint, duplicate))
+                        },
+                        None => None,
+                    };
+                    (document, content)
+                },
+            };
+            let is_duplicate = match &content {
+                Some((_, Some(duplicate))) => {
+                    info!(
+                        "Page {} is a duplicate of {} with distance {}",
+                        queue_item.url, duplicate.original_url, duplicate.distance);
+                    true
+                },
+                _ => false,
+            };
+            let document = document
+                .filter(|_| !is_duplicate || !duplicate_settings.skip_duplicates)
+                .map(|document| PageDocument { anchor_texts, ..document });
+
+            if directives.robots.noindex {
+                info!("Page {} is not indexed because of noindex directive", queue_item.url);
+            }
+
+            let now = Utc::now();
             let links = links.into_iter()
                 .filter(|l| {
-                    let was_indexed = indexed_links_storage.get_last_indexed_time(l).unwrap().is_some();
-                    if was_indexed {
-                        debug!("Skip already indexed URL {}", l);
+                    let is_in_scope = web_site.as_ref().is_some_and(|s| s.contains(l));
+                    if !is_in_scope {
+                        debug!("Skip URL {} out of the web site scope", l);
                     }
 
-                    !was_indexed
-                });
+                    is_in_scope
+                })
+                // Only URLs of crawled web sites are counted, so that links to other sites don't take the memory.
+                .filter(|l| {
+                    let is_trapped = !crawler_trap_detector.is_match(l);
+                    if is_trapped {
+                        debug!("Skip URL {} of a crawler trap", l);
+                    }
+
+                    !is_trapped
+                })
+                .collect::<Vec<_>>();
+            let last_indexed_times = indexed_links_storage.get_last_indexed_times(&links).await.unwrap();
+            let mut links = links.into_iter()
+                .filter_map(|l| match last_indexed_times.get(&l).copied() {
+                    None => Some((l, QueueItemPriority::NEW)),
+                    Some(last_indexed_time) if recrawl_policy.is_outdated(&l, last_indexed_time, now) =>
+                        Some((l, QueueItemPriority::RECRAWL)),
+                    Some(_) => {
+                        debug!("Skip already indexed URL {}", l);
+                        None
+                    },
+                })
+                .collect::<Vec<_>>();
 
-            for link in links {
-                queue.enqueue(link).unwrap();
+            if !links.is_empty() && !web_site.as_ref().is_some_and(|s| s.is_depth_allowed(link_depth)) {
+                debug!("Skip links of page {} exceeding the max depth of the web site", queue_item.url);
+                web_site_storage.set_budget_exhausted(queue_item.site_id).await.unwrap();
+                links.clear();
             }
 
-            if let Some(text) = text {
-                while let Err(err) = searching_client.add_page(AddPageRequest { url: queue_item.url.to_string(), text: text.clone() }).await {
-                    warn!("Failed to send page to searching service {}", err);
-                    tokio::time::sleep(Duration::from_secs(5)).await;
+            let mut allowed_links = Vec::with_capacity(links.len());
+            for (link, priority) in links {
+                if !robots_txt_cache.is_allowed(&link).await.unwrap() {
+                    debug!("Skip URL {} disallowed by robots.txt", link);
+                    continue;
                 }
+
+                allowed_links.push((link, priority));
+            }
+
+            // Links of the page are enqueued in one transaction.
+            let enqueue_result = enqueue_pages(
+                queue, web_site_storage, events, queue_item.site_id, link_depth, allowed_links).await;
+            if !enqueue_result.unwrap() {
+                info!("Page budget of the web site is exhausted, skip the rest links of page {}", queue_item.url);
+            }
+
+            // A recrawled page whose HTML changed may still have the same content, e.g. if only ads or scripts changed.
+            let document_hash = document.as_ref().map(|d| d.content_hash());
+            let sent_document_hash = indexed_links_storage.get_document_hash(&queue_item.url).await.unwrap();
+            match document {
+                Some(_) if document_hash == sent_document_hash => {
+                    info!("Content of page {} was not changed since the last indexing", queue_item.url);
+                    web_site_storage.count_unchanged_page(queue_item.site_id).await.unwrap();
+                },
+                Some(document) => {
+                    let page_rank = link_graph_storage.get_page_rank(&queue_item.url).await.unwrap();
+                    let request = create_add_page_request(&queue_item.url, document, page_rank);
+                    while let Err(err) = searching_service.add_page(request.clone()).await {
+                        warn!("Failed to send page to searching service {}", err);
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+
+                    web_site_storage.count_updated_page(queue_item.site_id).await.unwrap();
+                    events.publish(&queue_item.url, IndexingEventKind::Sent);
+                },
+                // A page which was sent before may have become a duplicate, an alternate or a noindex page since then.
+                None if sent_document_hash.is_some() => {
+                    info!("Page {} is removed from the searching service", queue_item.url);
+                    let request = DeletePageRequest { url: queue_item.url.to_string() };
+                    while let Err(err) = searching_service.delete_page(request.clone()).await {
+                        warn!("Failed to delete page from searching service {}", err);
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                },
+                None => {},
+            }
+
+            queue.mark_processed(&queue_item).await.unwrap();
+            indexed_links_storage
+                .add(
+                    &queue_item.url, queue_item.site_id, queue_item.depth, Utc::now(), &cache_validators,
+                    document_hash, Some(anchor_texts_hash))
+                .await
+                .unwrap();
+            if let Some((fingerprint, duplicate)) = content {
+                indexed_links_storage.set_content(&queue_item.url, &fingerprint, duplicate.as_ref()).await.unwrap();
+            }
+        }
+    }
+}
+
+async fn schedule_recrawls(
+    queue: &dyn IndexingQueue, indexed_links_storage: &dyn IndexedLinksStorage, robots_txt_cache: &RobotsTxtCache,
+    recrawl_policy: &RecrawlPolicy, events: &IndexingEvents)
+{
+    loop {
+        let now = Utc::now();
+        let mut outdated_links = Vec::new();
+
+        for (origin, max_age) in recrawl_policy.site_max_ages() {
+            outdated_links
+                .extend(indexed_links_storage.get_site_links_indexed_before(origin, now - max_age).await.unwrap());
+        }
+
+        let site_origins = recrawl_policy.site_max_ages().map(|(origin, _)| origin.to_string()).collect();
+        outdated_links.extend(indexed_links_storage
+            .get_other_sites_links_indexed_before(site_origins, now - recrawl_policy.default_max_age())
+            .await
+            .unwrap());
+
+        let mut scheduled_count = 0;
+        for link in outdated_links {
+            if !robots_txt_cache.is_allowed(&link.url).await.unwrap() {
+                debug!("Skip recrawling of URL {} disallowed by robots.txt", link.url);
+                continue;
+            }
+
+            if queue.enqueue(link.url.clone(), link.site_id, link.depth, QueueItemPriority::RECRAWL).await.unwrap() {
+                events.publish(&link.url, IndexingEventKind::Enqueued);
+                scheduled_count += 1;
             }
+        }
+
+        if scheduled_count > 0 {
+            info!("Scheduled {} outdated pages for recrawling", scheduled_count);
+        }
+
+        tokio::time::sleep(recrawl_policy.check_interval()).await;
+    }
+}
+
+// Page ranks are computed over the whole link graph, so they are periodically recomputed instead of being updated
+// with every indexed page.
+async fn update_page_ranks(
+    link_graph_storage: &LinkGraphStorage, page_rank_settings: &PageRankSettings,
+    searching_service: &dyn SearchingService)
+{
+    loop {
+        let links = link_graph_storage.get_links().await.unwrap();
+        let endorsed_links = links.iter()
+            .filter(|(_, link)| link.is_endorsed())
+            .map(|(source_url, link)| (source_url, &link.target_url));
+        let page_ranks = compute_page_ranks(endorsed_links, page_rank_settings);
+        link_graph_storage.set_page_ranks(page_ranks.iter().map(|(url, score)| (url, *score))).await.unwrap();
+        info!("Computed page ranks of {} pages", page_ranks.len());
+
+        let indexed_page_ranks = link_graph_storage.get_indexed_page_ranks().await.unwrap();
+        for batch in indexed_page_ranks.chunks(PAGE_RANKS_BATCH_SIZE) {
+            let request = SetPageRanksRequest {
+                page_ranks: batch.iter().map(|(url, score)| PageRank { url: url.to_string(), score: *score }).collect(),
+            };
+            while let Err(err) = searching_service.set_page_ranks(request.clone()).await {
+                warn!("Failed to send page ranks to searching service {}", err);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(page_rank_settings.update_interval_secs)).await;
+    }
+}
+
+// Returns false if the page budget of the web site is exhausted, pages after the first new page over the budget
+// aren't enqueued then. Only new pages count towards the budget, recrawled ones were counted when they had been found.
+async fn enqueue_pages(
+    queue: &dyn IndexingQueue, web_site_storage: &Arc<WebSiteStorage>, events: &IndexingEvents, site_id: i64,
+    depth: u32, pages: Vec<(Url, i32)>)
+    -> Result<bool, rusqlite::Error>
+{
+    if pages.is_empty() {
+        return Ok(true);
+    }
+
+    let result = queue.enqueue_batch(&pages, site_id, depth, web_site_storage).await?;
+    for ((url, _), is_inserted) in pages.iter().zip(result.inserted) {
+        if is_inserted {
+            events.publish(url, IndexingEventKind::Enqueued);
+        }
+    }
+
+    if result.is_budget_exhausted {
+        web_site_storage.set_budget_exhausted(site_id).await?;
+    }
+
+    Ok(!result.is_budget_exhausted)
+}
+
+enum FetchResult {
+    // URL is the URL of the page after redirects.
+    Modified { text: String, url: Url, cache_validators: CacheValidators, robots_directives: RobotsDirectives },
+    NotModified,
+    // The response isn't a page, e.g. an image or a PDF file.
+    Skipped { reason: String, cache_validators: CacheValidators },
+}
+
+async fn execute_request(fetcher: &dyn Fetcher, uri: &Url, cache_validators: Option<CacheValidators>)
+    -> Result<FetchResult, FetchError>
+{
+    let mut headers = HeaderMap::new();
+    if let Some(cache_validators) = cache_validators {
+        let to_header_value = |v: String| HeaderValue::from_str(&v).ok();
+        if let Some(etag) = cache_validators.etag.and_then(to_header_value) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
 
-            queue.mark_processed(queue_item.id).unwrap();
-            indexed_links_storage.add(&queue_item.url, Utc::now()).unwrap();
+        if let Some(last_modified) = cache_validators.last_modified.and_then(to_header_value) {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
         }
     }
+
+    let response = fetcher.fetch(uri, headers, MAX_PAGE_SIZE).await?;
+    if response.status == StatusCode::NOT_MODIFIED {
+        return Ok(FetchResult::NotModified);
+    }
+
+    let status = response.status;
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT {
+        let retry_after = get_retry_after(&response.headers, Utc::now());
+        return Err(FetchError::Transient { message: format!("Status {}", status), retry_after });
+    }
+
+    if !status.is_success() {
+        return Err(FetchError::Permanent(format!("Status {}", status)));
+    }
+
+    let content_type = response.headers.get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_ascii_lowercase())
+        .unwrap_or_default();
+    let is_html = content_type.starts_with("text/html") || content_type.starts_with("application/xhtml+xml");
+    let cache_validators = get_cache_validators(&response.headers);
+    if !content_type.is_empty() && !is_html {
+        let reason = format!("Unsupported content type {}", content_type);
+        return Ok(FetchResult::Skipped { reason, cache_validators });
+    }
+
+    let robots_directives = get_robots_directives(&response.headers);
+
+    Ok(FetchResult::Modified { text: response.text(), url: response.url, cache_validators, robots_directives })
+}
+
+fn get_retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let time = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((time.with_timezone(&Utc) - now).to_std().unwrap_or_default())
+}
+
+fn get_cache_validators(headers: &HeaderMap) -> CacheValidators {
+    let get_header = |name| headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+    CacheValidators { etag: get_header(ETAG), last_modified: get_header(LAST_MODIFIED) }
+}
+
+fn hash_anchor_texts(anchor_texts: &[String]) -> u64 {
+    let mut hasher = SipHasher13::new();
+    anchor_texts.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn create_add_page_request(url: &Url, document: PageDocument, page_rank: Option<f64>) -> AddPageRequest {
+    AddPageRequest {
+        url: url.to_string(),
+        text: document.text.unwrap_or_default(),
+        title: document.title.unwrap_or_default(),
+        description: document.description.unwrap_or_default(),
+        headings: document.headings.into_iter().map(|h| Heading { level: h.level, text: h.text }).collect(),
+        fetch_timestamp: document.fetch_time.timestamp_millis(),
+        page_rank,
+        anchor_texts: document.anchor_texts,
+    }
 }
 
-async fn execute_request(client: &reqwest::Client, uri: &Url) -> reqwest::Result<String> {
-    client.get(uri.clone()).send().await?.error_for_status()?.text().await
+fn get_robots_directives(headers: &HeaderMap) -> RobotsDirectives {
+    headers.get_all(X_ROBOTS_TAG).iter()
+        .filter_map(|v| v.to_str().ok())
+        .map(RobotsDirectives::parse)
+        .fold(RobotsDirectives::default(), RobotsDirectives::merge)
 }
 
 impl<U> Drop for Indexer<U> {
@@ -185,4 +804,331 @@ impl<U> Drop for Indexer<U> {
             while !handle.is_finished() {}
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod indexer_tests {
+    use chrono::TimeZone;
+
+    use crate::queue::{InMemoryIndexingQueue, SqliteIndexingQueue, PolitenessSettings, RetrySettings, PageStatus};
+    use crate::indexing::{
+        TextExtractionMode, RecrawlSettings, FixtureFetcher, FetchResponse, InMemorySearchingService, UrlProcessorImpl,
+        AllowedSchemeUrlFilter, RemoveFragmentNormalizer, InMemoryIndexedLinksStorage, SqliteIndexedLinksStorage,
+        SqlitePool, SqlitePoolSettings, CrawlerTrapSettings};
+
+    use super::*;
+
+    fn create_indexer(fetcher: Arc<FixtureFetcher>, searching_service: Arc<InMemorySearchingService>)
+        -> Indexer<UrlProcessorImpl<AllowedSchemeUrlFilter, RemoveFragmentNormalizer>>
+    {
+        let indexed_links_storage = Arc::new(InMemoryIndexedLinksStorage::default());
+        let queue = InMemoryIndexingQueue::new(
+            indexed_links_storage.clone(),
+            PolitenessSettings { min_delay_ms: 0, max_connections_per_host: 1 },
+            RetrySettings { max_attempts: 1, initial_delay_secs: 1, max_delay_secs: 1 });
+
+        create_indexer_with_storages(
+            Arc::new(SqlitePool::open_in_memory().unwrap()), Arc::new(queue), indexed_links_storage, fetcher,
+            searching_service)
+    }
+
+    fn create_indexer_with_storages(
+        pool: Arc<SqlitePool>, queue: DynIndexingQueue, indexed_links_storage: DynIndexedLinksStorage,
+        fetcher: Arc<FixtureFetcher>, searching_service: Arc<InMemorySearchingService>)
+        -> Indexer<UrlProcessorImpl<AllowedSchemeUrlFilter, RemoveFragmentNormalizer>>
+    {
+        let robots_txt_cache = RobotsTxtCache::new(
+            pool.clone(), fetcher.clone(), chrono::Duration::hours(1), chrono::Duration::hours(1))
+            .unwrap();
+
+        Indexer::new(
+            queue, indexed_links_storage, WebSiteStorage::new(pool.clone()).unwrap(),
+            LinkGraphStorage::new(pool).unwrap(), robots_txt_cache,
+            RecrawlPolicy::new(RecrawlSettings { default_max_age_hours: 24, check_interval_secs: 3600, sites: vec![] }),
+            DuplicateSettings { max_distance: 0, skip_duplicates: true },
+            PageRankSettings { update_interval_secs: 3600, damping_factor: 0.85, iterations: 10 },
+            UrlProcessorImpl::new(AllowedSchemeUrlFilter::new(vec!["https".to_string()]), RemoveFragmentNormalizer {}),
+            Arc::new(CrawlerTrapDetector::new(CrawlerTrapSettings {
+                max_repeated_segments: 3,
+                max_path_depth: 16,
+                max_query_combinations: 100,
+                max_urls_per_pattern: 100,
+                max_tracked_paths: 1000,
+            })),
+            TextExtractor::new(TextExtractionMode::Raw), SitemapLoader::new(fetcher.clone()), fetcher,
+            searching_service)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn should_index_pages_of_web_site() {
+        // Arrange
+
+        let url = |path| Url::parse("https://localhost/").unwrap().join(path).unwrap();
+        let fetcher = Arc::new(FixtureFetcher::default());
+        fetcher.add_page(url("/"), "text/html", r#"<p>Welcome to the home page.</p>
+            <a href="/docs">Docs</a> <a href="/missing">Missing</a> <a href="https://example.com/">External</a>
+            <a href="/manual.pdf">Manual</a>"#);
+        let mut docs_response = FetchResponse::new(StatusCode::OK, url("/docs/"));
+        docs_response.headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+        docs_response.body = r#"<p>Documentation of the project.</p><a href="intro">Intro</a>"#.as_bytes().to_vec();
+        fetcher.add_response(url("/docs"), Ok(docs_response));
+        fetcher.add_page(
+            url("/docs/"), "text/html", r#"<p>Documentation of the project.</p><a href="intro">Intro</a>"#);
+        fetcher.add_page(url("/docs/intro"), "text/html", "<p>Introduction to the project.</p>");
+        fetcher.add_page(url("/manual.pdf"), "application/pdf", "%PDF-1.4");
+        fetcher.add_page(url("/sitemap.xml"), "application/xml", r#"<?xml version="1.0" encoding="UTF-8"?>
+            <sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <sitemap><loc>https://localhost/news-sitemap.xml</loc></sitemap>
+                <sitemap><loc>https://example.com/sitemap.xml</loc></sitemap>
+            </sitemapindex>"#);
+        fetcher.add_page(url("/news-sitemap.xml"), "application/xml", r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <url><loc>https://localhost/news</loc></url>
+            </urlset>"#);
+        fetcher.add_page(url("/news"), "text/html", "<p>Latest news of the project.</p>");
+        let searching_service = Arc::new(InMemorySearchingService::default());
+        let mut target = create_indexer(fetcher.clone(), searching_service.clone());
+        target.start_processing(1);
+
+        // Act
+
+        target
+            .index_web_site(
+                url("/"), CrawlScope::SameOrigin, CrawlLimits { max_depth: None, max_pages: None },
+                PageDirectiveSettings::default())
+            .await;
+        let all_pages = PageFilter { origin: None, status: None };
+        for _ in 0..500 {
+            let pages = target.get_pages(&all_pages, None, 100).await;
+            if pages.len() == 7 && pages.iter().all(|p| matches!(p.status, PageStatus::Indexed | PageStatus::Failed)) {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Assert
+
+        let pages = target.get_pages(&all_pages, None, 100).await;
+        assert_eq!(
+            vec![
+                (url("/"), PageStatus::Indexed),
+                (url("/docs"), PageStatus::Indexed),
+                (url("/docs/"), PageStatus::Indexed),
+                (url("/docs/intro"), PageStatus::Indexed),
+                (url("/manual.pdf"), PageStatus::Indexed),
+                (url("/missing"), PageStatus::Failed),
+                (url("/news"), PageStatus::Indexed),
+            ],
+            pages.into_iter().map(|p| (p.url, p.status)).collect::<Vec<_>>());
+
+        let sent_pages = searching_service.pages();
+        assert_eq!(
+            vec![
+                ("https://localhost/", "Welcome to the home page.\n\nDocs Missing External Manual"),
+                ("https://localhost/docs/", "Documentation of the project.\n\nIntro"),
+                ("https://localhost/docs/intro", "Introduction to the project."),
+                ("https://localhost/news", "Latest news of the project."),
+            ],
+            sent_pages.iter().map(|p| (p.url.as_str(), p.text.as_str())).sorted().collect::<Vec<_>>());
+        let intro_page = sent_pages.iter().find(|p| p.url == "https://localhost/docs/intro").unwrap();
+        assert_eq!(vec!["Intro".to_string()], intro_page.anchor_texts);
+        assert!(!fetcher.requested_urls().iter().any(|u| u.host_str() == Some("example.com")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn should_delete_sent_page_which_became_duplicate() {
+        // Arrange
+
+        // The home page was sent before and has the same content as another page of the web site now.
+        let url = |path| Url::parse("https://localhost/").unwrap().join(path).unwrap();
+        let fetcher = Arc::new(FixtureFetcher::default());
+        fetcher.add_page(url("/"), "text/html", "<p>Same content.</p>");
+        let indexed_links_storage = Arc::new(InMemoryIndexedLinksStorage::default());
+        for (path, document_hash) in [("/original", Some(1)), ("/", Some(2))] {
+            indexed_links_storage
+                .add(&url(path), 1, 0, Utc::now(), &CacheValidators::default(), document_hash, None)
+                .await
+                .unwrap();
+        }
+        indexed_links_storage
+            .set_content(&url("/original"), &ContentFingerprint::new("Same content."), None)
+            .await
+            .unwrap();
+        let queue = InMemoryIndexingQueue::new(
+            indexed_links_storage.clone(),
+            PolitenessSettings { min_delay_ms: 0, max_connections_per_host: 1 },
+            RetrySettings { max_attempts: 1, initial_delay_secs: 1, max_delay_secs: 1 });
+        let searching_service = Arc::new(InMemorySearchingService::default());
+        let mut target = create_indexer_with_storages(
+            Arc::new(SqlitePool::open_in_memory().unwrap()), Arc::new(queue), indexed_links_storage, fetcher,
+            searching_service.clone());
+        target.start_processing(1);
+
+        // Act
+
+        target
+            .index_web_site(
+                url("/"), CrawlScope::SameOrigin, CrawlLimits { max_depth: None, max_pages: None },
+                PageDirectiveSettings::default())
+            .await;
+        for _ in 0..500 {
+            if !searching_service.deleted_pages().is_empty() {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Assert
+
+        assert!(searching_service.pages().is_empty());
+        assert_eq!(
+            vec!["https://localhost/".to_string()],
+            searching_service.deleted_pages().into_iter().map(|p| p.url).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn should_record_alternate_page_as_duplicate_of_canonical_page() {
+        // Arrange
+
+        let url = |path| Url::parse("https://localhost/").unwrap().join(path).unwrap();
+        let fetcher = Arc::new(FixtureFetcher::default());
+        fetcher.add_page(
+            url("/"), "text/html", r#"<link rel="canonical" href="/home"><p>Welcome to the home page.</p>"#);
+        fetcher.add_page(url("/home"), "text/html", "<p>Welcome to the home page.</p>");
+        let indexed_links_storage = Arc::new(InMemoryIndexedLinksStorage::default());
+        let queue = InMemoryIndexingQueue::new(
+            indexed_links_storage.clone(),
+            PolitenessSettings { min_delay_ms: 0, max_connections_per_host: 1 },
+            RetrySettings { max_attempts: 1, initial_delay_secs: 1, max_delay_secs: 1 });
+        let searching_service = Arc::new(InMemorySearchingService::default());
+        let mut target = create_indexer_with_storages(
+            Arc::new(SqlitePool::open_in_memory().unwrap()), Arc::new(queue), indexed_links_storage.clone(), fetcher,
+            searching_service.clone());
+        target.start_processing(1);
+
+        // Act
+
+        target
+            .index_web_site(
+                url("/"), CrawlScope::SameOrigin, CrawlLimits { max_depth: Some(1), max_pages: None },
+                PageDirectiveSettings::default())
+            .await;
+        for _ in 0..500 {
+            if !searching_service.pages().is_empty() {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let duplicates = indexed_links_storage.get_duplicates(None, None, 10).await.unwrap();
+
+        // Assert
+
+        assert_eq!(
+            vec!["https://localhost/home"],
+            searching_service.pages().iter().map(|p| p.url.as_str()).collect::<Vec<_>>());
+        assert_eq!(
+            vec![(url("/"), url("/home"))],
+            duplicates.into_iter().map(|d| (d.url, d.original_url)).collect::<Vec<_>>());
+    }
+
+    // Measures how indexing scales with workers sharing a file database while they wait for the network. Run it with
+    // cargo test benchmark_indexing_throughput --release -- --ignored --nocapture
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn benchmark_indexing_throughput() {
+        const PAGES_COUNT: usize = 1000;
+
+        for worker_count in [1, 4, 16, 64] {
+            // Arrange
+
+            let path = std::env::temp_dir().join(format!("wexplorer_benchmark_{}.db", std::process::id()));
+            let path = path.to_str().unwrap().to_string();
+            let remove_files = || {
+                for suffix in ["", "-wal", "-shm"] {
+                    let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+                }
+            };
+            remove_files();
+
+            // Pages make a binary tree, so the queue grows while workers index it.
+            let url = |i: usize| Url::parse(&format!("https://localhost/pages/{}", i)).unwrap();
+            let fetcher = Arc::new(FixtureFetcher::with_latency(Duration::from_millis(20)));
+            for i in 0..PAGES_COUNT {
+                let links = [2 * i + 1, 2 * i + 2].into_iter()
+                    .filter(|l| *l < PAGES_COUNT)
+                    .map(|l| format!(r#"<a href="/pages/{}">Page {}</a>"#, l, l))
+                    .join(" ");
+                fetcher.add_page(url(i), "text/html", &format!("<p>Content of page {}.</p>{}", i, links));
+            }
+
+            let pool_settings = SqlitePoolSettings { pool_size: worker_count.min(8) as usize, busy_timeout_ms: 5000 };
+            let pool = Arc::new(SqlitePool::open(&path, &pool_settings).unwrap());
+            let queue = SqliteIndexingQueue::new(
+                pool.clone(),
+                PolitenessSettings { min_delay_ms: 0, max_connections_per_host: worker_count },
+                RetrySettings { max_attempts: 1, initial_delay_secs: 1, max_delay_secs: 1 })
+                .unwrap();
+            let indexed_links_storage = SqliteIndexedLinksStorage::new(pool.clone()).unwrap();
+            let mut target = create_indexer_with_storages(
+                pool, Arc::new(queue), Arc::new(indexed_links_storage), fetcher,
+                Arc::new(InMemorySearchingService::default()));
+            target.start_processing(worker_count);
+
+            // Act
+
+            let start_time = std::time::Instant::now();
+            target
+                .index_web_site(
+                    url(0), CrawlScope::SameOrigin, CrawlLimits { max_depth: None, max_pages: None },
+                    PageDirectiveSettings::default())
+                .await;
+            let site_ids = target.get_web_sites(None, None, 1).await.iter().map(|s| s.web_site.id).collect::<Vec<_>>();
+            let mut indexed_count = 0;
+            while indexed_count < PAGES_COUNT && start_time.elapsed() < Duration::from_secs(120) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                let origin_page_counts = target.get_origin_page_counts(site_ids.clone()).await;
+                indexed_count = origin_page_counts.iter().map(|c| c.indexed as usize).sum();
+            }
+
+            let elapsed = start_time.elapsed();
+            drop(target);
+            remove_files();
+
+            // Assert
+
+            println!(
+                "{} workers indexed {} pages in {:?}, {:.0} pages/s",
+                worker_count, indexed_count, elapsed, indexed_count as f64 / elapsed.as_secs_f64());
+            assert_eq!(PAGES_COUNT, indexed_count);
+        }
+    }
+
+    #[test]
+    fn should_parse_retry_after_in_seconds_and_dates() {
+        // Arrange
+
+        let now = Utc.with_ymd_and_hms(2023, 5, 1, 10, 0, 0).unwrap();
+        let create_headers = |value| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, HeaderValue::from_static(value));
+            headers
+        };
+
+        // Act
+
+        let result1 = get_retry_after(&create_headers("120"), now);
+        let result2 = get_retry_after(&create_headers("Mon, 01 May 2023 10:05:00 GMT"), now);
+        let result3 = get_retry_after(&create_headers("Mon, 01 May 2023 09:00:00 GMT"), now);
+        let result4 = get_retry_after(&create_headers("soon"), now);
+
+        // Assert
+
+        assert_eq!(Some(Duration::from_secs(120)), result1);
+        assert_eq!(Some(Duration::from_secs(300)), result2);
+        assert_eq!(Some(Duration::ZERO), result3);
+        assert_eq!(None, result4);
+    }
+}
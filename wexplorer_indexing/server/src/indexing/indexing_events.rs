@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use tokio::sync::broadcast;
+use url::Url;
+
+const EVENTS_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IndexingEventKind {
+    Enqueued,
+    Fetched,
+    NotModified,
+    Extracted { links_count: usize },
+    Sent,
+    Failed { error: String, will_retry: bool },
+    Skipped { reason: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexingEvent {
+    pub url: Url,
+    pub kind: IndexingEventKind,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+pub struct IndexingEvents {
+    sender: broadcast::Sender<IndexingEvent>,
+}
+
+impl IndexingEvents {
+    pub fn new() -> Self {
+        Self { sender: broadcast::channel(EVENTS_CAPACITY).0 }
+    }
+
+    pub fn publish(&self, url: &Url, kind: IndexingEventKind) {
+        // Nobody may watch indexing at the moment, the event is dropped then.
+        let _ = self.sender.send(IndexingEvent { url: url.clone(), kind, time: Utc::now() });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<IndexingEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod indexing_events_tests {
+    use super::*;
+
+    #[test]
+    fn should_send_published_events_to_subscribers() {
+        // Arrange
+
+        let target = IndexingEvents::new();
+        let url = Url::parse("https://localhost/page").unwrap();
+        target.publish(&url, IndexingEventKind::Fetched);
+        let mut receiver = target.subscribe();
+
+        // Act
+
+        target.publish(&url, IndexingEventKind::Failed { error: "timeout".to_string(), will_retry: true });
+        let result = receiver.try_recv().unwrap();
+
+        // Assert
+
+        assert_eq!(url, result.url);
+        assert_eq!(IndexingEventKind::Failed { error: "timeout".to_string(), will_retry: true }, result.kind);
+        assert!(receiver.try_recv().is_err());
+    }
+}
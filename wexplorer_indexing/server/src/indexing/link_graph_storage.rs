@@ -0,0 +1,323 @@
+use std::sync::Arc;
+
+use rusqlite::{params, OptionalExtension, TransactionBehavior};
+use sea_query::{Table, Iden, ColumnDef, Query, SimpleExpr, Expr, Index, Order};
+use url::Url;
+
+use super::{SqliteSchemaStatementBuilder, SqliteQueryStatementWriter, IndexedLinks, SqlitePool};
+
+// Link types which don't pass the authority of the linking page to the linked one.
+const UNENDORSED_LINK_TYPES: [&str; 3] = ["nofollow", "sponsored", "ugc"];
+
+// Popular pages may be linked from thousands of pages mostly with the same few texts, the most frequent are kept.
+const MAX_INBOUND_ANCHOR_TEXTS: u64 = 100;
+
+#[derive(Iden)]
+pub enum LinkEdges {
+    Table,
+    SourceUrl,
+    TargetUrl,
+    AnchorText,
+    Rel,
+    IsEndorsed,
+}
+
+#[derive(Iden)]
+enum PageRanks {
+    Table,
+    Url,
+    Score,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkEdge {
+    pub target_url: Url,
+    pub anchor_text: String,
+    // Value of the rel attribute of the link.
+    pub rel: Option<String>,
+}
+
+impl LinkEdge {
+    pub fn is_endorsed(&self) -> bool {
+        is_endorsing_rel(self.rel.as_deref())
+    }
+}
+
+pub fn is_endorsing_rel(rel: Option<&str>) -> bool {
+    rel.unwrap_or("").split_ascii_whitespace()
+        .all(|r| !UNENDORSED_LINK_TYPES.iter().any(|t| r.eq_ignore_ascii_case(t)))
+}
+
+pub struct LinkGraphStorage {
+    pool: Arc<SqlitePool>,
+    delete_links_sql: String,
+    add_link_sql: String,
+    get_links_sql: String,
+    get_inbound_anchor_texts_sql: String,
+    delete_page_ranks_sql: String,
+    add_page_rank_sql: String,
+    get_page_rank_sql: String,
+    get_indexed_page_ranks_sql: String,
+}
+
+impl LinkGraphStorage {
+    pub fn new(pool: Arc<SqlitePool>) -> Result<Self, rusqlite::Error> {
+        let create_edges_table_sql = Table::create()
+            .table(LinkEdges::Table)
+            .if_not_exists()
+            .col(ColumnDef::new(LinkEdges::SourceUrl).text().not_null())
+            .col(ColumnDef::new(LinkEdges::TargetUrl).text().not_null())
+            .col(ColumnDef::new(LinkEdges::AnchorText).text().not_null())
+            .col(ColumnDef::new(LinkEdges::Rel).text().null())
+            .col(ColumnDef::new(LinkEdges::IsEndorsed).boolean().not_null().default(true))
+            .to_sqlite_string();
+        let create_source_index_sql = Index::create()
+            .name("IX_LinkEdges_SourceUrl")
+            .table(LinkEdges::Table)
+            .if_not_exists()
+            .col(LinkEdges::SourceUrl)
+            .to_sqlite_string();
+        let create_target_index_sql = Index::create()
+            .name("IX_LinkEdges_TargetUrl")
+            .table(LinkEdges::Table)
+            .if_not_exists()
+            .col(LinkEdges::TargetUrl)
+            .to_sqlite_string();
+        let create_page_ranks_table_sql = Table::create()
+            .table(PageRanks::Table)
+            .if_not_exists()
+            .col(ColumnDef::new(PageRanks::Url).text().not_null().primary_key())
+            .col(ColumnDef::new(PageRanks::Score).double().not_null())
+            .to_sqlite_string();
+        pool.run_blocking(|connection| {
+            connection.execute(&create_edges_table_sql, ())?;
+            connection.execute(&create_source_index_sql, ())?;
+            connection.execute(&create_target_index_sql, ())?;
+            connection.execute(&create_page_ranks_table_sql, ())
+        })?;
+
+        let delete_links_sql = Query::delete()
+            .from_table(LinkEdges::Table)
+            .and_where(Expr::col(LinkEdges::SourceUrl).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        let add_link_sql = Query::insert()
+            .into_table(LinkEdges::Table)
+            .columns([
+                LinkEdges::SourceUrl,
+                LinkEdges::TargetUrl,
+                LinkEdges::AnchorText,
+                LinkEdges::Rel,
+                LinkEdges::IsEndorsed,
+            ])
+            .values_panic([
+                SimpleExpr::Custom("?1".to_string()),
+                SimpleExpr::Custom("?2".to_string()),
+                SimpleExpr::Custom("?3".to_string()),
+                SimpleExpr::Custom("?4".to_string()),
+                SimpleExpr::Custom("?5".to_string()),
+            ])
+            .to_sqlite_string();
+
+        let get_links_sql = Query::select()
+            .columns([LinkEdges::SourceUrl, LinkEdges::TargetUrl, LinkEdges::AnchorText, LinkEdges::Rel])
+            .from(LinkEdges::Table)
+            .to_sqlite_string();
+
+        let get_inbound_anchor_texts_sql = Query::select()
+            .column(LinkEdges::AnchorText)
+            .from(LinkEdges::Table)
+            .and_where(Expr::col(LinkEdges::TargetUrl).eq(SimpleExpr::Custom("?1".to_string())))
+            .and_where(Expr::col(LinkEdges::SourceUrl).ne(SimpleExpr::Custom("?1".to_string())))
+            .and_where(Expr::col(LinkEdges::AnchorText).ne(""))
+            .and_where(Expr::col(LinkEdges::IsEndorsed).eq(true))
+            .group_by_col(LinkEdges::AnchorText)
+            .order_by_expr(Expr::col(LinkEdges::AnchorText).count(), Order::Desc)
+            .order_by(LinkEdges::AnchorText, Order::Asc)
+            .limit(MAX_INBOUND_ANCHOR_TEXTS)
+            .to_sqlite_string();
+
+        let delete_page_ranks_sql = Query::delete()
+            .from_table(PageRanks::Table)
+            .to_sqlite_string();
+
+        let add_page_rank_sql = Query::insert()
+            .into_table(PageRanks::Table)
+            .columns([PageRanks::Url, PageRanks::Score])
+            .values_panic([SimpleExpr::Custom("?1".to_string()), SimpleExpr::Custom("?2".to_string())])
+            .to_sqlite_string();
+
+        let get_page_rank_sql = Query::select()
+            .column(PageRanks::Score)
+            .from(PageRanks::Table)
+            .and_where(Expr::col(PageRanks::Url).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        // Only pages which were sent to the searching service have documents to update.
+        let get_indexed_page_ranks_sql = Query::select()
+            .columns([(PageRanks::Table, PageRanks::Url), (PageRanks::Table, PageRanks::Score)])
+            .from(PageRanks::Table)
+            .inner_join(
+                IndexedLinks::Table,
+                Expr::col((IndexedLinks::Table, IndexedLinks::Url)).equals((PageRanks::Table, PageRanks::Url)))
+            .and_where(Expr::col((IndexedLinks::Table, IndexedLinks::DocumentHash)).is_not_null())
+            .to_sqlite_string();
+
+        Ok(Self {
+            pool,
+            delete_links_sql,
+            add_link_sql,
+            get_links_sql,
+            get_inbound_anchor_texts_sql,
+            delete_page_ranks_sql,
+            add_page_rank_sql,
+            get_page_rank_sql,
+            get_indexed_page_ranks_sql,
+        })
+    }
+
+    // Replaces the outgoing links of the page found during its previous indexing.
+    pub async fn set_links(&self, source_url: &Url, links: &[LinkEdge]) -> Result<(), rusqlite::Error> {
+        let (delete_links_sql, add_link_sql) = (self.delete_links_sql.clone(), self.add_link_sql.clone());
+        let (source_url, links) = (source_url.clone(), links.to_vec());
+        self.pool
+            .run(move |connection| {
+                let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+                transaction.execute(&delete_links_sql, [&source_url])?;
+                {
+                    let mut statement = transaction.prepare_cached(&add_link_sql)?;
+                    for link in &links {
+                        let is_endorsed = link.is_endorsed();
+                        statement.execute(
+                            params![source_url, link.target_url, link.anchor_text, link.rel, is_endorsed])?;
+                    }
+                }
+
+                transaction.commit()
+            })
+            .await
+    }
+
+    pub async fn get_links(&self) -> Result<Vec<(Url, LinkEdge)>, rusqlite::Error> {
+        let sql = self.get_links_sql.clone();
+        self.pool
+            .run(move |connection| {
+                let mut statement = connection.prepare(&sql)?;
+                let links = statement
+                    .query_map((), |row| Ok((
+                        row.get(0)?,
+                        LinkEdge { target_url: row.get(1)?, anchor_text: row.get(2)?, rel: row.get(3)? },
+                    )))?
+                    .collect();
+                links
+            })
+            .await
+    }
+
+    // Distinct texts of endorsed links to the page from other pages, the most frequent first.
+    pub async fn get_inbound_anchor_texts(&self, url: &Url) -> Result<Vec<String>, rusqlite::Error> {
+        let (sql, url) = (self.get_inbound_anchor_texts_sql.clone(), url.clone());
+        self.pool
+            .run(move |connection| {
+                let mut statement = connection.prepare_cached(&sql)?;
+                let anchor_texts = statement.query_map([&url], |row| row.get(0))?.collect();
+                anchor_texts
+            })
+            .await
+    }
+
+    pub async fn set_page_ranks<'a>(&self, page_ranks: impl IntoIterator<Item = (&'a Url, f64)>)
+        -> Result<(), rusqlite::Error>
+    {
+        let (delete_page_ranks_sql, add_page_rank_sql) =
+            (self.delete_page_ranks_sql.clone(), self.add_page_rank_sql.clone());
+        let page_ranks = page_ranks.into_iter().map(|(url, score)| (url.clone(), score)).collect::<Vec<_>>();
+        self.pool
+            .run(move |connection| {
+                let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+                transaction.execute(&delete_page_ranks_sql, ())?;
+                {
+                    let mut statement = transaction.prepare_cached(&add_page_rank_sql)?;
+                    for (url, score) in &page_ranks {
+                        statement.execute(params![url, score])?;
+                    }
+                }
+
+                transaction.commit()
+            })
+            .await
+    }
+
+    pub async fn get_page_rank(&self, url: &Url) -> Result<Option<f64>, rusqlite::Error> {
+        let (sql, url) = (self.get_page_rank_sql.clone(), url.clone());
+        self.pool.run(move |connection| connection.query_row(&sql, [&url], |row| row.get(0)).optional()).await
+    }
+
+    pub async fn get_indexed_page_ranks(&self) -> Result<Vec<(Url, f64)>, rusqlite::Error> {
+        let sql = self.get_indexed_page_ranks_sql.clone();
+        self.pool
+            .run(move |connection| {
+                let mut statement = connection.prepare(&sql)?;
+                let page_ranks = statement.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+                page_ranks
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod link_graph_storage_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_replace_links_of_reindexed_page() {
+        // Arrange
+
+        let target = LinkGraphStorage::new(Arc::new(SqlitePool::open_in_memory().unwrap())).unwrap();
+        let source_url = Url::parse("https://localhost/").unwrap();
+        let link = |url: &str, rel: Option<&str>| LinkEdge {
+            target_url: Url::parse(url).unwrap(),
+            anchor_text: "Link".to_string(),
+            rel: rel.map(|r| r.to_string()),
+        };
+        target.set_links(&source_url, &[link("https://localhost/old", None)]).await.unwrap();
+        let links = vec![link("https://localhost/a", None), link("https://localhost/b", Some("external NoFollow"))];
+
+        // Act
+
+        target.set_links(&source_url, &links).await.unwrap();
+        let result = target.get_links().await.unwrap();
+
+        // Assert
+
+        assert_eq!(links.into_iter().map(|l| (source_url.clone(), l)).collect::<Vec<_>>(), result);
+        assert!(result[0].1.is_endorsed());
+        assert!(!result[1].1.is_endorsed());
+    }
+
+    #[tokio::test]
+    async fn should_get_most_frequent_anchor_texts_of_endorsed_inbound_links() {
+        // Arrange
+
+        let target = LinkGraphStorage::new(Arc::new(SqlitePool::open_in_memory().unwrap())).unwrap();
+        let url = Url::parse("https://localhost/docs").unwrap();
+        let link = |anchor_text: &str, rel: Option<&str>| LinkEdge {
+            target_url: url.clone(),
+            anchor_text: anchor_text.to_string(),
+            rel: rel.map(|r| r.to_string()),
+        };
+        let source_url = |path| url.join(path).unwrap();
+        target.set_links(&source_url("/"), &[link("Docs", None), link("", None)]).await.unwrap();
+        target.set_links(&source_url("/a"), &[link("API", None), link("Docs", None)]).await.unwrap();
+        target.set_links(&source_url("/b"), &[link("Spam", Some("ugc"))]).await.unwrap();
+        target.set_links(&url, &[link("Self", None)]).await.unwrap();
+
+        // Act
+
+        let result = target.get_inbound_anchor_texts(&url).await.unwrap();
+
+        // Assert
+
+        assert_eq!(vec!["Docs".to_string(), "API".to_string()], result);
+    }
+}
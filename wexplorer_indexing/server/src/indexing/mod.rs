@@ -1,12 +1,48 @@
 mod indexer;
 mod url_processing;
+mod url_processing_settings;
 mod text_extracting;
+mod text_formatting;
 mod indexed_links_storage;
+mod robots_txt;
+mod robots_txt_cache;
+mod sitemap;
+mod recrawl;
+mod crawl_scope;
+mod web_site_storage;
+mod indexing_events;
+mod page_directives;
+mod content_fingerprint;
+mod link_graph_storage;
+mod page_rank;
+mod crawler_traps;
+mod fetching;
+mod searching_service;
+mod sqlite_pool;
+mod schema_migrations;
 
 pub use indexer::*;
 pub use url_processing::*;
+pub use url_processing_settings::*;
 pub use text_extracting::*;
+pub use text_formatting::*;
 pub use indexed_links_storage::*;
+pub use robots_txt::*;
+pub use robots_txt_cache::*;
+pub use sitemap::*;
+pub use recrawl::*;
+pub use crawl_scope::*;
+pub use web_site_storage::*;
+pub use indexing_events::*;
+pub use page_directives::*;
+pub use content_fingerprint::*;
+pub use link_graph_storage::*;
+pub use page_rank::*;
+pub use crawler_traps::*;
+pub use fetching::*;
+pub use searching_service::*;
+pub use sqlite_pool::*;
+pub use schema_migrations::*;
 
 use sea_query::{SchemaStatementBuilder, SqliteQueryBuilder, QueryStatementWriter};
 
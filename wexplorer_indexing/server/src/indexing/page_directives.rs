@@ -0,0 +1,179 @@
+use scraper::{Html, Selector, ElementRef};
+use url::Url;
+
+use super::{UrlProcessor, CRAWLER_USER_AGENT};
+
+pub const X_ROBOTS_TAG: &str = "x-robots-tag";
+
+// Directives which can be written as "name: value" and so are not confused with a user agent prefix.
+const DIRECTIVES_WITH_VALUE: [&str; 4] = ["unavailable_after", "max-snippet", "max-image-preview", "max-video-preview"];
+
+// Which indexing instructions of pages are followed when a web site is crawled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageDirectiveSettings {
+    pub respect_canonical: bool,
+    pub respect_meta_robots: bool,
+    pub respect_x_robots_tag: bool,
+    pub respect_nofollow: bool,
+}
+
+impl Default for PageDirectiveSettings {
+    fn default() -> Self {
+        Self { respect_canonical: true, respect_meta_robots: true, respect_x_robots_tag: true, respect_nofollow: true }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RobotsDirectives {
+    pub noindex: bool,
+    pub nofollow: bool,
+}
+
+impl RobotsDirectives {
+    // Parses a value of a robots meta tag or an X-Robots-Tag header, e.g. "noindex, nofollow" or "wexplorer: none".
+    // Values addressed to other user agents are ignored.
+    pub fn parse(value: &str) -> Self {
+        let mut directives = value;
+        if let Some((prefix, rest)) = value.split_once(':') {
+            let prefix = prefix.trim();
+            let is_user_agent = !prefix.contains(',')
+                && !DIRECTIVES_WITH_VALUE.iter().any(|d| prefix.eq_ignore_ascii_case(d));
+            if is_user_agent {
+                if !prefix.eq_ignore_ascii_case(CRAWLER_USER_AGENT) {
+                    return Self::default();
+                }
+
+                directives = rest;
+            }
+        }
+
+        directives.split(',')
+            .map(|d| d.trim())
+            .fold(Self::default(), |result, directive| {
+                let is_none = directive.eq_ignore_ascii_case("none");
+                Self {
+                    noindex: result.noindex || is_none || directive.eq_ignore_ascii_case("noindex"),
+                    nofollow: result.nofollow || is_none || directive.eq_ignore_ascii_case("nofollow"),
+                }
+            })
+    }
+
+    pub fn merge(self, other: Self) -> Self {
+        Self { noindex: self.noindex || other.noindex, nofollow: self.nofollow || other.nofollow }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PageDirectives {
+    pub robots: RobotsDirectives,
+    pub canonical_url: Option<Url>,
+}
+
+pub struct PageDirectivesExtractor {
+    meta_selector: Selector,
+    link_selector: Selector,
+}
+
+impl PageDirectivesExtractor {
+    pub fn new() -> Self {
+        Self {
+            meta_selector: Selector::parse("meta[name][content]").unwrap(),
+            link_selector: Selector::parse("link[rel][href]").unwrap(),
+        }
+    }
+
+    // Combines the directives of the X-Robots-Tag header with the ones of the page which are enabled for the site.
+    pub fn extract(
+        &self, html: &Html, base_url: &Url, header_directives: RobotsDirectives, settings: &PageDirectiveSettings,
+        url_processor: &impl UrlProcessor)
+        -> PageDirectives
+    {
+        let mut robots = if settings.respect_x_robots_tag { header_directives } else { RobotsDirectives::default() };
+        if settings.respect_meta_robots {
+            robots = html.select(&self.meta_selector)
+                .filter(|m| m.value().attr("name")
+                    .is_some_and(|n| n.eq_ignore_ascii_case("robots") || n.eq_ignore_ascii_case(CRAWLER_USER_AGENT)))
+                .map(|m| RobotsDirectives::parse(m.value().attr("content").unwrap_or("")))
+                .fold(robots, RobotsDirectives::merge);
+        }
+
+        let canonical_url = if settings.respect_canonical {
+            html.select(&self.link_selector)
+                .find(|l| has_rel(l, "canonical"))
+                .and_then(|l| url_processor.parse_url(base_url, l.value().attr("href").unwrap_or("")))
+        }
+        else {
+            None
+        };
+
+        PageDirectives { robots, canonical_url }
+    }
+}
+
+pub fn is_nofollow_link(link: &ElementRef, settings: &PageDirectiveSettings) -> bool {
+    settings.respect_nofollow && has_rel(link, "nofollow")
+}
+
+fn has_rel(element: &ElementRef, rel: &str) -> bool {
+    element.value().attr("rel").is_some_and(|r| r.split_ascii_whitespace().any(|r| r.eq_ignore_ascii_case(rel)))
+}
+
+#[cfg(test)]
+mod page_directives_tests {
+    use super::*;
+    use crate::indexing::{UrlProcessorImpl, AllowedSchemeUrlFilter, NoopNormalizer};
+
+    #[test]
+    fn should_parse_robots_directives_for_crawler() {
+        // Act
+
+        let result1 = RobotsDirectives::parse("NoIndex, follow");
+        let result2 = RobotsDirectives::parse("none");
+        let result3 = RobotsDirectives::parse("googlebot: noindex, nofollow");
+        let result4 = RobotsDirectives::parse("wexplorer: nofollow");
+        let result5 = RobotsDirectives::parse("unavailable_after: 25 Jun 2010 15:00:00 PST, noindex");
+
+        // Assert
+
+        assert_eq!(RobotsDirectives { noindex: true, nofollow: false }, result1);
+        assert_eq!(RobotsDirectives { noindex: true, nofollow: true }, result2);
+        assert_eq!(RobotsDirectives::default(), result3);
+        assert_eq!(RobotsDirectives { noindex: false, nofollow: true }, result4);
+        assert_eq!(RobotsDirectives { noindex: true, nofollow: false }, result5);
+    }
+
+    #[test]
+    fn should_extract_only_enabled_directives() {
+        // Arrange
+
+        let html = Html::parse_document(r#"<html><head>
+            <meta name="robots" content="noindex">
+            <meta name="description" content="nofollow">
+            <link rel="Alternate Canonical" href="/page?id=1">
+            </head><body></body></html>"#);
+        let base_url = Url::parse("https://localhost/page?id=1&sort=asc").unwrap();
+        let url_processor = UrlProcessorImpl::new(
+            AllowedSchemeUrlFilter::new(vec!["https".to_string()]), NoopNormalizer {});
+        let header_directives = RobotsDirectives { noindex: false, nofollow: true };
+        let target = PageDirectivesExtractor::new();
+
+        // Act
+
+        let result1 = target.extract(
+            &html, &base_url, header_directives, &PageDirectiveSettings::default(), &url_processor);
+        let result2 = target.extract(
+            &html, &base_url, header_directives,
+            &PageDirectiveSettings { respect_canonical: false, respect_meta_robots: false, ..Default::default() },
+            &url_processor);
+
+        // Assert
+
+        assert_eq!(
+            PageDirectives {
+                robots: RobotsDirectives { noindex: true, nofollow: true },
+                canonical_url: Some(Url::parse("https://localhost/page?id=1").unwrap()),
+            },
+            result1);
+        assert_eq!(PageDirectives { robots: header_directives, canonical_url: None }, result2);
+    }
+}
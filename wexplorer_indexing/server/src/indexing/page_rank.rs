@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use serde::Deserialize;
+use url::Url;
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageRankSettings {
+    pub update_interval_secs: u64,
+    // Probability of following a link of the page instead of jumping to a random page.
+    pub damping_factor: f64,
+    pub iterations: u32,
+}
+
+// Scores are scaled by the count of pages, so an average page has the score of 1 regardless of the graph size.
+pub fn compute_page_ranks<'a>(
+    links: impl IntoIterator<Item = (&'a Url, &'a Url)>, settings: &PageRankSettings)
+    -> HashMap<Url, f64>
+{
+    let mut pages = HashMap::<&Url, usize>::new();
+    let mut page_index = |url| {
+        let next_index = pages.len();
+        *pages.entry(url).or_insert(next_index)
+    };
+    let links = links.into_iter()
+        .map(|(source, target)| (page_index(source), page_index(target)))
+        .filter(|(source, target)| source != target)
+        .unique()
+        .collect::<Vec<_>>();
+
+    let pages_count = pages.len();
+    let mut out_counts = vec![0; pages_count];
+    for (source, _) in &links {
+        out_counts[*source] += 1;
+    }
+
+    let damping_factor = settings.damping_factor;
+    let mut ranks = vec![1.0 / pages_count as f64; pages_count];
+    for _ in 0..settings.iterations {
+        // Pages without links are considered to link to every page.
+        let dangling_rank = ranks.iter().zip(&out_counts).filter(|(_, c)| **c == 0).map(|(r, _)| r).sum::<f64>();
+        let base_rank = (1.0 - damping_factor + damping_factor * dangling_rank) / pages_count as f64;
+        let mut next_ranks = vec![base_rank; pages_count];
+        for (source, target) in &links {
+            next_ranks[*target] += damping_factor * ranks[*source] / out_counts[*source] as f64;
+        }
+
+        ranks = next_ranks;
+    }
+
+    pages.into_iter().map(|(url, index)| (url.clone(), ranks[index] * pages_count as f64)).collect()
+}
+
+#[cfg(test)]
+mod page_rank_tests {
+    use super::*;
+
+    #[test]
+    fn should_give_higher_score_to_pages_with_more_inbound_links() {
+        // Arrange
+
+        let urls = ["home", "a", "b", "c"].map(|p| Url::parse("https://localhost/").unwrap().join(p).unwrap());
+        let [home, a, b, c] = &urls;
+        let links = [(a, home), (b, home), (c, home), (home, a), (a, a), (a, home)];
+        let settings = PageRankSettings { update_interval_secs: 60, damping_factor: 0.85, iterations: 50 };
+
+        // Act
+
+        let result = compute_page_ranks(links, &settings);
+
+        // Assert
+
+        assert_eq!(4, result.len());
+        assert!((result.values().sum::<f64>() - 4.0).abs() < 1e-6);
+        assert!(result[home] > result[a]);
+        assert!(result[a] > result[b]);
+        assert!((result[b] - result[c]).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc, Duration};
+use serde::Deserialize;
+use thiserror::Error;
+use url::Url;
+
+// Longer max ages can't be added to times of last indexing.
+const MAX_MAX_AGE_HOURS: i64 = 100 * 365 * 24;
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecrawlSettings {
+    pub default_max_age_hours: i64,
+    pub check_interval_secs: u64,
+    #[serde(default)]
+    pub sites: Vec<SiteRecrawlSettings>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteRecrawlSettings {
+    pub origin: String,
+    pub max_age_hours: i64,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RecrawlSettingsError {
+    #[error("max age must be between 1 and {} hours", MAX_MAX_AGE_HOURS)]
+    InvalidMaxAge,
+    #[error("max age of web site {0} must be between 1 and {} hours", MAX_MAX_AGE_HOURS)]
+    InvalidSiteMaxAge(String),
+    #[error("check interval must be greater than zero")]
+    ZeroCheckInterval,
+}
+
+impl RecrawlSettings {
+    pub fn validate(&self) -> Result<(), RecrawlSettingsError> {
+        let is_valid_max_age = |hours| (1..=MAX_MAX_AGE_HOURS).contains(&hours);
+        if !is_valid_max_age(self.default_max_age_hours) {
+            return Err(RecrawlSettingsError::InvalidMaxAge);
+        }
+        if let Some(site) = self.sites.iter().find(|s| !is_valid_max_age(s.max_age_hours)) {
+            return Err(RecrawlSettingsError::InvalidSiteMaxAge(site.origin.clone()));
+        }
+        if self.check_interval_secs == 0 {
+            return Err(RecrawlSettingsError::ZeroCheckInterval);
+        }
+        Ok(())
+    }
+}
+
+pub struct RecrawlPolicy {
+    default_max_age: Duration,
+    site_max_ages: HashMap<String, Duration>,
+    check_interval: std::time::Duration,
+}
+
+impl RecrawlPolicy {
+    pub fn new(settings: RecrawlSettings) -> Self {
+        Self {
+            default_max_age: Duration::hours(settings.default_max_age_hours),
+            site_max_ages: settings.sites.into_iter()
+                .map(|s| {
+                    let origin = Url::parse(&s.origin).map(|u| u.origin().ascii_serialization()).unwrap_or(s.origin);
+                    (origin, Duration::hours(s.max_age_hours))
+                })
+                .collect(),
+            check_interval: std::time::Duration::from_secs(settings.check_interval_secs),
+        }
+    }
+
+    pub fn default_max_age(&self) -> Duration {
+        self.default_max_age
+    }
+
+    pub fn site_max_ages(&self) -> impl Iterator<Item = (&str, Duration)> {
+        self.site_max_ages.iter().map(|(origin, max_age)| (origin.as_str(), *max_age))
+    }
+
+    pub fn check_interval(&self) -> std::time::Duration {
+        self.check_interval
+    }
+
+    pub fn max_age(&self, url: &Url) -> Duration {
+        self.site_max_ages.get(&url.origin().ascii_serialization()).copied().unwrap_or(self.default_max_age)
+    }
+
+    pub fn is_outdated(&self, url: &Url, last_indexed_time: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        last_indexed_time + self.max_age(url) < now
+    }
+}
+
+#[cfg(test)]
+mod recrawl_policy_tests {
+    use super::*;
+
+    #[test]
+    fn should_use_site_max_age_if_configured() {
+        // Arrange
+
+        let target = RecrawlPolicy::new(RecrawlSettings {
+            default_max_age_hours: 24,
+            check_interval_secs: 60,
+            sites: vec![SiteRecrawlSettings { origin: "https://localhost/".to_string(), max_age_hours: 1 }],
+        });
+
+        // Act
+
+        let result1 = target.max_age(&Url::parse("https://localhost/page").unwrap());
+        let result2 = target.max_age(&Url::parse("https://localhost:8080/page").unwrap());
+
+        // Assert
+
+        assert_eq!(Duration::hours(1), result1);
+        assert_eq!(Duration::hours(24), result2);
+    }
+
+    #[test]
+    fn should_detect_outdated_pages() {
+        // Arrange
+
+        let target = RecrawlPolicy::new(RecrawlSettings { default_max_age_hours: 24, check_interval_secs: 60, sites: vec![] });
+        let url = Url::parse("https://localhost/page").unwrap();
+        let now = Utc::now();
+
+        // Act
+
+        let result1 = target.is_outdated(&url, now - Duration::hours(25), now);
+        let result2 = target.is_outdated(&url, now - Duration::hours(23), now);
+
+        // Assert
+
+        assert!(result1);
+        assert!(!result2);
+    }
+
+    #[test]
+    fn should_reject_invalid_settings() {
+        // Arrange
+
+        let create_settings = |default_max_age_hours, site_max_age_hours, check_interval_secs| RecrawlSettings {
+            default_max_age_hours,
+            check_interval_secs,
+            sites: vec![
+                SiteRecrawlSettings { origin: "https://localhost".to_string(), max_age_hours: site_max_age_hours },
+            ],
+        };
+
+        // Act
+
+        let results = [
+            create_settings(24, 1, 60), create_settings(0, 1, 60), create_settings(i64::MAX, 1, 60),
+            create_settings(24, -1, 60), create_settings(24, 1, 0),
+        ].map(|s| s.validate().err());
+
+        // Assert
+
+        assert_eq!(
+            [
+                None,
+                Some(RecrawlSettingsError::InvalidMaxAge),
+                Some(RecrawlSettingsError::InvalidMaxAge),
+                Some(RecrawlSettingsError::InvalidSiteMaxAge("https://localhost".to_string())),
+                Some(RecrawlSettingsError::ZeroCheckInterval),
+            ],
+            results);
+    }
+}
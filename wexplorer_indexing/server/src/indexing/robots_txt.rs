@@ -0,0 +1,321 @@
+use std::time::Duration;
+
+use url::Url;
+
+// Longer crawl delays would stop crawling of hosts, so they are limited.
+const MAX_CRAWL_DELAY: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Default)]
+pub struct RobotsTxt {
+    groups: Vec<RobotsTxtGroup>,
+    sitemaps: Vec<Url>,
+}
+
+#[derive(Clone, Default)]
+struct RobotsTxtGroup {
+    user_agents: Vec<String>,
+    rules: Vec<RobotsTxtRule>,
+    crawl_delay: Option<Duration>,
+}
+
+#[derive(Clone)]
+struct RobotsTxtRule {
+    allow: bool,
+    pattern: String,
+}
+
+impl RobotsTxt {
+    pub fn parse(content: &str) -> Self {
+        let mut groups: Vec<RobotsTxtGroup> = Vec::new();
+        let mut sitemaps = Vec::new();
+        let mut current_group: Option<RobotsTxtGroup> = None;
+        let mut is_previous_line_user_agent = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else { continue; };
+            let value = value.trim();
+
+            let key = key.trim().to_ascii_lowercase();
+            let is_user_agent = key == "user-agent";
+
+            match key.as_str() {
+                "user-agent" => {
+                    // Consecutive user-agent lines share the same group,
+                    // a user-agent line after other directives starts a new one.
+                    if !is_previous_line_user_agent {
+                        groups.extend(current_group.take());
+                    }
+
+                    current_group.get_or_insert_with(RobotsTxtGroup::default)
+                        .user_agents
+                        .push(value.to_ascii_lowercase());
+                },
+                directive @ ("allow" | "disallow") => {
+                    let Some(group) = current_group.as_mut() else { continue; };
+                    // An empty "Disallow:" doesn't restrict anything, so it's ignored as well as an empty "Allow:".
+                    if !value.is_empty() {
+                        group.rules.push(RobotsTxtRule { allow: directive == "allow", pattern: value.to_string() });
+                    }
+                },
+                "crawl-delay" => {
+                    let Some(group) = current_group.as_mut() else { continue; };
+                    group.crawl_delay = value.parse::<f64>().ok()
+                        .filter(|d| *d >= 0.0)
+                        .map(|d| Duration::from_secs_f64(d.min(MAX_CRAWL_DELAY.as_secs_f64())));
+                },
+                // Sitemaps don't belong to any group.
+                "sitemap" => sitemaps.extend(Url::parse(value).ok()),
+                _ => {},
+            }
+
+            is_previous_line_user_agent = is_user_agent;
+        }
+
+        groups.extend(current_group);
+
+        Self { groups, sitemaps }
+    }
+
+    pub fn is_allowed(&self, user_agent: &str, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        if path == "/robots.txt" {
+            return true;
+        }
+
+        self.get_matching_rules(user_agent)
+            .filter(|r| pattern_matches(&r.pattern, &path))
+            .max_by(|r1, r2| r1.pattern.len().cmp(&r2.pattern.len()).then(r1.allow.cmp(&r2.allow)))
+            .map(|r| r.allow)
+            .unwrap_or(true)
+    }
+
+    pub fn sitemaps(&self) -> &[Url] {
+        &self.sitemaps
+    }
+
+    pub fn crawl_delay(&self, user_agent: &str) -> Option<Duration> {
+        self.get_matching_groups(user_agent).filter_map(|g| g.crawl_delay).max()
+    }
+
+    fn get_matching_groups<'a>(&'a self, user_agent: &str) -> impl Iterator<Item = &'a RobotsTxtGroup> {
+        let user_agent = user_agent.to_ascii_lowercase();
+        let matched_agent = self.groups.iter()
+            .flat_map(|g| g.user_agents.iter())
+            .filter(|a| a.as_str() != "*" && user_agent.starts_with(a.as_str()))
+            .max_by_key(|a| a.len())
+            .cloned()
+            .unwrap_or_else(|| "*".to_string());
+
+        self.groups.iter().filter(move |g| g.user_agents.contains(&matched_agent))
+    }
+
+    fn get_matching_rules<'a>(&'a self, user_agent: &str) -> impl Iterator<Item = &'a RobotsTxtRule> {
+        self.get_matching_groups(user_agent).flat_map(|g| g.rules.iter())
+    }
+}
+
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first_part = parts.next().unwrap_or("");
+    if !path.starts_with(first_part) {
+        return false;
+    }
+
+    let mut position = first_part.len();
+    let mut parts = parts.collect::<Vec<_>>();
+    let last_part = if anchored { parts.pop() } else { None };
+
+    for part in parts {
+        match path[position..].find(part) {
+            Some(index) => position += index + part.len(),
+            None => return false,
+        }
+    }
+
+    match last_part {
+        Some(last_part) => path.len() - position >= last_part.len() && path.ends_with(last_part),
+        None => !anchored || position == path.len(),
+    }
+}
+
+#[cfg(test)]
+mod robots_txt_tests {
+    use super::*;
+
+    fn url(path: &str) -> Url {
+        Url::parse("https://localhost").unwrap().join(path).unwrap()
+    }
+
+    #[test]
+    fn should_allow_everything_for_empty_robots_txt() {
+        // Arrange
+
+        let target = RobotsTxt::parse("");
+
+        // Act
+
+        let result = target.is_allowed("wexplorer", &url("/path"));
+
+        // Assert
+
+        assert!(result);
+    }
+
+    #[test]
+    fn should_apply_longest_matching_rule() {
+        // Arrange
+
+        let target = RobotsTxt::parse("User-agent: *\nDisallow: /private\nAllow: /private/public\n");
+
+        // Act
+
+        let result1 = target.is_allowed("wexplorer", &url("/private/page"));
+        let result2 = target.is_allowed("wexplorer", &url("/private/public/page"));
+        let result3 = target.is_allowed("wexplorer", &url("/page"));
+
+        // Assert
+
+        assert!(!result1);
+        assert!(result2);
+        assert!(result3);
+    }
+
+    #[test]
+    fn should_prefer_allow_rule_if_rules_have_same_length() {
+        // Arrange
+
+        let target = RobotsTxt::parse("User-agent: *\nDisallow: /page\nAllow: /page\n");
+
+        // Act
+
+        let result = target.is_allowed("wexplorer", &url("/page"));
+
+        // Assert
+
+        assert!(result);
+    }
+
+    #[test]
+    fn should_support_wildcards_and_end_anchors() {
+        // Arrange
+
+        let target = RobotsTxt::parse("User-agent: *\nDisallow: /*.pdf$\nDisallow: /*?session=\n");
+
+        // Act
+
+        let result1 = target.is_allowed("wexplorer", &url("/docs/file.pdf"));
+        let result2 = target.is_allowed("wexplorer", &url("/docs/file.pdf.html"));
+        let result3 = target.is_allowed("wexplorer", &url("/page?session=1"));
+        let result4 = target.is_allowed("wexplorer", &url("/page?id=1"));
+
+        // Assert
+
+        assert!(!result1);
+        assert!(result2);
+        assert!(!result3);
+        assert!(result4);
+    }
+
+    #[test]
+    fn should_use_most_specific_user_agent_group() {
+        // Arrange
+
+        let target = RobotsTxt::parse(
+            "User-agent: *\nDisallow: /\n\nUser-agent: WExplorer\nUser-agent: other\nDisallow: /private # comment\n");
+
+        // Act
+
+        let result1 = target.is_allowed("wexplorer", &url("/page"));
+        let result2 = target.is_allowed("wexplorer", &url("/private"));
+        let result3 = target.is_allowed("somebot", &url("/page"));
+
+        // Assert
+
+        assert!(result1);
+        assert!(!result2);
+        assert!(!result3);
+    }
+
+    #[test]
+    fn should_parse_crawl_delay_of_matching_group() {
+        // Arrange
+
+        let target = RobotsTxt::parse("User-agent: *\nCrawl-delay: 10\n\nUser-agent: wexplorer\nCrawl-delay: 0.5\n");
+
+        // Act
+
+        let result1 = target.crawl_delay("wexplorer");
+        let result2 = target.crawl_delay("somebot");
+
+        // Assert
+
+        assert_eq!(Some(Duration::from_millis(500)), result1);
+        assert_eq!(Some(Duration::from_secs(10)), result2);
+    }
+
+    #[test]
+    fn should_limit_crawl_delay() {
+        // Arrange
+
+        let target = RobotsTxt::parse("User-agent: *\nCrawl-delay: 1e300\n\nUser-agent: wexplorer\nCrawl-delay: -1\n");
+
+        // Act
+
+        let result1 = target.crawl_delay("somebot");
+        let result2 = target.crawl_delay("wexplorer");
+
+        // Assert
+
+        assert_eq!(Some(MAX_CRAWL_DELAY), result1);
+        assert_eq!(None, result2);
+    }
+
+    #[test]
+    fn should_parse_sitemaps_outside_of_groups() {
+        // Arrange
+
+        let target = RobotsTxt::parse(concat!(
+            "Sitemap: https://localhost/sitemap1.xml\nUser-agent: *\nDisallow: /private\n",
+            "Sitemap: https://localhost/sitemap2.xml\nSitemap: invalid\n"));
+
+        // Act
+
+        let result = target.sitemaps();
+
+        // Assert
+
+        assert_eq!(
+            &[
+                Url::parse("https://localhost/sitemap1.xml").unwrap(),
+                Url::parse("https://localhost/sitemap2.xml").unwrap(),
+            ],
+            result);
+    }
+
+    #[test]
+    fn should_always_allow_robots_txt() {
+        // Arrange
+
+        let target = RobotsTxt::parse("User-agent: *\nDisallow: /");
+
+        // Act
+
+        let result1 = target.is_allowed("wexplorer", &url("/robots.txt"));
+        let result2 = target.is_allowed("wexplorer", &url("/page"));
+
+        // Assert
+
+        assert!(result1);
+        assert!(!result2);
+    }
+}
@@ -0,0 +1,153 @@
+use std::{sync::{Arc, Mutex}, collections::HashMap};
+
+use chrono::{Utc, DateTime};
+use reqwest::{StatusCode, header::HeaderMap};
+use rusqlite::{params, OptionalExtension};
+use sea_query::{Table, Iden, ColumnDef, Query, SimpleExpr, Expr};
+use tracing::{debug, warn};
+use url::Url;
+
+use super::{SqliteSchemaStatementBuilder, SqliteQueryStatementWriter, RobotsTxt, CRAWLER_USER_AGENT, DynFetcher, SqlitePool};
+
+// Robots.txt used when the file couldn't be fetched because of a server or network error.
+// The site is considered fully disallowed until the next attempt.
+const UNAVAILABLE_ROBOTS_TXT: &str = "User-agent: *\nDisallow: /";
+
+// Larger robots.txt files are unlikely to be written by hand, they are treated as unavailable.
+const MAX_ROBOTS_TXT_SIZE: u64 = 512 * 1024;
+
+#[derive(Iden)]
+enum RobotsTxtFiles {
+    Table,
+    Origin,
+    Content,
+    ExpiresTimestamp,
+}
+
+struct CachedRobotsTxt {
+    robots_txt: Arc<RobotsTxt>,
+    expires_time: DateTime<Utc>,
+}
+
+pub struct RobotsTxtCache {
+    pool: Arc<SqlitePool>,
+    fetcher: DynFetcher,
+    expiration: chrono::Duration,
+    unavailable_expiration: chrono::Duration,
+    cache: Mutex<HashMap<String, CachedRobotsTxt>>,
+    add_sql: String,
+    get_sql: String,
+}
+
+impl RobotsTxtCache {
+    pub fn new(
+        pool: Arc<SqlitePool>, fetcher: DynFetcher, expiration: chrono::Duration,
+        unavailable_expiration: chrono::Duration)
+        -> Result<Self, rusqlite::Error>
+    {
+        let create_table_sql = Table::create()
+            .table(RobotsTxtFiles::Table)
+            .if_not_exists()
+            .col(ColumnDef::new(RobotsTxtFiles::Origin).text().not_null().primary_key())
+            .col(ColumnDef::new(RobotsTxtFiles::Content).text().not_null())
+            .col(ColumnDef::new(RobotsTxtFiles::ExpiresTimestamp).integer().not_null())
+            .to_sqlite_string();
+        pool.run_blocking(|connection| connection.execute(&create_table_sql, ()))?;
+
+        let add_sql = Query::insert()
+            .into_table(RobotsTxtFiles::Table)
+            .columns([RobotsTxtFiles::Origin, RobotsTxtFiles::Content, RobotsTxtFiles::ExpiresTimestamp])
+            .values_panic([
+                SimpleExpr::Custom("?1".to_string()),
+                SimpleExpr::Custom("?2".to_string()),
+                SimpleExpr::Custom("?3".to_string()),
+            ])
+            .to_sqlite_string()
+            .replace("INSERT", "REPLACE");
+
+        let get_sql = Query::select()
+            .columns([RobotsTxtFiles::Content, RobotsTxtFiles::ExpiresTimestamp])
+            .from(RobotsTxtFiles::Table)
+            .and_where(Expr::col(RobotsTxtFiles::Origin).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        Ok(Self {
+            pool,
+            fetcher,
+            expiration,
+            unavailable_expiration,
+            cache: Mutex::new(HashMap::new()),
+            add_sql,
+            get_sql,
+        })
+    }
+
+    pub async fn is_allowed(&self, url: &Url) -> Result<bool, rusqlite::Error> {
+        Ok(self.get(url).await?.is_allowed(CRAWLER_USER_AGENT, url))
+    }
+
+    pub async fn get(&self, url: &Url) -> Result<Arc<RobotsTxt>, rusqlite::Error> {
+        let origin = url.origin().ascii_serialization();
+        let now = Utc::now();
+
+        if let Some(cached) = self.cache.lock().unwrap().get(&origin).filter(|c| c.expires_time > now) {
+            return Ok(cached.robots_txt.clone());
+        }
+
+        let (sql, stored_origin) = (self.get_sql.clone(), origin.clone());
+        let stored = self.pool
+            .run(move |connection| connection
+                .query_row(&sql, [&stored_origin], |row| Ok((row.get(0)?, row.get::<_, DateTime<Utc>>(1)?)))
+                .optional())
+            .await?
+            .filter(|(_, expires_time)| *expires_time > now);
+
+        let (content, expires_time) = match stored {
+            Some(stored) => stored,
+            None => {
+                let (content, expiration) = self.fetch(url).await;
+                let expires_time = now + expiration;
+                let (sql, stored_origin, stored_content) = (self.add_sql.clone(), origin.clone(), content.clone());
+                self.pool
+                    .run(move |connection| {
+                        connection.execute(&sql, params![stored_origin, stored_content, expires_time])
+                    })
+                    .await?;
+                (content, expires_time)
+            },
+        };
+
+        let robots_txt = Arc::new(RobotsTxt::parse(&content));
+        self.cache.lock().unwrap().insert(origin, CachedRobotsTxt { robots_txt: robots_txt.clone(), expires_time });
+
+        Ok(robots_txt)
+    }
+
+    async fn fetch(&self, url: &Url) -> (String, chrono::Duration) {
+        let Ok(robots_txt_url) = url.join("/robots.txt") else {
+            return (UNAVAILABLE_ROBOTS_TXT.to_string(), self.unavailable_expiration);
+        };
+        debug!("Fetching {}", robots_txt_url);
+
+        let response = match self.fetcher.fetch(&robots_txt_url, HeaderMap::new(), MAX_ROBOTS_TXT_SIZE).await {
+            Ok(response) => response,
+            Err(err) => {
+                warn!("Request {} failed {}", robots_txt_url, err);
+                return (UNAVAILABLE_ROBOTS_TXT.to_string(), self.unavailable_expiration);
+            },
+        };
+
+        let status = response.status;
+        if status.is_success() {
+            (response.text(), self.expiration)
+        }
+        // A missing robots.txt means that there are no crawling restrictions.
+        else if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+            (String::new(), self.expiration)
+        }
+        else {
+            warn!("Request {} failed with status {}", robots_txt_url, status);
+            (UNAVAILABLE_ROBOTS_TXT.to_string(), self.unavailable_expiration)
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use rusqlite::{Connection, params, TransactionBehavior};
+use sea_query::{Alias, ColumnDef, Expr, Iden, Query, SimpleExpr, Table};
+use url::Url;
+
+use crate::queue::Queue;
+
+use super::{
+    SqlitePool, IndexedLinks, WebSites, LinkEdges, SqliteSchemaStatementBuilder, SqliteQueryStatementWriter,
+    SIMHASH_BAND_COLUMNS, is_endorsing_rel};
+
+// Version of the schema which the storages create, it's stored in PRAGMA user_version of the database.
+const SCHEMA_VERSION: i32 = 3;
+
+// Storages create their tables with all columns when the tables don't exist, so the migrations only change tables of
+// older databases. It should be called before the storages are created.
+pub fn migrate_schema(pool: &SqlitePool) -> Result<(), rusqlite::Error> {
+    pool.run_blocking(|connection| {
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let version = transaction.query_row("PRAGMA user_version", (), |row| row.get::<_, i32>(0))?;
+        if version < 1 {
+            migrate_to_version_1(&transaction)?;
+        }
+        if version < 2 {
+            migrate_to_version_2(&transaction)?;
+        }
+        if version < 3 {
+            migrate_to_version_3(&transaction)?;
+        }
+        if version < SCHEMA_VERSION {
+            transaction.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+        transaction.commit()
+    })
+}
+
+// Databases without a version could be created by any older build, so each step adds only the missing columns.
+// Origins and hosts of existing rows are computed from their URLs, other columns get the values of pages crawled
+// before sites.
+fn migrate_to_version_1(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_queue_hosts(connection)?;
+    add_priorities_and_link_origins(connection)?;
+    add_cache_validators(connection)?;
+    add_site_ids(connection)?;
+    add_depths_and_budgets(connection)?;
+    add_retry_attempts(connection)?;
+    add_queue_and_site_origins(connection)?;
+    add_page_directive_settings(connection)?;
+    add_content_fingerprints(connection)?;
+    add_document_hashes_and_change_counts(connection)
+}
+
+// Queue items are taken by host, so that politeness of hosts is kept.
+fn add_queue_hosts(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(
+        connection, Queue::Table, &mut [ColumnDef::new(Queue::Host).text().not_null().default("").to_owned()])?;
+    fill_empty_column(
+        connection, Queue::Table, Queue::Url, Queue::Host, |url| url.host_str().unwrap_or("").to_string())
+}
+
+// Queue items are ordered by priority, and indexed links are recrawled by origin.
+fn add_priorities_and_link_origins(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(
+        connection, Queue::Table, &mut [ColumnDef::new(Queue::Priority).integer().not_null().default(0).to_owned()])?;
+    add_missing_columns(
+        connection, IndexedLinks::Table,
+        &mut [ColumnDef::new(IndexedLinks::Origin).text().not_null().default("").to_owned()])?;
+    fill_empty_column(
+        connection, IndexedLinks::Table, IndexedLinks::Url, IndexedLinks::Origin,
+        |url| url.origin().ascii_serialization())
+}
+
+// Pages are requested conditionally with validators of their last responses.
+fn add_cache_validators(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(connection, IndexedLinks::Table, &mut [
+        ColumnDef::new(IndexedLinks::ETag).text().null().to_owned(),
+        ColumnDef::new(IndexedLinks::LastModified).text().null().to_owned(),
+    ])
+}
+
+// Queue items and indexed links belong to web sites, rows crawled before web sites belong to none.
+fn add_site_ids(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(
+        connection, Queue::Table, &mut [ColumnDef::new(Queue::SiteId).integer().not_null().default(0).to_owned()])?;
+    add_missing_columns(
+        connection, IndexedLinks::Table,
+        &mut [ColumnDef::new(IndexedLinks::SiteId).integer().not_null().default(0).to_owned()])
+}
+
+// Crawling of web sites is limited by depths of their pages and by their page budgets, which older sites don't have.
+fn add_depths_and_budgets(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(
+        connection, Queue::Table, &mut [ColumnDef::new(Queue::Depth).integer().not_null().default(0).to_owned()])?;
+    add_missing_columns(
+        connection, IndexedLinks::Table,
+        &mut [ColumnDef::new(IndexedLinks::Depth).integer().not_null().default(0).to_owned()])?;
+    add_missing_columns(connection, WebSites::Table, &mut [
+        ColumnDef::new(WebSites::MaxDepth).integer().null().to_owned(),
+        ColumnDef::new(WebSites::MaxPages).integer().null().to_owned(),
+        ColumnDef::new(WebSites::PagesCount).integer().not_null().default(0).to_owned(),
+        ColumnDef::new(WebSites::BudgetExhausted).boolean().not_null().default(false).to_owned(),
+    ])
+}
+
+// Failed queue items are retried later, existing items have no failed attempts.
+fn add_retry_attempts(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(connection, Queue::Table, &mut [
+        ColumnDef::new(Queue::Attempts).integer().not_null().default(0).to_owned(),
+        ColumnDef::new(Queue::NextAttemptTimestamp).integer().null().to_owned(),
+        ColumnDef::new(Queue::LastError).text().null().to_owned(),
+    ])
+}
+
+// Queue items and web sites are listed by origin.
+fn add_queue_and_site_origins(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(
+        connection, Queue::Table, &mut [ColumnDef::new(Queue::Origin).text().not_null().default("").to_owned()])?;
+    fill_empty_column(connection, Queue::Table, Queue::Url, Queue::Origin, |url| url.origin().ascii_serialization())?;
+    add_missing_columns(
+        connection, WebSites::Table, &mut [ColumnDef::new(WebSites::Origin).text().not_null().default("").to_owned()])?;
+    fill_empty_column(
+        connection, WebSites::Table, WebSites::Url, WebSites::Origin, |url| url.origin().ascii_serialization())
+}
+
+// Page directives are respected by web sites unless they are added with other settings.
+fn add_page_directive_settings(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(connection, WebSites::Table, &mut [
+        ColumnDef::new(WebSites::RespectCanonical).boolean().not_null().default(true).to_owned(),
+        ColumnDef::new(WebSites::RespectMetaRobots).boolean().not_null().default(true).to_owned(),
+        ColumnDef::new(WebSites::RespectXRobotsTag).boolean().not_null().default(true).to_owned(),
+        ColumnDef::new(WebSites::RespectNofollow).boolean().not_null().default(true).to_owned(),
+    ])
+}
+
+// Duplicates are found by fingerprints of page contents, pages get them when they are indexed again.
+fn add_content_fingerprints(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(connection, IndexedLinks::Table, &mut [
+        ColumnDef::new(IndexedLinks::ContentHash).integer().null().to_owned(),
+        ColumnDef::new(IndexedLinks::SimHash).integer().null().to_owned(),
+        ColumnDef::new(IndexedLinks::DuplicateOf).text().null().to_owned(),
+        ColumnDef::new(IndexedLinks::DuplicateDistance).integer().null().to_owned(),
+    ])
+}
+
+// Unchanged pages are detected by hashes of their documents and counted by web sites.
+fn add_document_hashes_and_change_counts(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(
+        connection, IndexedLinks::Table,
+        &mut [ColumnDef::new(IndexedLinks::DocumentHash).integer().null().to_owned()])?;
+    add_missing_columns(connection, WebSites::Table, &mut [
+        ColumnDef::new(WebSites::UpdatedPagesCount).integer().not_null().default(0).to_owned(),
+        ColumnDef::new(WebSites::UnchangedPagesCount).integer().not_null().default(0).to_owned(),
+    ])
+}
+
+// Near-duplicates are looked up by bands of SimHashes, the bands of existing pages are computed from their SimHashes.
+fn migrate_to_version_2(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(
+        connection, IndexedLinks::Table,
+        &mut SIMHASH_BAND_COLUMNS.map(|band_column| ColumnDef::new(band_column).integer().null().to_owned()))?;
+    if get_column_names(connection, &IndexedLinks::Table.to_string())?.is_empty() {
+        return Ok(());
+    }
+
+    let mut query = Query::update();
+    query.table(IndexedLinks::Table);
+    for (i, band_column) in SIMHASH_BAND_COLUMNS.into_iter().enumerate() {
+        let band = format!("(\"{}\" >> {}) & 65535", IndexedLinks::SimHash.to_string(), i * 16);
+        query.value(band_column, Expr::cust(band));
+    }
+    let set_simhash_bands_sql = query
+        .and_where(Expr::col(IndexedLinks::SimHash).is_not_null())
+        .to_sqlite_string();
+    connection.execute(&set_simhash_bands_sql, ())?;
+    Ok(())
+}
+
+// Inbound anchor texts are grouped in queries, so links store whether they are endorsed. Pages whose anchor texts
+// weren't hashed yet are fetched in full with their next recrawl.
+fn migrate_to_version_3(connection: &Connection) -> Result<(), rusqlite::Error> {
+    add_missing_columns(
+        connection, IndexedLinks::Table,
+        &mut [ColumnDef::new(IndexedLinks::AnchorTextsHash).integer().null().to_owned()])?;
+    add_missing_columns(
+        connection, LinkEdges::Table,
+        &mut [ColumnDef::new(LinkEdges::IsEndorsed).boolean().not_null().default(true).to_owned()])?;
+    if get_column_names(connection, &LinkEdges::Table.to_string())?.is_empty() {
+        return Ok(());
+    }
+
+    let get_rels_sql = Query::select()
+        .expr(Expr::cust("rowid"))
+        .column(LinkEdges::Rel)
+        .from(LinkEdges::Table)
+        .and_where(Expr::col(LinkEdges::Rel).is_not_null())
+        .to_sqlite_string();
+    let set_unendorsed_sql = Query::update()
+        .table(LinkEdges::Table)
+        .value(LinkEdges::IsEndorsed, false)
+        .and_where(Expr::cust("rowid = ?1"))
+        .to_sqlite_string();
+    let rels = connection
+        .prepare(&get_rels_sql)?
+        .query_map((), |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut set_unendorsed_statement = connection.prepare(&set_unendorsed_sql)?;
+    for (row_id, rel) in rels {
+        if !is_endorsing_rel(Some(&rel)) {
+            set_unendorsed_statement.execute([row_id])?;
+        }
+    }
+    Ok(())
+}
+
+// Tables which don't exist yet are skipped, they are created with all columns by their storages.
+fn add_missing_columns(connection: &Connection, table: impl Iden, columns: &mut [ColumnDef])
+    -> Result<(), rusqlite::Error>
+{
+    let table = table.to_string();
+    let existing_columns = get_column_names(connection, &table)?;
+    if existing_columns.is_empty() {
+        return Ok(());
+    }
+
+    for column in columns {
+        if !existing_columns.contains(&column.get_column_name().to_lowercase()) {
+            let add_column_sql = Table::alter()
+                .table(Alias::new(&table))
+                .add_column(column)
+                .to_sqlite_string();
+            connection.execute(&add_column_sql, ())?;
+        }
+    }
+    Ok(())
+}
+
+// Names are lowercase, because SQLite compares names of tables and columns case-insensitively.
+fn get_column_names(connection: &Connection, table: &str) -> Result<Vec<String>, rusqlite::Error> {
+    connection
+        .prepare("SELECT lower(name) FROM pragma_table_info(?1)")?
+        .query_map([table], |row| row.get::<_, String>(0))?
+        .collect()
+}
+
+fn fill_empty_column(
+    connection: &Connection, table: impl Iden, url_column: impl Iden, column: impl Iden, value: impl Fn(&Url) -> String)
+    -> Result<(), rusqlite::Error>
+{
+    if get_column_names(connection, &table.to_string())?.is_empty() {
+        return Ok(());
+    }
+
+    let (table, url_column, column) =
+        (Alias::new(table.to_string()), Alias::new(url_column.to_string()), Alias::new(column.to_string()));
+
+    let get_urls_sql = Query::select()
+        .from(table.clone())
+        .column(url_column.clone())
+        .and_where(Expr::col(column.clone()).eq(""))
+        .to_sqlite_string();
+    let set_value_sql = Query::update()
+        .table(table)
+        .value(column, SimpleExpr::Custom("?2".to_string()))
+        .and_where(Expr::col(url_column).eq(SimpleExpr::Custom("?1".to_string())))
+        .to_sqlite_string();
+    let urls = connection
+        .prepare(&get_urls_sql)?
+        .query_map((), |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut set_value_statement = connection.prepare(&set_value_sql)?;
+    for url in urls {
+        if let Ok(parsed_url) = Url::parse(&url) {
+            set_value_statement.execute(params![url, value(&parsed_url)])?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod schema_migrations_tests {
+    use std::sync::Arc;
+
+    use crate::{queue::{SqliteIndexingQueue, PolitenessSettings, RetrySettings, IndexingQueue}, indexing::{SqliteIndexedLinksStorage, IndexedLinksStorage, ContentFingerprint}};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn should_migrate_tables_of_unversioned_database() {
+        // Arrange
+
+        let pool = Arc::new(SqlitePool::open_in_memory().unwrap());
+        pool.run_blocking(|connection| {
+            connection.execute_batch("
+                CREATE TABLE queue (id integer NOT NULL PRIMARY KEY AUTOINCREMENT, url text NOT NULL UNIQUE,
+                    status integer NOT NULL);
+                CREATE TABLE indexed_links (url text NOT NULL PRIMARY KEY, last_indexed_timestamp integer NOT NULL,
+                    sim_hash integer NULL);
+                CREATE TABLE link_edges (source_url text NOT NULL, target_url text NOT NULL, anchor_text text NOT NULL,
+                    rel text NULL);
+                INSERT INTO link_edges VALUES ('https://example.com/', 'https://example.com/a', 'A', NULL),
+                    ('https://example.com/', 'https://example.com/b', 'B', 'ugc nofollow');
+                INSERT INTO queue (url, status) VALUES ('https://example.com/page', 0);
+                INSERT INTO indexed_links (url, last_indexed_timestamp, sim_hash)
+                    VALUES ('https://example.com/', 1000, -81985529216486896);")
+        }).unwrap();
+
+        // Act
+
+        migrate_schema(&pool).unwrap();
+        migrate_schema(&pool).unwrap();
+        let version = pool.run_blocking(|c| c.query_row("PRAGMA user_version", (), |row| row.get::<_, i32>(0)));
+        let bands = pool.run_blocking(|c| c.query_row(
+            "SELECT sim_hash_band0, sim_hash_band1, sim_hash_band2, sim_hash_band3 FROM indexed_links", (),
+            |row| Ok([row.get::<_, u16>(0)?, row.get(1)?, row.get(2)?, row.get(3)?])));
+        let endorsements = pool.run_blocking(|c| c
+            .prepare("SELECT is_endorsed FROM link_edges ORDER BY target_url")?
+            .query_map((), |row| row.get::<_, bool>(0))?
+            .collect::<Result<Vec<_>, _>>());
+        let indexed_links_storage = SqliteIndexedLinksStorage::new(pool.clone()).unwrap();
+        let queue = SqliteIndexingQueue::new(
+            pool,
+            PolitenessSettings { min_delay_ms: 0, max_connections_per_host: 1 },
+            RetrySettings { max_attempts: 1, initial_delay_secs: 1, max_delay_secs: 1 }).unwrap();
+        let item = queue.peek().await.unwrap();
+        let indexed_links = indexed_links_storage
+            .get_site_links_indexed_before("https://example.com", chrono::Utc::now())
+            .await
+            .unwrap();
+
+        // Assert
+
+        assert_eq!(SCHEMA_VERSION, version.unwrap());
+        assert_eq!("https://example.com/page", item.url.as_str());
+        assert_eq!("example.com", item.host);
+        assert_eq!(1, indexed_links.len());
+        assert_eq!(ContentFingerprint { hash: 0, simhash: 0xfedc_ba98_7654_3210 }.simhash_bands(), bands.unwrap());
+        assert_eq!(vec![true, false], endorsements.unwrap());
+    }
+}
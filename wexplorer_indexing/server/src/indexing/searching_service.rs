@@ -0,0 +1,71 @@
+use std::sync::Arc;
+#[cfg(test)]
+use std::sync::Mutex;
+
+use tonic::{transport::Channel, Status};
+use wexplorer_searching_grpc_client::{
+    searching_api_client::SearchingApiClient, AddPageRequest, DeletePageRequest, SetPageRanksRequest};
+
+pub type DynSearchingService = Arc<dyn SearchingService>;
+
+#[tonic::async_trait]
+pub trait SearchingService: Send + Sync {
+    async fn add_page(&self, request: AddPageRequest) -> Result<(), Status>;
+
+    async fn delete_page(&self, request: DeletePageRequest) -> Result<(), Status>;
+
+    async fn set_page_ranks(&self, request: SetPageRanksRequest) -> Result<(), Status>;
+}
+
+// Clients share the channel, which connects with the first request and reconnects after connection errors.
+#[tonic::async_trait]
+impl SearchingService for SearchingApiClient<Channel> {
+    async fn add_page(&self, request: AddPageRequest) -> Result<(), Status> {
+        self.clone().add_page(request).await.map(|_| ())
+    }
+
+    async fn delete_page(&self, request: DeletePageRequest) -> Result<(), Status> {
+        self.clone().delete_page(request).await.map(|_| ())
+    }
+
+    async fn set_page_ranks(&self, request: SetPageRanksRequest) -> Result<(), Status> {
+        self.clone().set_page_ranks(request).await.map(|_| ())
+    }
+}
+
+// Keeps sent requests in memory instead of sending them, so that indexing can be run without the searching service.
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemorySearchingService {
+    pages: Mutex<Vec<AddPageRequest>>,
+    deleted_pages: Mutex<Vec<DeletePageRequest>>,
+}
+
+#[cfg(test)]
+impl InMemorySearchingService {
+    pub fn pages(&self) -> Vec<AddPageRequest> {
+        self.pages.lock().unwrap().clone()
+    }
+
+    pub fn deleted_pages(&self) -> Vec<DeletePageRequest> {
+        self.deleted_pages.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+#[tonic::async_trait]
+impl SearchingService for InMemorySearchingService {
+    async fn add_page(&self, request: AddPageRequest) -> Result<(), Status> {
+        self.pages.lock().unwrap().push(request);
+        Ok(())
+    }
+
+    async fn delete_page(&self, request: DeletePageRequest) -> Result<(), Status> {
+        self.deleted_pages.lock().unwrap().push(request);
+        Ok(())
+    }
+
+    async fn set_page_ranks(&self, _request: SetPageRanksRequest) -> Result<(), Status> {
+        Ok(())
+    }
+}
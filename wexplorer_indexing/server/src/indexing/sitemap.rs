@@ -0,0 +1,247 @@
+use std::io::Read;
+
+use chrono::{DateTime, Utc, NaiveDate, FixedOffset, TimeZone};
+use flate2::read::GzDecoder;
+use reqwest::header::HeaderMap;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::queue::IndexingQueue;
+
+use super::{DynFetcher, RobotsTxtCache, CRAWLER_USER_AGENT};
+
+const MAX_SITEMAP_SIZE: u64 = 50 * 1024 * 1024;
+
+const MAX_SITEMAP_INDEX_DEPTH: u32 = 2;
+
+#[derive(Debug, PartialEq)]
+pub struct SitemapEntry {
+    pub url: Url,
+    pub last_modified_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Sitemap {
+    UrlSet(Vec<SitemapEntry>),
+    Index(Vec<SitemapEntry>),
+}
+
+impl Sitemap {
+    pub fn parse(content: &[u8]) -> Result<Self, String> {
+        let content = if content.starts_with(&[0x1f, 0x8b]) {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(content)
+                .take(MAX_SITEMAP_SIZE)
+                .read_to_end(&mut decompressed)
+                .map_err(|err| err.to_string())?;
+            decompressed
+        }
+        else {
+            content.to_vec()
+        };
+
+        let text = String::from_utf8_lossy(&content);
+        let document = roxmltree::Document::parse(text.trim_start_matches('\u{feff}')).map_err(|err| err.to_string())?;
+        let root = document.root_element();
+
+        let entry_tag = match root.tag_name().name() {
+            "urlset" => "url",
+            "sitemapindex" => "sitemap",
+            tag => return Err(format!("Unknown sitemap root element {}", tag)),
+        };
+
+        let entries = root.children()
+            .filter(|n| n.tag_name().name() == entry_tag)
+            .filter_map(|entry| {
+                let get_child_text = |name: &str| entry.children()
+                    .find(|n| n.tag_name().name() == name)
+                    .and_then(|n| n.text())
+                    .map(|t| t.trim());
+
+                Some(SitemapEntry {
+                    url: Url::parse(get_child_text("loc")?).ok()?,
+                    last_modified_time: get_child_text("lastmod").and_then(parse_w3c_datetime),
+                })
+            })
+            .collect();
+
+        Ok(if entry_tag == "url" { Sitemap::UrlSet(entries) } else { Sitemap::Index(entries) })
+    }
+}
+
+fn parse_w3c_datetime(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+
+    // W3C datetime allows to omit seconds.
+    if let Ok(datetime) = DateTime::<FixedOffset>::parse_from_str(&value.replace('Z', "+00:00"), "%Y-%m-%dT%H:%M%:z") {
+        return Some(datetime.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|datetime| Utc.from_utc_datetime(&datetime))
+}
+
+#[derive(Clone)]
+pub struct SitemapLoader {
+    fetcher: DynFetcher,
+}
+
+impl SitemapLoader {
+    pub fn new(fetcher: DynFetcher) -> Self {
+        Self { fetcher }
+    }
+
+    // Sitemaps are requested within robots.txt rules and politeness of their hosts like pages.
+    // Sitemap URLs come from the web site, so they are loaded only if the processing returns them, e.g. if they are
+    // within the scope of the web site.
+    pub async fn load_entries(
+        &self, sitemap_url: &Url, process_url: impl Fn(Url) -> Option<Url> + Sync, robots_txt_cache: &RobotsTxtCache,
+        queue: &dyn IndexingQueue)
+        -> Vec<SitemapEntry>
+    {
+        let mut entries = Vec::new();
+        let mut sitemap_urls = vec![(sitemap_url.clone(), 0)];
+
+        while let Some((sitemap_url, depth)) = sitemap_urls.pop() {
+            let Some(sitemap_url) = process_url(sitemap_url.clone()) else {
+                debug!("Skip filtered out sitemap {}", sitemap_url);
+                continue;
+            };
+
+            match self.load(&sitemap_url, robots_txt_cache, queue).await {
+                Some(Sitemap::UrlSet(url_entries)) => {
+                    debug!("Sitemap {} has {} URLs", sitemap_url, url_entries.len());
+                    entries.extend(url_entries);
+                },
+                Some(Sitemap::Index(_)) if depth >= MAX_SITEMAP_INDEX_DEPTH => {
+                    warn!("Sitemap index {} is nested too deep", sitemap_url);
+                },
+                Some(Sitemap::Index(sitemap_entries)) => {
+                    debug!("Sitemap index {} has {} sitemaps", sitemap_url, sitemap_entries.len());
+                    sitemap_urls.extend(sitemap_entries.into_iter().map(|e| (e.url, depth + 1)));
+                },
+                None => {},
+            }
+        }
+
+        entries
+    }
+
+    async fn load(&self, sitemap_url: &Url, robots_txt_cache: &RobotsTxtCache, queue: &dyn IndexingQueue)
+        -> Option<Sitemap>
+    {
+        let robots_txt = robots_txt_cache.get(sitemap_url).await.unwrap();
+        if !robots_txt.is_allowed(CRAWLER_USER_AGENT, sitemap_url) {
+            debug!("Sitemap {} is disallowed by robots.txt", sitemap_url);
+            return None;
+        }
+
+        let host = sitemap_url.host_str().unwrap_or("");
+        queue.set_crawl_delay(host, robots_txt.crawl_delay(CRAWLER_USER_AGENT));
+        queue.acquire_host(host).await;
+        let fetch_result = self.fetcher.fetch(sitemap_url, HeaderMap::new(), MAX_SITEMAP_SIZE).await;
+        queue.release_host(host);
+
+        let response = match fetch_result {
+            Ok(response) if response.status.is_success() => response,
+            Ok(response) => {
+                debug!("Couldn't load sitemap {} status {}", sitemap_url, response.status);
+                return None;
+            },
+            Err(err) => {
+                debug!("Couldn't load sitemap {} {}", sitemap_url, err);
+                return None;
+            },
+        };
+
+        match Sitemap::parse(&response.body) {
+            Ok(sitemap) => Some(sitemap),
+            Err(err) => {
+                warn!("Couldn't parse sitemap {} {}", sitemap_url, err);
+                None
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod sitemap_tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    #[test]
+    fn should_parse_url_set() {
+        // Arrange
+
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <url><loc>https://localhost/page1</loc><lastmod>2023-05-01</lastmod></url>
+                <url><loc> https://localhost/page2 </loc><lastmod>2023-05-01T10:20:30+02:00</lastmod></url>
+                <url><loc>https://localhost/page3</loc><lastmod>2023-05-01T10:20Z</lastmod></url>
+                <url><loc>not a url</loc></url>
+            </urlset>"#;
+
+        // Act
+
+        let result = Sitemap::parse(content.as_bytes()).unwrap();
+
+        // Assert
+
+        assert_eq!(Sitemap::UrlSet(vec![
+            SitemapEntry {
+                url: Url::parse("https://localhost/page1").unwrap(),
+                last_modified_time: Some(Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap()),
+            },
+            SitemapEntry {
+                url: Url::parse("https://localhost/page2").unwrap(),
+                last_modified_time: Some(Utc.with_ymd_and_hms(2023, 5, 1, 8, 20, 30).unwrap()),
+            },
+            SitemapEntry {
+                url: Url::parse("https://localhost/page3").unwrap(),
+                last_modified_time: Some(Utc.with_ymd_and_hms(2023, 5, 1, 10, 20, 0).unwrap()),
+            },
+        ]), result);
+    }
+
+    #[test]
+    fn should_parse_gzipped_sitemap_index() {
+        // Arrange
+
+        let content = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <sitemap><loc>https://localhost/sitemap1.xml.gz</loc></sitemap>
+            </sitemapindex>"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+
+        // Act
+
+        let result = Sitemap::parse(&encoder.finish().unwrap()).unwrap();
+
+        // Assert
+
+        assert_eq!(Sitemap::Index(vec![
+            SitemapEntry { url: Url::parse("https://localhost/sitemap1.xml.gz").unwrap(), last_modified_time: None },
+        ]), result);
+    }
+
+    #[test]
+    fn should_fail_for_unknown_document() {
+        // Arrange
+
+        let content = "<html><body></body></html>";
+
+        // Act
+
+        let result = Sitemap::parse(content.as_bytes());
+
+        // Assert
+
+        assert!(result.is_err());
+    }
+}
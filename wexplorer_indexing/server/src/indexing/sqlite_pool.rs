@@ -0,0 +1,197 @@
+use std::{sync::{Arc, Mutex}, panic, thread, time::Duration};
+
+use rusqlite::{Connection, ErrorCode};
+use serde::Deserialize;
+use tokio::sync::{Semaphore, OwnedSemaphorePermit};
+use tracing::warn;
+
+const BUSY_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+// A writer which holds the lock longer is likely stuck, so the error is returned after these retries.
+const MAX_BUSY_RETRIES: u32 = 50;
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SqlitePoolSettings {
+    pub pool_size: usize,
+    // Time a connection waits for the lock of another writer before it fails with SQLITE_BUSY.
+    pub busy_timeout_ms: u64,
+}
+
+// Connections are used on the blocking thread pool of tokio, so that queries don't block the async tasks of the
+// crawler. A file database is opened in WAL mode, so that readers don't wait for the writer and the writer doesn't
+// wait for readers.
+pub struct SqlitePool {
+    connections: Arc<Mutex<Vec<Connection>>>,
+    semaphore: Arc<Semaphore>,
+}
+
+// Returns the connection to the pool even if the function using it panics. A transaction which is in progress is
+// rolled back when it's dropped, so the connection can be reused.
+struct PooledConnection {
+    connection: Option<Connection>,
+    connections: Arc<Mutex<Vec<Connection>>>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.connections.lock().unwrap().push(connection);
+        }
+    }
+}
+
+impl SqlitePool {
+    pub fn open(path: &str, settings: &SqlitePoolSettings) -> Result<Self, rusqlite::Error> {
+        let connections = (0..settings.pool_size.max(1))
+            .map(|_| {
+                let connection = Connection::open(path)?;
+                connection.busy_timeout(Duration::from_millis(settings.busy_timeout_ms))?;
+                connection.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+                // Commits aren't synced to disk until a checkpoint, which is durable enough for a crawler.
+                connection.pragma_update(None, "synchronous", "NORMAL")?;
+                Ok(connection)
+            })
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        Ok(Self::from_connections(connections))
+    }
+
+    // An in-memory database exists only within its connection, so the pool has a single one.
+    #[allow(dead_code)]
+    pub fn open_in_memory() -> Result<Self, rusqlite::Error> {
+        Ok(Self::from_connections(vec![Connection::open_in_memory()?]))
+    }
+
+    fn from_connections(connections: Vec<Connection>) -> Self {
+        Self { semaphore: Arc::new(Semaphore::new(connections.len())), connections: Arc::new(Mutex::new(connections)) }
+    }
+
+    // Runs the function with a free connection on the blocking thread pool. The function keeps running if the
+    // returned future is dropped, the connection is returned to the pool after it. Writers of other connections are
+    // waited for up to the busy timeout, the function is run again up to MAX_BUSY_RETRIES times if they hold the lock
+    // even longer. So the function must be idempotent, e.g. it should write in an IMMEDIATE transaction which takes
+    // the lock before reading and is rolled back on failure.
+    pub async fn run<T, F>(&self, mut f: F) -> Result<T, rusqlite::Error>
+    where
+        T: Send + 'static,
+        F: FnMut(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+    {
+        let permit = self.semaphore.clone().acquire_owned().await.unwrap();
+        let connections = self.connections.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let connection = connections.lock().unwrap().pop();
+            let mut pooled_connection = PooledConnection { connection, connections, _permit: permit };
+            let mut retries = 0;
+            loop {
+                match f(pooled_connection.connection.as_mut().unwrap()) {
+                    Err(rusqlite::Error::SqliteFailure(err, _))
+                        if err.code == ErrorCode::DatabaseBusy && retries < MAX_BUSY_RETRIES =>
+                    {
+                        warn!("SQLite database is locked by another writer, retrying");
+                        retries += 1;
+                        thread::sleep(BUSY_RETRY_DELAY);
+                    },
+                    result => return result,
+                }
+            }
+        }).await;
+
+        match result {
+            Ok(result) => result,
+            Err(err) => panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    // Runs the function on the current thread, it's used to create tables before the pool is shared.
+    pub fn run_blocking<T>(&self, f: impl FnOnce(&mut Connection) -> Result<T, rusqlite::Error>)
+        -> Result<T, rusqlite::Error>
+    {
+        let permit = self.semaphore.clone().try_acquire_owned().expect("SQLite pool is in use");
+        let connection = self.connections.lock().unwrap().pop();
+        let connections = self.connections.clone();
+        let mut pooled_connection = PooledConnection { connection, connections, _permit: permit };
+        f(pooled_connection.connection.as_mut().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod sqlite_pool_tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn should_share_file_database_between_concurrent_connections() {
+        // Arrange
+
+        let path = std::env::temp_dir().join(format!("wexplorer_sqlite_pool_{}.db", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let remove_files = || {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+            }
+        };
+        remove_files();
+        let target = Arc::new(
+            SqlitePool::open(&path, &SqlitePoolSettings { pool_size: 4, busy_timeout_ms: 5000 }).unwrap());
+        target.run_blocking(|c| c.execute("CREATE TABLE Items (Id INTEGER NOT NULL)", ())).unwrap();
+
+        // Act
+
+        let inserts = (0..20)
+            .map(|i| {
+                let target = target.clone();
+                tokio::spawn(async move { target.run(move |c| c.execute("INSERT INTO Items VALUES (?1)", [i])).await })
+            })
+            .collect::<Vec<_>>();
+        for insert in inserts {
+            insert.await.unwrap().unwrap();
+        }
+        let result1 = target.run(|c| c.query_row("SELECT COUNT(*) FROM Items", (), |row| row.get::<_, i64>(0))).await;
+        let result2 = target.run(|c| c.query_row("PRAGMA journal_mode", (), |row| row.get::<_, String>(0))).await;
+        drop(target);
+        remove_files();
+
+        // Assert
+
+        assert_eq!(20, result1.unwrap());
+        assert_eq!("wal", result2.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn should_retry_write_when_database_is_locked_longer_than_busy_timeout() {
+        // Arrange
+
+        let path = std::env::temp_dir().join(format!("wexplorer_sqlite_pool_busy_{}.db", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let remove_files = || {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+            }
+        };
+        remove_files();
+        let target = SqlitePool::open(&path, &SqlitePoolSettings { pool_size: 2, busy_timeout_ms: 0 }).unwrap();
+        target.run_blocking(|c| c.execute("CREATE TABLE Items (Id INTEGER NOT NULL)", ())).unwrap();
+        let mut writer = Connection::open(&path).unwrap();
+        let transaction = writer.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate).unwrap();
+        transaction.execute("INSERT INTO Items VALUES (1)", ()).unwrap();
+
+        // Act
+
+        let insert = target.run(|c| c.execute("INSERT INTO Items VALUES (2)", ()));
+        let commit = async {
+            tokio::time::sleep(BUSY_RETRY_DELAY * 3).await;
+            transaction.commit()
+        };
+        let (result1, result2) = tokio::join!(insert, commit);
+        let result3 = target.run(|c| c.query_row("SELECT COUNT(*) FROM Items", (), |row| row.get::<_, i64>(0))).await;
+        drop(writer);
+        drop(target);
+        remove_files();
+
+        // Assert
+
+        assert_eq!(1, result1.unwrap());
+        result2.unwrap();
+        assert_eq!(2, result3.unwrap());
+    }
+}
@@ -1,51 +1,333 @@
-use ego_tree::iter::Edge;
+use std::{collections::HashMap, hash::{Hash, Hasher}};
+
+use chrono::{DateTime, Utc};
+use ego_tree::{iter::Edge, NodeId};
 use itertools::Itertools;
-use scraper::{Html, Selector, ElementRef, Node};
+use scraper::{Html, Selector, ElementRef, Node, node::Element};
+use serde::Deserialize;
+use siphasher::sip::SipHasher13;
+
+use super::{format_text, format_inline_text};
+
+// Elements which contain texts of paragraphs and so give scores to their containers.
+const PARAGRAPH_ELEMENTS: [&str; 4] = ["p", "pre", "blockquote", "td"];
+
+// Containers which are considered to be paragraphs if they have their own text.
+const CONTAINER_ELEMENTS: [&str; 3] = ["div", "section", "article"];
+
+const BOILERPLATE_ELEMENTS: [&str; 5] = ["nav", "header", "footer", "aside", "form"];
+
+const BOILERPLATE_ROLES: [&str; 5] = ["navigation", "banner", "contentinfo", "complementary", "search"];
+
+const MIN_PARAGRAPH_LENGTH: usize = 25;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum TextExtractionMode {
+    // All texts of the body.
+    #[default]
+    Raw,
+    // Texts of the blocks which look like the main content of the page, without navigation, footers, sidebars, etc.
+    MainContent,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextExtractionSettings {
+    #[serde(default)]
+    pub mode: TextExtractionMode,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq)]
+pub struct Heading {
+    pub level: u32,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageDocument {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub headings: Vec<Heading>,
+    pub text: Option<String>,
+    // Texts of links to the page from other pages, they aren't a part of the page HTML.
+    pub anchor_texts: Vec<String>,
+    pub fetch_time: DateTime<Utc>,
+}
+
+impl PageDocument {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.description.is_none() && self.headings.is_empty() && self.text.is_none()
+    }
+
+    // Hash of the content which is sent to the searching service, the fetch time doesn't change it.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = SipHasher13::new();
+        (&self.title, &self.description, &self.headings, &self.text, &self.anchor_texts).hash(&mut hasher);
+        hasher.finish()
+    }
+}
 
 #[derive(Clone)]
 pub struct TextExtractor {
+    mode: TextExtractionMode,
     body_selector: Selector,
+    title_selector: Selector,
+    description_selector: Selector,
+    headings_selector: Selector,
 }
 
 impl TextExtractor {
-    pub fn new() -> Self {
-        Self { body_selector: Selector::parse("body").unwrap() }
+    pub fn new(mode: TextExtractionMode) -> Self {
+        Self {
+            mode,
+            body_selector: Selector::parse("body").unwrap(),
+            title_selector: Selector::parse("title").unwrap(),
+            description_selector: Selector::parse("meta[name][content]").unwrap(),
+            headings_selector: Selector::parse("h1, h2, h3, h4, h5, h6").unwrap(),
+        }
     }
 
-    pub fn extract_text(&self, html: &Html) -> Option<String> {
-        html.select(&self.body_selector)
+    pub fn extract_document(&self, html: &Html, fetch_time: DateTime<Utc>) -> PageDocument {
+        let title = html.select(&self.title_selector)
             .next()
-            .map(|body| extract_text_from_body(body))
-            .and_then(|text| if text.is_empty() { None } else { Some(text) })
+            .and_then(|title| non_empty(format_inline_text(title, is_skipped)));
+
+        let description = html.select(&self.description_selector)
+            .find(|m| m.value().attr("name").is_some_and(|n| n.eq_ignore_ascii_case("description")))
+            .and_then(|m| non_empty(m.value().attr("content").unwrap_or("").split_whitespace().join(" ")));
+
+        let headings = html.select(&self.body_selector)
+            .next()
+            .map(|body| body.select(&self.headings_selector)
+                .filter(|h| self.mode == TextExtractionMode::Raw
+                    || !h.ancestors().filter_map(ElementRef::wrap).any(|a| is_boilerplate(&a)))
+                .filter_map(|h| {
+                    let level = h.value().name()[1..].parse().ok()?;
+                    Some(Heading { level, text: non_empty(format_inline_text(h, is_skipped))? })
+                })
+                .collect())
+            .unwrap_or_default();
+
+        PageDocument {
+            title,
+            description,
+            headings,
+            text: self.extract_text(html),
+            anchor_texts: Vec::new(),
+            fetch_time,
+        }
+    }
+
+    pub fn extract_text(&self, html: &Html) -> Option<String> {
+        let body = html.select(&self.body_selector).next()?;
+        let text = match self.mode {
+            TextExtractionMode::Raw => format_text([body], is_skipped),
+            TextExtractionMode::MainContent => extract_main_content(body),
+        };
+
+        non_empty(text)
     }
 }
 
-fn extract_text_from_body(body: ElementRef) -> String {
-    let skip_elements = get_skip_elements();
-    body.traverse()
-        .filter_map(|edge| {
-            let Edge::Open(node) = edge else { return None; };
-            let Node::Text(text) = node.value() else { return None; };
-            let text = Some(text.trim());
-            let Some(parent) = node.parent() else { return text; };
-            let Node::Element(parent_element) = parent.value() else { return text; };
+// Scores blocks like Readability does: every paragraph gives its parent a score depending on the length of its text
+// and the density of links in it, the grandparent gets a half of it. The best scored block is taken together with
+// its siblings which look like a part of the same content.
+fn extract_main_content(body: ElementRef) -> String {
+    let is_skipped = |e: &ElementRef| is_skipped(e) || is_boilerplate(e);
 
-            if skip_elements.contains(&parent_element.name()) {
-                None
+    let mut scores = HashMap::<NodeId, f64>::new();
+    let mut candidates = Vec::new();
+    let mut skipped_node = None;
+    for edge in body.traverse() {
+        let node = match edge {
+            Edge::Open(node) if skipped_node.is_none() => node,
+            Edge::Close(node) if skipped_node == Some(node.id()) => {
+                skipped_node = None;
+                continue;
+            },
+            _ => continue,
+        };
+
+        let Some(element) = ElementRef::wrap(node) else { continue; };
+        if is_skipped(&element) {
+            skipped_node = Some(node.id());
+            continue;
+        }
+
+        if !is_paragraph(&element) {
+            continue;
+        }
+
+        let stats = TextStats::measure(element, is_skipped);
+        if stats.text_length < MIN_PARAGRAPH_LENGTH {
+            continue;
+        }
+
+        let commas_count = collect_texts(element, is_skipped).iter().map(|t| t.matches(',').count()).sum::<usize>();
+        let score = (1.0 + commas_count as f64 + (stats.text_length as f64 / 100.0).min(3.0))
+            * (1.0 - stats.link_density());
+
+        for (ancestor, divider) in element.ancestors().filter_map(ElementRef::wrap).take(2).zip([1.0, 2.0]) {
+            let ancestor_score = scores.entry(ancestor.id()).or_insert_with(|| {
+                candidates.push(ancestor);
+                0.0
+            });
+            *ancestor_score += score / divider;
+        }
+    }
+
+    let final_scores = candidates.iter()
+        .map(|c| (c.id(), scores[&c.id()] * (1.0 - TextStats::measure(*c, is_skipped).link_density())))
+        .collect::<HashMap<_, _>>();
+
+    let top_candidate = candidates.into_iter()
+        .max_by(|c1, c2| final_scores[&c1.id()].total_cmp(&final_scores[&c2.id()]));
+    let Some(top_candidate) = top_candidate else {
+        return format_text([body], is_skipped);
+    };
+
+    let Some(parent) = top_candidate.parent().and_then(ElementRef::wrap) else {
+        return format_text([top_candidate], is_skipped);
+    };
+
+    let sibling_threshold = (final_scores[&top_candidate.id()] * 0.2).max(10.0);
+    let content = parent.children()
+        .filter_map(ElementRef::wrap)
+        .filter(|sibling| {
+            if *sibling == top_candidate {
+                return true;
+            }
+
+            if is_skipped(sibling) {
+                return false;
+            }
+
+            if final_scores.get(&sibling.id()).is_some_and(|s| *s >= sibling_threshold) {
+                return true;
             }
-            else {
-                text
+
+            let stats = TextStats::measure(*sibling, is_skipped);
+            is_paragraph(sibling) && stats.text_length > 80 && stats.link_density() < 0.25
+        })
+        .collect::<Vec<_>>();
+
+    // Blocks within the content which are too short or consist of links, like ads and share buttons, are dropped.
+    let is_noise = |e: &ElementRef| {
+        if !CONTAINER_ELEMENTS.contains(&e.value().name()) || content.contains(e) {
+            return false;
+        }
+
+        let stats = TextStats::measure(*e, is_skipped);
+        stats.text_length < MIN_PARAGRAPH_LENGTH || stats.link_density() > 0.5
+    };
+    format_text(content.iter().copied(), |e| is_skipped(e) || is_noise(e))
+}
+
+struct TextStats {
+    text_length: usize,
+    link_text_length: usize,
+}
+
+impl TextStats {
+    fn measure(element: ElementRef, is_skipped: impl Fn(&ElementRef) -> bool) -> Self {
+        let mut stats = Self { text_length: 0, link_text_length: 0 };
+        let mut link_depth = 0;
+        let mut skipped_node = None;
+        for edge in element.traverse() {
+            match edge {
+                Edge::Open(node) if skipped_node.is_none() => match node.value() {
+                    Node::Text(text) => {
+                        let length = text.trim().chars().count();
+                        stats.text_length += length;
+                        if link_depth > 0 {
+                            stats.link_text_length += length;
+                        }
+                    },
+                    Node::Element(e) => {
+                        if ElementRef::wrap(node).is_some_and(|e| is_skipped(&e)) {
+                            skipped_node = Some(node.id());
+                        }
+                        else if is_link(e) {
+                            link_depth += 1;
+                        }
+                    },
+                    _ => {},
+                },
+                Edge::Close(node) if skipped_node == Some(node.id()) => skipped_node = None,
+                Edge::Close(node) if skipped_node.is_none() && node.value().as_element().is_some_and(is_link) =>
+                    link_depth -= 1,
+                _ => {},
             }
+        }
+
+        stats
+    }
+
+    fn link_density(&self) -> f64 {
+        if self.text_length == 0 { 0.0 } else { self.link_text_length as f64 / self.text_length as f64 }
+    }
+}
+
+// Collects trimmed texts of the element skipping the subtrees of the skipped elements.
+fn collect_texts<'a>(element: ElementRef<'a>, is_skipped: impl Fn(&ElementRef) -> bool) -> Vec<&'a str> {
+    let mut skipped_node = None;
+    element.traverse()
+        .filter_map(|edge| match edge {
+            Edge::Open(node) if skipped_node.is_none() => match node.value() {
+                Node::Text(text) => Some(text.trim()).filter(|t| !t.is_empty()),
+                Node::Element(_) => {
+                    if ElementRef::wrap(node).is_some_and(|e| is_skipped(&e)) {
+                        skipped_node = Some(node.id());
+                    }
+
+                    None
+                },
+                _ => None,
+            },
+            Edge::Close(node) if skipped_node == Some(node.id()) => {
+                skipped_node = None;
+                None
+            },
+            _ => None,
         })
-        .join(" ")
+        .collect()
+}
+
+fn is_link(element: &Element) -> bool {
+    element.name() == "a"
+}
+
+fn is_paragraph(element: &ElementRef) -> bool {
+    let name = element.value().name();
+    PARAGRAPH_ELEMENTS.contains(&name)
+        || CONTAINER_ELEMENTS.contains(&name)
+            && element.children().any(|c| c.value().as_text().is_some_and(|t| !t.trim().is_empty()))
+}
+
+fn is_skipped(element: &ElementRef) -> bool {
+    get_skip_elements().contains(&element.value().name())
+}
+
+fn is_boilerplate(element: &ElementRef) -> bool {
+    let element = element.value();
+    BOILERPLATE_ELEMENTS.contains(&element.name())
+        || element.attr("role").is_some_and(|r| BOILERPLATE_ROLES.iter().any(|b| r.eq_ignore_ascii_case(b)))
+        || element.attr("aria-hidden").is_some_and(|h| h.eq_ignore_ascii_case("true"))
+}
+
+fn non_empty(text: String) -> Option<String> {
+    if text.is_empty() { None } else { Some(text) }
 }
 
 fn get_skip_elements() -> &'static [&'static str] {
-    &["script", "style"]
+    &["script", "style", "noscript", "template"]
 }
 
 #[cfg(test)]
 mod text_extractor_tests {
+    use std::{env, fs, path::Path};
+
     use super::*;
     use scraper::Html;
 
@@ -54,7 +336,80 @@ mod text_extractor_tests {
         // Arrange
 
         let html = Html::parse_document("<html><body>Test<div>text<span>example</span></div>text</body></html>");
-        let target = TextExtractor::new();
+        let target = TextExtractor::new(TextExtractionMode::Raw);
+
+        // Act
+
+        let result = target.extract_text(&html).unwrap();
+
+        // Assert
+
+        assert_eq!("Test\n\ntextexample\n\ntext", result);
+    }
+
+    #[test]
+    fn should_extract_document_structure() {
+        // Arrange
+
+        let html = Html::parse_document(r#"<html><head>
+            <title> Test  page </title>
+            <meta name="Description" content="Page
+                description">
+            </head><body><h1>Header</h1><p>Text</p><h3>Sub<em>header</em></h3><h2> </h2></body></html>"#);
+        let fetch_time = Utc::now();
+        let target = TextExtractor::new(TextExtractionMode::Raw);
+
+        // Act
+
+        let result = target.extract_document(&html, fetch_time);
+
+        // Assert
+
+        assert_eq!(
+            PageDocument {
+                title: Some("Test page".to_string()),
+                description: Some("Page description".to_string()),
+                headings: vec![
+                    Heading { level: 1, text: "Header".to_string() },
+                    Heading { level: 3, text: "Subheader".to_string() },
+                ],
+                text: Some("Header\n\nText\n\nSubheader".to_string()),
+                anchor_texts: Vec::new(),
+                fetch_time,
+            },
+            result);
+    }
+
+    #[test]
+    fn should_hash_content_regardless_of_fetch_time() {
+        // Arrange
+
+        let html1 = Html::parse_document("<html><head><title>Page</title></head><body><p>Text</p></body></html>");
+        let html2 = Html::parse_document("<html><head><title>Page 2</title></head><body><p>Text</p></body></html>");
+        let target = TextExtractor::new(TextExtractionMode::Raw);
+
+        // Act
+
+        let result1 = target.extract_document(&html1, Utc::now()).content_hash();
+        let result2 = target.extract_document(&html1, Utc::now() + chrono::Duration::days(1)).content_hash();
+        let result3 = target.extract_document(&html2, Utc::now()).content_hash();
+
+        // Assert
+
+        assert_eq!(result1, result2);
+        assert_ne!(result1, result3);
+    }
+
+    #[test]
+    fn should_separate_blocks_and_collapse_whitespace() {
+        // Arrange
+
+        let html = Html::parse_document(
+            "<html><body><p>Fused<b>word</b> and&nbsp;\u{200B}spaced\t\n <i>words</i></p>\
+            <ol start=\"3\"><li>First<li>Second<ul><li>Nested</ul></ol>\
+            <table><tr><th>Name<th>Value<tr><td>a &lt;b&gt;<td>1</table>\
+            <pre>  let x = 1;\n\n  let y = 2;\n</pre>Line<br>break</body></html>");
+        let target = TextExtractor::new(TextExtractionMode::Raw);
 
         // Act
 
@@ -62,7 +417,46 @@ mod text_extractor_tests {
 
         // Assert
 
-        assert_eq!("Test text example text", result);
+        assert_eq!(
+            "Fusedword and spaced words\n\n3. First\n4. Second\n\n- Nested\n\nName Value\n\
+            a <b> 1\n\n  let x = 1;\n\n  let y = 2;\n\nLine\nbreak",
+            result);
+    }
+
+    #[test]
+    fn should_extract_text_of_golden_pages() {
+        // Arrange
+
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/text_extracting");
+        let html_paths = fs::read_dir(&directory).unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "html"))
+            .sorted()
+            .collect::<Vec<_>>();
+        // Set UPDATE_GOLDEN_FILES to regenerate the expected texts after an intended change of the extraction.
+        let update_golden_files = env::var_os("UPDATE_GOLDEN_FILES").is_some();
+
+        assert!(!html_paths.is_empty());
+        for html_path in html_paths {
+            let html = Html::parse_document(&fs::read_to_string(&html_path).unwrap());
+            let modes = [(TextExtractionMode::Raw, "raw.txt"), (TextExtractionMode::MainContent, "main.txt")];
+            for (mode, extension) in modes {
+                let target = TextExtractor::new(mode);
+                let golden_path = html_path.with_extension(extension);
+
+                // Act
+
+                let result = target.extract_text(&html).unwrap_or_default() + "\n";
+
+                // Assert
+
+                if update_golden_files {
+                    fs::write(&golden_path, &result).unwrap();
+                }
+
+                assert_eq!(fs::read_to_string(&golden_path).unwrap(), result, "{}", golden_path.display());
+            }
+        }
     }
 
     #[test]
@@ -70,7 +464,7 @@ mod text_extractor_tests {
         // Arrange
 
         let html = Html::parse_document("<html><body>Test<SCRIPT>script</SCRIPT><Style>style</Style></body></html>");
-        let target = TextExtractor::new();
+        let target = TextExtractor::new(TextExtractionMode::Raw);
 
         // Act
 
@@ -80,4 +474,40 @@ mod text_extractor_tests {
 
         assert_eq!("Test", result);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_extract_only_main_content() {
+        // Arrange
+
+        let html = Html::parse_document(r#"<html><body>
+            <header><h1>Site</h1></header>
+            <nav><a href="/">Home</a><a href="/news">News</a></nav>
+            <div class="layout">
+                <div role="complementary"><p>Sidebar with a long enough text to be a paragraph.</p></div>
+                <div class="content">
+                    <h2>Article</h2>
+                    <p>First paragraph of the article, which is long enough, and has commas.</p>
+                    <p>Second paragraph of the article with a <a href="/link">link</a> inside of it.</p>
+                </div>
+                <div class="links">
+                    <p><a href="/1">Related article number one</a> <a href="/2">Related article number two</a></p>
+                </div>
+            </div>
+            <footer>Copyright</footer>
+            </body></html>"#);
+        let target = TextExtractor::new(TextExtractionMode::MainContent);
+
+        // Act
+
+        let result1 = target.extract_text(&html).unwrap();
+        let result2 = target.extract_document(&html, Utc::now()).headings;
+
+        // Assert
+
+        assert_eq!(
+            "Article\n\nFirst paragraph of the article, which is long enough, and has commas.\n\n\
+            Second paragraph of the article with a link inside of it.",
+            result1);
+        assert_eq!(vec![Heading { level: 2, text: "Article".to_string() }], result2);
+    }
+}
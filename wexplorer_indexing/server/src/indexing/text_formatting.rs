@@ -0,0 +1,195 @@
+use ego_tree::iter::Edge;
+use scraper::{ElementRef, Node, node::Element};
+
+// Elements which start a new paragraph of the text.
+const PARAGRAPH_BLOCK_ELEMENTS: [&str; 35] = [
+    "address", "article", "aside", "blockquote", "body", "caption", "center", "details", "dialog", "dir", "div", "dl",
+    "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hgroup", "hr",
+    "main", "menu", "nav", "ol", "p", "pre", "section", "table", "ul",
+];
+
+// Elements which start a new line of the text.
+const LINE_BLOCK_ELEMENTS: [&str; 7] = ["br", "dd", "dt", "li", "option", "summary", "tr"];
+
+// Elements which are separated from their neighbours by a space even if there is no whitespace between them.
+const SPACED_ELEMENTS: [&str; 6] = ["td", "th", "img", "input", "select", "textarea"];
+
+// Invisible characters which only affect rendering of words.
+const IGNORED_CHARS: [char; 6] = ['\u{00AD}', '\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}'];
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Separator {
+    None,
+    Space,
+    Line,
+    Paragraph,
+}
+
+// Formats texts of the elements the way a browser renders them: whitespace is collapsed, blocks are separated by
+// line and paragraph breaks, list items get markers and preformatted texts are kept as is.
+pub fn format_text<'a>(
+    elements: impl IntoIterator<Item = ElementRef<'a>>, is_skipped: impl Fn(&ElementRef) -> bool)
+    -> String
+{
+    let mut writer = TextWriter { text: String::new(), separator: Separator::None };
+    for element in elements {
+        writer.separate(Separator::Paragraph);
+        write_element(&mut writer, element, &is_skipped);
+    }
+
+    writer.text.truncate(writer.text.trim_end().len());
+    writer.text
+}
+
+// Formats the text of the element as a single line.
+pub fn format_inline_text(element: ElementRef, is_skipped: impl Fn(&ElementRef) -> bool) -> String {
+    let text = format_text([element], is_skipped);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn write_element(writer: &mut TextWriter, element: ElementRef, is_skipped: &impl Fn(&ElementRef) -> bool) {
+    let mut skipped_node = None;
+    let mut preformatted_depth = 0;
+    // Next numbers of items of the open lists, None for unordered lists.
+    let mut lists = Vec::<Option<i64>>::new();
+    for edge in element.traverse() {
+        match edge {
+            Edge::Open(node) if skipped_node.is_none() => match node.value() {
+                Node::Text(text) if preformatted_depth > 0 => writer.write_preformatted(text),
+                Node::Text(text) => writer.write(text),
+                Node::Element(e) => {
+                    if ElementRef::wrap(node).is_some_and(|e| is_skipped(&e)) {
+                        skipped_node = Some(node.id());
+                        continue;
+                    }
+
+                    writer.separate(get_separator(e));
+                    match e.name() {
+                        "pre" | "textarea" | "listing" | "plaintext" => preformatted_depth += 1,
+                        "ol" => lists.push(Some(e.attr("start").and_then(|s| s.trim().parse().ok()).unwrap_or(1))),
+                        "ul" | "menu" | "dir" => lists.push(None),
+                        "li" => match lists.last_mut() {
+                            Some(Some(number)) => {
+                                writer.write_marker(&format!("{}.", number));
+                                *number += 1;
+                            },
+                            _ => writer.write_marker("-"),
+                        },
+                        _ => {},
+                    }
+                },
+                _ => {},
+            },
+            Edge::Close(node) if skipped_node == Some(node.id()) => skipped_node = None,
+            Edge::Close(node) if skipped_node.is_none() => {
+                let Node::Element(e) = node.value() else { continue; };
+                match e.name() {
+                    "pre" | "textarea" | "listing" | "plaintext" => preformatted_depth -= 1,
+                    "ol" | "ul" | "menu" | "dir" => { lists.pop(); },
+                    _ => {},
+                }
+
+                writer.separate(get_separator(e));
+            },
+            _ => {},
+        }
+    }
+}
+
+fn get_separator(element: &Element) -> Separator {
+    let name = element.name();
+    if PARAGRAPH_BLOCK_ELEMENTS.contains(&name) {
+        Separator::Paragraph
+    }
+    else if LINE_BLOCK_ELEMENTS.contains(&name) {
+        Separator::Line
+    }
+    else if SPACED_ELEMENTS.contains(&name) {
+        Separator::Space
+    }
+    else {
+        Separator::None
+    }
+}
+
+struct TextWriter {
+    text: String,
+    // Separator which is written before the next text, separators at the start and the end of the text are dropped.
+    separator: Separator,
+}
+
+impl TextWriter {
+    fn separate(&mut self, separator: Separator) {
+        if separator > self.separator {
+            self.separator = separator;
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        for c in text.chars().filter(|c| !IGNORED_CHARS.contains(c)) {
+            if c.is_whitespace() {
+                self.separate(Separator::Space);
+            }
+            else {
+                self.write_separator();
+                self.text.push(c);
+            }
+        }
+    }
+
+    fn write_preformatted(&mut self, text: &str) {
+        for line in text.split_inclusive('\n') {
+            let content = line.trim_end_matches(['\r', '\n']);
+            let has_line_break = content.len() < line.len();
+            let content = if has_line_break { content.trim_end() } else { content };
+            let content = if self.text.is_empty() { content.trim_start() } else { content };
+            if !content.is_empty() {
+                self.write_separator();
+                self.text.extend(content.chars()
+                    .filter(|c| !IGNORED_CHARS.contains(c))
+                    .map(|c| if c.is_whitespace() && c != '\t' { ' ' } else { c }));
+            }
+
+            if has_line_break {
+                self.write_line_break();
+            }
+        }
+    }
+
+    fn write_marker(&mut self, marker: &str) {
+        self.write_separator();
+        self.text.push_str(marker);
+        self.separator = Separator::Space;
+    }
+
+    // Keeps empty lines of preformatted texts, unlike separators which are collapsed.
+    fn write_line_break(&mut self) {
+        if !self.text.is_empty() {
+            self.separator = Separator::None;
+            self.text.push('\n');
+        }
+    }
+
+    fn write_separator(&mut self) {
+        if !self.text.is_empty() {
+            let line_breaks_count = match self.separator {
+                Separator::None => 0,
+                Separator::Space if !self.text.ends_with('\n') => {
+                    self.text.push(' ');
+                    0
+                },
+                Separator::Space => 0,
+                Separator::Line => 1,
+                Separator::Paragraph => 2,
+            };
+
+            // A preformatted text may already end with line breaks.
+            let existing_count = self.text.chars().rev().take(2).take_while(|c| *c == '\n').count();
+            for _ in existing_count.min(line_breaks_count)..line_breaks_count {
+                self.text.push('\n');
+            }
+        }
+
+        self.separator = Separator::None;
+    }
+}
@@ -1,11 +1,28 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
 
-use url::Url;
+use regex::RegexSet;
+use serde::Deserialize;
+use url::{Url, Host};
+
+// Images, media, archives, documents and binaries, which have no text to index.
+pub const DEFAULT_EXCLUDED_EXTENSIONS: [&str; 34] = [
+    "jpg", "jpeg", "png", "gif", "bmp", "webp", "svg", "ico", "tif", "tiff",
+    "mp3", "wav", "ogg", "mp4", "avi", "mov", "mkv", "webm",
+    "zip", "rar", "7z", "tar", "gz", "bz2", "xz",
+    "pdf", "doc", "docx", "xls", "xlsx",
+    "exe", "dll", "iso", "bin",
+];
 
 pub trait UrlFilter {
     fn is_match(&self, url: &Url) -> bool;
 }
 
+impl<T: UrlFilter + ?Sized> UrlFilter for Arc<T> {
+    fn is_match(&self, url: &Url) -> bool {
+        (**self).is_match(url)
+    }
+}
+
 #[derive(Clone)]
 pub struct AllowedSchemeUrlFilter {
     allowed_schemes: Vec<String>,
@@ -19,40 +36,198 @@ impl AllowedSchemeUrlFilter {
 
 impl UrlFilter for AllowedSchemeUrlFilter {
     fn is_match(&self, url: &Url) -> bool {
-        self.allowed_schemes.iter().any(|s| url.scheme().eq_ignore_ascii_case(s))
+        self.allowed_schemes.iter().any(|s| url.scheme() == s)
     }
 }
 
-pub trait UrlNormalizer {
-    fn normalize(&self, url: Url) -> Url;
+// Matches URLs matched by all the filters, like a chain of And which is known only at runtime.
+impl<T: UrlFilter> UrlFilter for Vec<T> {
+    fn is_match(&self, url: &Url) -> bool {
+        self.iter().all(|f| f.is_match(url))
+    }
 }
 
 #[derive(Clone)]
-pub struct NoopNormalizer {}
+pub struct And<A, B>(pub A, pub B);
 
-impl UrlNormalizer for NoopNormalizer {
-    fn normalize(&self, url: Url) -> Url {
-        url
+impl<A: UrlFilter, B: UrlFilter> UrlFilter for And<A, B> {
+    fn is_match(&self, url: &Url) -> bool {
+        self.0.is_match(url) && self.1.is_match(url)
+    }
+}
+
+#[derive(Clone)]
+pub struct Or<A, B>(pub A, pub B);
+
+impl<A: UrlFilter, B: UrlFilter> UrlFilter for Or<A, B> {
+    fn is_match(&self, url: &Url) -> bool {
+        self.0.is_match(url) || self.1.is_match(url)
+    }
+}
+
+#[derive(Clone)]
+pub struct Not<F>(pub F);
+
+impl<F: UrlFilter> UrlFilter for Not<F> {
+    fn is_match(&self, url: &Url) -> bool {
+        !self.0.is_match(url)
+    }
+}
+
+// Host patterns are host names or wildcards like *.example.com, which match example.com and all its subdomains.
+// URLs are matched if their host is allowed or the allowed list is empty, and isn't denied.
+#[derive(Clone)]
+pub struct HostUrlFilter {
+    allowed_hosts: Vec<String>,
+    denied_hosts: Vec<String>,
+}
+
+impl HostUrlFilter {
+    pub fn new(allowed_hosts: Vec<String>, denied_hosts: Vec<String>) -> Self {
+        let to_lower_case = |hosts: Vec<String>| hosts.into_iter().map(|h| h.to_ascii_lowercase()).collect();
+        Self { allowed_hosts: to_lower_case(allowed_hosts), denied_hosts: to_lower_case(denied_hosts) }
+    }
+
+    fn is_host_match(host: &str, pattern: &str) -> bool {
+        match pattern.strip_prefix("*.") {
+            Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.is_empty() || sub.ends_with('.')),
+            None => host == pattern,
+        }
+    }
+}
+
+impl UrlFilter for HostUrlFilter {
+    fn is_match(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else { return false; };
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+        (self.allowed_hosts.is_empty() || self.allowed_hosts.iter().any(|p| Self::is_host_match(&host, p)))
+            && !self.denied_hosts.iter().any(|p| Self::is_host_match(&host, p))
+    }
+}
+
+// Matches URLs whose path matches any of the included patterns, or the included list is empty, and none of the
+// excluded ones.
+#[derive(Clone)]
+pub struct PathPatternUrlFilter {
+    included_paths: RegexSet,
+    excluded_paths: RegexSet,
+}
+
+impl PathPatternUrlFilter {
+    pub fn from_regexes(included_paths: &[String], excluded_paths: &[String]) -> Result<Self, regex::Error> {
+        Ok(Self { included_paths: RegexSet::new(included_paths)?, excluded_paths: RegexSet::new(excluded_paths)? })
+    }
+
+    // In globs * and ? match any characters except /, ** matches any characters, e.g. /docs/**/*.html.
+    pub fn from_globs(included_paths: &[String], excluded_paths: &[String]) -> Result<Self, regex::Error> {
+        let to_regexes = |globs: &[String]| globs.iter().map(|g| glob_to_regex(g)).collect::<Vec<_>>();
+        Self::from_regexes(&to_regexes(included_paths), &to_regexes(excluded_paths))
+    }
+}
+
+impl UrlFilter for PathPatternUrlFilter {
+    fn is_match(&self, url: &Url) -> bool {
+        let path = url.path();
+        (self.included_paths.is_empty() || self.included_paths.is_match(path)) && !self.excluded_paths.is_match(path)
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            },
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+
+    regex.push('$');
+    regex
+}
+
+// Skips URLs of files which aren't web pages by the extension of the last path segment.
+#[derive(Clone)]
+pub struct ExcludedExtensionUrlFilter {
+    extensions: Vec<String>,
+}
+
+impl ExcludedExtensionUrlFilter {
+    pub fn new(extensions: Vec<String>) -> Self {
+        Self { extensions }
+    }
+}
+
+impl Default for ExcludedExtensionUrlFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_EXCLUDED_EXTENSIONS.iter().map(|e| e.to_string()).collect())
+    }
+}
+
+impl UrlFilter for ExcludedExtensionUrlFilter {
+    fn is_match(&self, url: &Url) -> bool {
+        let file_name = url.path().rsplit('/').next().unwrap_or("");
+        match file_name.rsplit_once('.') {
+            Some((_, extension)) => !self.extensions.iter().any(|e| extension.eq_ignore_ascii_case(e)),
+            None => true,
+        }
+    }
+}
+
+// Very long URLs are usually generated by crawler traps or carry session state.
+#[derive(Clone)]
+pub struct MaxLengthUrlFilter {
+    max_length: usize,
+}
+
+impl MaxLengthUrlFilter {
+    pub fn new(max_length: usize) -> Self {
+        Self { max_length }
+    }
+}
+
+impl UrlFilter for MaxLengthUrlFilter {
+    fn is_match(&self, url: &Url) -> bool {
+        url.as_str().len() <= self.max_length
     }
 }
 
-pub struct UrlNormalizerBuilder<T = NoopNormalizer> {
-    normalizer: T,
+pub trait UrlNormalizer {
+    fn normalize(&self, url: Url) -> Url;
 }
 
-impl UrlNormalizerBuilder {
-    pub fn new() -> Self {
-        Self { normalizer: NoopNormalizer {} }
+impl<T: UrlNormalizer + ?Sized> UrlNormalizer for Arc<T> {
+    fn normalize(&self, url: Url) -> Url {
+        (**self).normalize(url)
     }
 }
 
-impl<T: UrlNormalizer> UrlNormalizerBuilder<T> {
-    pub fn add_normalizer<N: UrlNormalizer>(self, new_normalizer: N) -> UrlNormalizerBuilder<(N, T)> {
-        UrlNormalizerBuilder { normalizer: (new_normalizer, self.normalizer) }
+// Applies the normalizers in their order, like nested pairs of normalizers which are known only at runtime.
+impl<T: UrlNormalizer> UrlNormalizer for Vec<T> {
+    fn normalize(&self, url: Url) -> Url {
+        self.iter().fold(url, |url, normalizer| normalizer.normalize(url))
     }
+}
+
+#[cfg(test)]
+#[derive(Clone)]
+pub struct NoopNormalizer {}
 
-    pub fn build(self) -> T {
-        self.normalizer
+#[cfg(test)]
+impl UrlNormalizer for NoopNormalizer {
+    fn normalize(&self, url: Url) -> Url {
+        url
     }
 }
 
@@ -76,7 +251,8 @@ impl UrlNormalizer for RemoveFragmentNormalizer {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum QueryParamMatchType {
     Equals,
     StartWith,
@@ -186,6 +362,263 @@ impl UrlNormalizer for SchemeToLowerCaseNormalizer {
     }
 }
 
+// Also converts internationalized domain names to punycode and removes the trailing dot of fully qualified names.
+#[derive(Clone)]
+pub struct HostToLowerCaseNormalizer {}
+
+impl UrlNormalizer for HostToLowerCaseNormalizer {
+    fn normalize(&self, mut url: Url) -> Url {
+        let canonical_host = match url.host() {
+            Some(Host::Domain(domain)) => match Host::parse(domain.trim_end_matches('.')) {
+                Ok(Host::Domain(canonical_domain)) if canonical_domain != domain => canonical_domain,
+                _ => return url,
+            },
+            _ => return url,
+        };
+
+        let _ = url.set_host(Some(&canonical_host));
+        url
+    }
+}
+
+// The url crate already drops default ports of http and https URLs while parsing, other schemes are kept as is.
+#[derive(Clone)]
+pub struct RemoveDefaultPortNormalizer {}
+
+impl UrlNormalizer for RemoveDefaultPortNormalizer {
+    fn normalize(&self, mut url: Url) -> Url {
+        let default_port = match url.scheme() {
+            "http" | "ws" => Some(80),
+            "https" | "wss" => Some(443),
+            "ftp" => Some(21),
+            "gopher" => Some(70),
+            _ => None,
+        };
+
+        if url.port().is_some() && url.port() == default_port {
+            let _ = url.set_port(None);
+        }
+
+        url
+    }
+}
+
+// Resolves "." and ".." segments as described in RFC 3986 section 5.2.4, including percent-encoded dots.
+#[derive(Clone)]
+pub struct RemoveDotSegmentsNormalizer {}
+
+impl UrlNormalizer for RemoveDotSegmentsNormalizer {
+    fn normalize(&self, mut url: Url) -> Url {
+        if url.cannot_be_a_base() {
+            return url;
+        }
+
+        let path = url.path();
+        let input_segments = path.strip_prefix('/').unwrap_or(path).split('/').collect::<Vec<_>>();
+        let last_index = input_segments.len() - 1;
+        let mut segments = Vec::new();
+        for (index, segment) in input_segments.into_iter().enumerate() {
+            let is_dot = segment == "." || segment.eq_ignore_ascii_case("%2e");
+            let is_double_dot = matches!(segment.to_ascii_lowercase().as_str(), ".." | ".%2e" | "%2e." | "%2e%2e");
+            if is_double_dot {
+                segments.pop();
+            }
+
+            if !is_dot && !is_double_dot {
+                segments.push(segment);
+            }
+            // The resolved path of a directory keeps its trailing slash.
+            else if index == last_index {
+                segments.push("");
+            }
+        }
+
+        let new_path = format!("/{}", segments.join("/"));
+        if new_path != path {
+            url.set_path(&new_path);
+        }
+
+        url
+    }
+}
+
+#[derive(Clone)]
+pub struct RemoveDuplicateSlashesNormalizer {}
+
+impl UrlNormalizer for RemoveDuplicateSlashesNormalizer {
+    fn normalize(&self, mut url: Url) -> Url {
+        if url.cannot_be_a_base() || !url.path().contains("//") {
+            return url;
+        }
+
+        let path = url.path();
+        let mut new_path = path.split('/')
+            .filter(|s| !s.is_empty())
+            .fold(String::new(), |mut new_path, segment| {
+                new_path.push('/');
+                new_path.push_str(segment);
+                new_path
+            });
+        if new_path.is_empty() || path.ends_with('/') {
+            new_path.push('/');
+        }
+
+        url.set_path(&new_path);
+        url
+    }
+}
+
+// Decodes percent-encoded unreserved characters and uppercases hex digits of the rest percent-encoded octets,
+// RFC 3986 section 6.2.2.
+#[derive(Clone)]
+pub struct PercentEncodingNormalizer {}
+
+impl UrlNormalizer for PercentEncodingNormalizer {
+    fn normalize(&self, mut url: Url) -> Url {
+        if let Some(path) = normalize_percent_encoding(url.path()) {
+            url.set_path(&path);
+        }
+
+        if let Some(query) = url.query().and_then(normalize_percent_encoding) {
+            url.set_query(Some(&query));
+        }
+
+        if let Some(fragment) = url.fragment().and_then(normalize_percent_encoding) {
+            url.set_fragment(Some(&fragment));
+        }
+
+        url
+    }
+}
+
+// Returns None if the text is already normalized.
+fn normalize_percent_encoding(text: &str) -> Option<String> {
+    let mut normalized_text = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find('%') {
+        normalized_text.push_str(&rest[..index]);
+        let hex = rest.get(index + 1..index + 3).filter(|h| h.bytes().all(|b| b.is_ascii_hexdigit()));
+        match hex {
+            Some(hex) => {
+                let octet = u8::from_str_radix(hex, 16).unwrap();
+                if octet.is_ascii_alphanumeric() || matches!(octet, b'-' | b'.' | b'_' | b'~') {
+                    normalized_text.push(octet as char);
+                }
+                else {
+                    normalized_text.push('%');
+                    normalized_text.push_str(&hex.to_ascii_uppercase());
+                }
+
+                rest = &rest[index + 3..];
+            },
+            None => {
+                normalized_text.push('%');
+                rest = &rest[index + 1..];
+            },
+        }
+    }
+
+    normalized_text.push_str(rest);
+    Some(normalized_text).filter(|t| t != text)
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TrailingSlashPolicy {
+    // Adds the slash to paths whose last segment doesn't look like a file name, i.e. has no extension.
+    Add,
+    Remove,
+}
+
+#[derive(Clone)]
+pub struct TrailingSlashNormalizer {
+    policy: TrailingSlashPolicy,
+}
+
+impl TrailingSlashNormalizer {
+    pub fn new(policy: TrailingSlashPolicy) -> Self {
+        Self { policy }
+    }
+}
+
+impl UrlNormalizer for TrailingSlashNormalizer {
+    fn normalize(&self, mut url: Url) -> Url {
+        if url.cannot_be_a_base() {
+            return url;
+        }
+
+        let path = url.path();
+        let new_path = match self.policy {
+            TrailingSlashPolicy::Add => {
+                let last_segment = path.rsplit('/').next().unwrap_or("");
+                if last_segment.is_empty() || last_segment.contains('.') {
+                    return url;
+                }
+
+                format!("{}/", path)
+            },
+            TrailingSlashPolicy::Remove => {
+                let trimmed_path = path.trim_end_matches('/');
+                if trimmed_path.is_empty() || trimmed_path.len() == path.len() {
+                    return url;
+                }
+
+                trimmed_path.to_string()
+            },
+        };
+
+        url.set_path(&new_path);
+        url
+    }
+}
+
+// Removes the file name of directory index pages, e.g. index.html, leaving the directory path.
+#[derive(Clone)]
+pub struct RemoveDirectoryIndexNormalizer {
+    index_names: Vec<String>,
+}
+
+impl RemoveDirectoryIndexNormalizer {
+    pub fn new(index_names: Vec<String>) -> Self {
+        Self { index_names }
+    }
+}
+
+impl UrlNormalizer for RemoveDirectoryIndexNormalizer {
+    fn normalize(&self, mut url: Url) -> Url {
+        if url.cannot_be_a_base() {
+            return url;
+        }
+
+        let path = url.path();
+        let Some((directory, file_name)) = path.rsplit_once('/') else { return url; };
+        if !self.index_names.iter().any(|n| file_name.eq_ignore_ascii_case(n)) {
+            return url;
+        }
+
+        let new_path = format!("{}/", directory);
+        url.set_path(&new_path);
+        url
+    }
+}
+
+// Considers www.example.com and example.com to be the same host. It's optional because some sites serve different
+// content on them.
+#[derive(Clone)]
+pub struct RemoveWwwNormalizer {}
+
+impl UrlNormalizer for RemoveWwwNormalizer {
+    fn normalize(&self, mut url: Url) -> Url {
+        let host = match url.host_str().and_then(|h| h.strip_prefix("www.")) {
+            Some(host) if host.contains('.') => host.to_string(),
+            _ => return url,
+        };
+
+        let _ = url.set_host(Some(&host));
+        url
+    }
+}
+
 pub trait UrlProcessor {
     fn parse_url(&self, base_url: &Url, new_url: &str) -> Option<Url>;
 
@@ -218,12 +651,160 @@ where
         self.process_url(parsed_url)
     }
 
+    // URLs are filtered after they are normalized, so that other forms of a URL can't bypass the filters.
     fn process_url(&self, url: Url) -> Option<Url> {
+        let url = self.normalizer.normalize(url);
         if !self.filter.is_match(&url) {
             return None;
         }
 
-        Some(self.normalizer.normalize(url))
+        Some(url)
+    }
+}
+
+// Processes URLs of some origins with their own processors.
+#[derive(Clone)]
+pub struct PerSiteUrlProcessor<P> {
+    default_processor: P,
+    site_processors: Arc<HashMap<String, P>>,
+}
+
+impl<P: UrlProcessor> PerSiteUrlProcessor<P> {
+    pub fn new(default_processor: P, site_processors: HashMap<String, P>) -> Self {
+        Self { default_processor, site_processors: Arc::new(site_processors) }
+    }
+
+    fn get_processor(&self, url: &Url) -> &P {
+        self.site_processors.get(&url.origin().ascii_serialization()).unwrap_or(&self.default_processor)
+    }
+}
+
+impl<P: UrlProcessor> UrlProcessor for PerSiteUrlProcessor<P> {
+    fn parse_url(&self, base_url: &Url, new_url: &str) -> Option<Url> {
+        let parsed_url = base_url.join(new_url).ok()?;
+        self.process_url(parsed_url)
+    }
+
+    fn process_url(&self, url: Url) -> Option<Url> {
+        self.get_processor(&url).process_url(url)
+    }
+}
+
+#[cfg(test)]
+mod filter_combinators_tests {
+    use super::*;
+
+    #[test]
+    fn should_combine_filters() {
+        // Arrange
+
+        let target = Or(
+            And(AllowedSchemeUrlFilter::new(vec!["https".to_string()]), Not(MaxLengthUrlFilter::new(25))),
+            HostUrlFilter::new(vec!["localhost".to_string()], Vec::new()));
+
+        // Act
+
+        let result1 = target.is_match(&Url::parse("https://example.com/long-path").unwrap());
+        let result2 = target.is_match(&Url::parse("https://example.com/").unwrap());
+        let result3 = target.is_match(&Url::parse("http://example.com/long-path").unwrap());
+        let result4 = target.is_match(&Url::parse("http://localhost/").unwrap());
+
+        // Assert
+
+        assert!(result1);
+        assert!(!result2);
+        assert!(!result3);
+        assert!(result4);
+    }
+}
+
+#[cfg(test)]
+mod host_url_filter_tests {
+    use super::*;
+
+    #[test]
+    fn should_match_allowed_and_not_denied_hosts() {
+        // Arrange
+
+        let target = HostUrlFilter::new(
+            vec!["*.Example.com".to_string(), "localhost".to_string()],
+            vec!["*.ads.example.com".to_string()]);
+
+        // Act
+
+        let results = [
+            "https://example.com/", "https://docs.example.com./", "https://ads.example.com/",
+            "https://cdn.ads.example.com/", "https://notexample.com/", "https://localhost/", "https://sub.localhost/",
+        ].map(|u| target.is_match(&Url::parse(u).unwrap()));
+
+        // Assert
+
+        assert_eq!([true, true, false, false, false, true, false], results);
+    }
+}
+
+#[cfg(test)]
+mod path_pattern_url_filter_tests {
+    use super::*;
+
+    #[test]
+    fn should_match_paths_by_regexes() {
+        // Arrange
+
+        let target = PathPatternUrlFilter::from_regexes(
+            &["^/docs/".to_string()], &[r"/v\d+/".to_string()]).unwrap();
+
+        // Act
+
+        let results = ["https://localhost/docs/intro", "https://localhost/docs/v2/intro", "https://localhost/blog"]
+            .map(|u| target.is_match(&Url::parse(u).unwrap()));
+
+        // Assert
+
+        assert_eq!([true, false, false], results);
+    }
+
+    #[test]
+    fn should_match_paths_by_globs() {
+        // Arrange
+
+        let target = PathPatternUrlFilter::from_globs(
+            &["/docs/**/*.html".to_string(), "/?".to_string()], &["/docs/private/**".to_string()]).unwrap();
+
+        // Act
+
+        let results = [
+            "https://localhost/docs/intro.html", "https://localhost/docs/a/b/intro.html",
+            "https://localhost/docs/intro.htm", "https://localhost/docs/private/a.html", "https://localhost/a",
+            "https://localhost/ab",
+        ].map(|u| target.is_match(&Url::parse(u).unwrap()));
+
+        // Assert
+
+        assert_eq!([true, true, false, false, true, false], results);
+    }
+}
+
+#[cfg(test)]
+mod excluded_extension_url_filter_tests {
+    use super::*;
+
+    #[test]
+    fn should_skip_urls_with_excluded_extensions() {
+        // Arrange
+
+        let target = ExcludedExtensionUrlFilter::default();
+
+        // Act
+
+        let results = [
+            "https://localhost/image.PNG?size=2", "https://localhost/files/archive.tar.gz",
+            "https://localhost/page.html", "https://localhost/files.zip/", "https://localhost/docs",
+        ].map(|u| target.is_match(&Url::parse(u).unwrap()));
+
+        // Assert
+
+        assert_eq!([false, false, true, true, true], results);
     }
 }
 
@@ -349,4 +930,215 @@ mod sort_query_params_normalizer_tests {
 
         assert_eq!(Url::parse("https://localhost/path?a&y=2&z=1").unwrap(), result);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod host_to_lower_case_normalizer_tests {
+    use super::*;
+
+    #[test]
+    fn should_convert_host_to_canonical_form() {
+        // Arrange
+
+        let target = HostToLowerCaseNormalizer {};
+
+        // Act
+
+        let result1 = target.normalize(Url::parse("https://LocalHost./Path").unwrap());
+        let result2 = target.normalize(Url::parse("https://Bücher.Example/").unwrap());
+        let result3 = target.normalize(Url::parse("custom://Example.COM/Path").unwrap());
+        let result4 = target.normalize(Url::parse("https://127.0.0.1/").unwrap());
+
+        // Assert
+
+        assert_eq!(Url::parse("https://localhost/Path").unwrap(), result1);
+        assert_eq!(Url::parse("https://xn--bcher-kva.example/").unwrap(), result2);
+        assert_eq!(Url::parse("custom://example.com/Path").unwrap(), result3);
+        assert_eq!(Url::parse("https://127.0.0.1/").unwrap(), result4);
+    }
+}
+
+#[cfg(test)]
+mod remove_default_port_normalizer_tests {
+    use super::*;
+
+    #[test]
+    fn should_remove_only_default_port() {
+        // Arrange
+
+        let target = RemoveDefaultPortNormalizer {};
+
+        // Act
+
+        let result1 = target.normalize(Url::parse("https://localhost:443/path").unwrap());
+        let result2 = target.normalize(Url::parse("gopher://localhost:70/path").unwrap());
+        let result3 = target.normalize(Url::parse("http://localhost:8080/path").unwrap());
+
+        // Assert
+
+        assert_eq!("https://localhost/path", result1.as_str());
+        assert_eq!("gopher://localhost/path", result2.as_str());
+        assert_eq!("http://localhost:8080/path", result3.as_str());
+    }
+}
+
+#[cfg(test)]
+mod remove_dot_segments_normalizer_tests {
+    use super::*;
+
+    #[test]
+    fn should_resolve_dot_segments() {
+        // Arrange
+
+        let target = RemoveDotSegmentsNormalizer {};
+
+        // Act
+
+        let result1 = target.normalize(Url::parse("custom://localhost/a/./b/../c").unwrap());
+        let result2 = target.normalize(Url::parse("custom://localhost/a/b/%2E%2e").unwrap());
+        let result3 = target.normalize(Url::parse("custom://localhost/../../a/.").unwrap());
+        let result4 = target.normalize(Url::parse("https://localhost/a/b/c?q=../d").unwrap());
+
+        // Assert
+
+        assert_eq!("custom://localhost/a/c", result1.as_str());
+        assert_eq!("custom://localhost/a/", result2.as_str());
+        assert_eq!("custom://localhost/a/", result3.as_str());
+        assert_eq!("https://localhost/a/b/c?q=../d", result4.as_str());
+    }
+}
+
+#[cfg(test)]
+mod remove_duplicate_slashes_normalizer_tests {
+    use super::*;
+
+    #[test]
+    fn should_remove_duplicate_slashes() {
+        // Arrange
+
+        let target = RemoveDuplicateSlashesNormalizer {};
+
+        // Act
+
+        let result1 = target.normalize(Url::parse("https://localhost//a///b//?q=//").unwrap());
+        let result2 = target.normalize(Url::parse("https://localhost//").unwrap());
+        let result3 = target.normalize(Url::parse("https://localhost/a/b").unwrap());
+
+        // Assert
+
+        assert_eq!("https://localhost/a/b/?q=//", result1.as_str());
+        assert_eq!("https://localhost/", result2.as_str());
+        assert_eq!("https://localhost/a/b", result3.as_str());
+    }
+}
+
+#[cfg(test)]
+mod percent_encoding_normalizer_tests {
+    use super::*;
+
+    #[test]
+    fn should_decode_unreserved_chars_and_uppercase_encoded_octets() {
+        // Arrange
+
+        let target = PercentEncodingNormalizer {};
+
+        // Act
+
+        let result1 = target.normalize(Url::parse("https://localhost/%7euser/a%2fb%c3%bc?q=%41%2b%zz#%2D").unwrap());
+        let result2 = target.normalize(Url::parse("https://localhost/a%2Fb?q=%2B").unwrap());
+
+        // Assert
+
+        assert_eq!("https://localhost/~user/a%2Fb%C3%BC?q=A%2B%zz#-", result1.as_str());
+        assert_eq!("https://localhost/a%2Fb?q=%2B", result2.as_str());
+    }
+}
+
+#[cfg(test)]
+mod trailing_slash_normalizer_tests {
+    use super::*;
+
+    #[test]
+    fn should_add_trailing_slash_to_directories() {
+        // Arrange
+
+        let target = TrailingSlashNormalizer::new(TrailingSlashPolicy::Add);
+
+        // Act
+
+        let result1 = target.normalize(Url::parse("https://localhost/docs?q").unwrap());
+        let result2 = target.normalize(Url::parse("https://localhost/docs/page.html").unwrap());
+        let result3 = target.normalize(Url::parse("https://localhost/").unwrap());
+
+        // Assert
+
+        assert_eq!("https://localhost/docs/?q", result1.as_str());
+        assert_eq!("https://localhost/docs/page.html", result2.as_str());
+        assert_eq!("https://localhost/", result3.as_str());
+    }
+
+    #[test]
+    fn should_remove_trailing_slash() {
+        // Arrange
+
+        let target = TrailingSlashNormalizer::new(TrailingSlashPolicy::Remove);
+
+        // Act
+
+        let result1 = target.normalize(Url::parse("https://localhost/docs//?q").unwrap());
+        let result2 = target.normalize(Url::parse("https://localhost/").unwrap());
+
+        // Assert
+
+        assert_eq!("https://localhost/docs?q", result1.as_str());
+        assert_eq!("https://localhost/", result2.as_str());
+    }
+}
+
+#[cfg(test)]
+mod remove_directory_index_normalizer_tests {
+    use super::*;
+
+    #[test]
+    fn should_remove_index_file_name() {
+        // Arrange
+
+        let target = RemoveDirectoryIndexNormalizer::new(vec!["index.html".to_string(), "index.php".to_string()]);
+
+        // Act
+
+        let result1 = target.normalize(Url::parse("https://localhost/docs/Index.HTML?q").unwrap());
+        let result2 = target.normalize(Url::parse("https://localhost/index.php").unwrap());
+        let result3 = target.normalize(Url::parse("https://localhost/docs/index.htm").unwrap());
+
+        // Assert
+
+        assert_eq!("https://localhost/docs/?q", result1.as_str());
+        assert_eq!("https://localhost/", result2.as_str());
+        assert_eq!("https://localhost/docs/index.htm", result3.as_str());
+    }
+}
+
+#[cfg(test)]
+mod remove_www_normalizer_tests {
+    use super::*;
+
+    #[test]
+    fn should_remove_www_prefix_of_host() {
+        // Arrange
+
+        let target = RemoveWwwNormalizer {};
+
+        // Act
+
+        let result1 = target.normalize(Url::parse("https://www.example.com/path").unwrap());
+        let result2 = target.normalize(Url::parse("https://www.localhost/path").unwrap());
+        let result3 = target.normalize(Url::parse("https://wwwexample.com/path").unwrap());
+
+        // Assert
+
+        assert_eq!("https://example.com/path", result1.as_str());
+        assert_eq!("https://www.localhost/path", result2.as_str());
+        assert_eq!("https://wwwexample.com/path", result3.as_str());
+    }
+}
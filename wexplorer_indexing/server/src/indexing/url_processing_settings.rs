@@ -0,0 +1,393 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::Deserialize;
+use thiserror::Error;
+use url::{Url, Host};
+
+use super::{
+    UrlFilter, UrlNormalizer, UrlProcessorImpl, PerSiteUrlProcessor, AllowedSchemeUrlFilter, RemoveFragmentNormalizer,
+    RemoveQueryParamsNormalizer, RemoveQueryParam, QueryParamMatchType, SortQueryParamsNormalizer,
+    SchemeToLowerCaseNormalizer, HostToLowerCaseNormalizer, RemoveDefaultPortNormalizer, RemoveDotSegmentsNormalizer,
+    RemoveDuplicateSlashesNormalizer, PercentEncodingNormalizer, TrailingSlashPolicy, TrailingSlashNormalizer,
+    RemoveDirectoryIndexNormalizer, RemoveWwwNormalizer, And, Or, Not, HostUrlFilter, PathPatternUrlFilter,
+    ExcludedExtensionUrlFilter, MaxLengthUrlFilter};
+
+pub type DynUrlFilter = Arc<dyn UrlFilter + Send + Sync>;
+
+pub type DynUrlNormalizer = Arc<dyn UrlNormalizer + Send + Sync>;
+
+pub type ConfiguredUrlProcessor = PerSiteUrlProcessor<UrlProcessorImpl<DynUrlFilter, DynUrlNormalizer>>;
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UrlProcessingSettings {
+    pub allowed_schemes: Vec<String>,
+    // URLs are processed only if they are matched by all the filters, which are applied to normalized URLs.
+    #[serde(default)]
+    pub filters: Vec<UrlFilterSettings>,
+    // Normalizers are applied in the order of the list.
+    #[serde(default)]
+    pub normalizers: Vec<UrlNormalizerSettings>,
+    #[serde(default)]
+    pub sites: Vec<SiteUrlProcessingSettings>,
+}
+
+// Overrides the default settings for URLs of the origin, omitted settings are taken from the default ones. The origin
+// is matched before URLs are normalized, and it must not have a path, a query or a fragment.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteUrlProcessingSettings {
+    pub origin: String,
+    pub filters: Option<Vec<UrlFilterSettings>>,
+    pub normalizers: Option<Vec<UrlNormalizerSettings>>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum UrlFilterSettings {
+    Hosts {
+        #[serde(default)]
+        allowed: Vec<String>,
+        #[serde(default)]
+        denied: Vec<String>,
+    },
+    PathRegex {
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+    },
+    PathGlob {
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+    },
+    // The default list of images, archives, binaries etc. is used if extensions are omitted.
+    ExcludedExtensions { extensions: Option<Vec<String>> },
+    MaxLength { max_length: usize },
+    And { filters: Vec<UrlFilterSettings> },
+    Or { filters: Vec<UrlFilterSettings> },
+    Not { filter: Box<UrlFilterSettings> },
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum UrlNormalizerSettings {
+    RemoveFragment,
+    RemoveQueryParams { params: Vec<RemoveQueryParamSettings> },
+    SortQueryParams,
+    SchemeToLowerCase,
+    HostToLowerCase,
+    RemoveDefaultPort,
+    RemoveDotSegments,
+    RemoveDuplicateSlashes,
+    PercentEncoding,
+    TrailingSlash { policy: TrailingSlashPolicy },
+    RemoveDirectoryIndex { names: Vec<String> },
+    RemoveWww,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveQueryParamSettings {
+    pub match_type: QueryParamMatchType,
+    pub name: String,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum UrlProcessingSettingsError {
+    #[error("at least one allowed URL scheme is required")]
+    NoAllowedSchemes,
+    #[error("invalid URL scheme \"{0}\"")]
+    InvalidScheme(String),
+    #[error("invalid host pattern \"{0}\"")]
+    InvalidHostPattern(String),
+    #[error("invalid path pattern: {0}")]
+    InvalidPathPattern(String),
+    #[error("invalid file extension \"{0}\"")]
+    InvalidFileExtension(String),
+    #[error("max URL length must be greater than zero")]
+    ZeroMaxLength,
+    #[error("or filter requires at least one filter")]
+    EmptyOrFilter,
+    #[error("name of a removed query param is empty")]
+    EmptyQueryParamName,
+    #[error("invalid directory index file name \"{0}\"")]
+    InvalidDirectoryIndexName(String),
+    #[error("invalid web site origin \"{0}\"")]
+    InvalidSiteOrigin(String),
+    #[error("URL processing of web site {0} is configured more than once")]
+    DuplicateSite(String),
+    #[error("invalid URL processing of web site {origin}: {source}")]
+    InvalidSite { origin: String, source: Box<UrlProcessingSettingsError> },
+}
+
+pub fn build_url_processor(settings: &UrlProcessingSettings)
+    -> Result<ConfiguredUrlProcessor, UrlProcessingSettingsError>
+{
+    let default_processor = build_processor(&settings.allowed_schemes, &settings.filters, &settings.normalizers)?;
+
+    let mut site_processors = HashMap::new();
+    for site in &settings.sites {
+        let origin = Url::parse(&site.origin).ok()
+            .filter(|u| u.has_host() && u.path() == "/" && u.query().is_none() && u.fragment().is_none())
+            .map(|u| u.origin().ascii_serialization())
+            .ok_or_else(|| UrlProcessingSettingsError::InvalidSiteOrigin(site.origin.clone()))?;
+        let processor = build_processor(
+            &settings.allowed_schemes,
+            site.filters.as_ref().unwrap_or(&settings.filters),
+            site.normalizers.as_ref().unwrap_or(&settings.normalizers))
+            .map_err(|err| UrlProcessingSettingsError::InvalidSite { origin: origin.clone(), source: Box::new(err) })?;
+
+        if site_processors.insert(origin.clone(), processor).is_some() {
+            return Err(UrlProcessingSettingsError::DuplicateSite(origin));
+        }
+    }
+
+    Ok(PerSiteUrlProcessor::new(default_processor, site_processors))
+}
+
+fn build_processor(allowed_schemes: &[String], filters: &[UrlFilterSettings], normalizers: &[UrlNormalizerSettings])
+    -> Result<UrlProcessorImpl<DynUrlFilter, DynUrlNormalizer>, UrlProcessingSettingsError>
+{
+    if allowed_schemes.is_empty() {
+        return Err(UrlProcessingSettingsError::NoAllowedSchemes);
+    }
+
+    if let Some(scheme) = allowed_schemes.iter().find(|s| !is_valid_scheme(s)) {
+        return Err(UrlProcessingSettingsError::InvalidScheme(scheme.clone()));
+    }
+
+    // Schemes of parsed URLs are lowercase.
+    let allowed_schemes = allowed_schemes.iter().map(|s| s.to_ascii_lowercase()).collect();
+    let filters = filters.iter().map(build_filter).collect::<Result<Vec<_>, _>>()?;
+    let filter: DynUrlFilter = Arc::new(And(AllowedSchemeUrlFilter::new(allowed_schemes), filters));
+    let normalizers = normalizers.iter().map(build_normalizer).collect::<Result<Vec<_>, _>>()?;
+    Ok(UrlProcessorImpl::new(filter, Arc::new(normalizers)))
+}
+
+fn build_filter(settings: &UrlFilterSettings) -> Result<DynUrlFilter, UrlProcessingSettingsError> {
+    let invalid_path_pattern = |err: regex::Error| UrlProcessingSettingsError::InvalidPathPattern(err.to_string());
+    Ok(match settings {
+        UrlFilterSettings::Hosts { allowed, denied } => {
+            if let Some(pattern) = allowed.iter().chain(denied).find(|p| !is_valid_host_pattern(p)) {
+                return Err(UrlProcessingSettingsError::InvalidHostPattern(pattern.clone()));
+            }
+
+            Arc::new(HostUrlFilter::new(allowed.clone(), denied.clone()))
+        },
+        UrlFilterSettings::PathRegex { include, exclude } =>
+            Arc::new(PathPatternUrlFilter::from_regexes(include, exclude).map_err(invalid_path_pattern)?),
+        UrlFilterSettings::PathGlob { include, exclude } =>
+            Arc::new(PathPatternUrlFilter::from_globs(include, exclude).map_err(invalid_path_pattern)?),
+        UrlFilterSettings::ExcludedExtensions { extensions: None } => Arc::new(ExcludedExtensionUrlFilter::default()),
+        UrlFilterSettings::ExcludedExtensions { extensions: Some(extensions) } => {
+            if let Some(extension) = extensions.iter().find(|e| e.is_empty() || e.contains(['.', '/'])) {
+                return Err(UrlProcessingSettingsError::InvalidFileExtension(extension.clone()));
+            }
+
+            Arc::new(ExcludedExtensionUrlFilter::new(extensions.clone()))
+        },
+        UrlFilterSettings::MaxLength { max_length: 0 } => return Err(UrlProcessingSettingsError::ZeroMaxLength),
+        UrlFilterSettings::MaxLength { max_length } => Arc::new(MaxLengthUrlFilter::new(*max_length)),
+        UrlFilterSettings::And { filters } =>
+            Arc::new(filters.iter().map(build_filter).collect::<Result<Vec<_>, _>>()?),
+        UrlFilterSettings::Or { filters } => filters.iter()
+            .map(build_filter)
+            .reduce(|a, b| Ok(Arc::new(Or(a?, b?)) as DynUrlFilter))
+            .ok_or(UrlProcessingSettingsError::EmptyOrFilter)??,
+        UrlFilterSettings::Not { filter } => Arc::new(Not(build_filter(filter)?)),
+    })
+}
+
+fn build_normalizer(settings: &UrlNormalizerSettings) -> Result<DynUrlNormalizer, UrlProcessingSettingsError> {
+    Ok(match settings {
+        UrlNormalizerSettings::RemoveFragment => Arc::new(RemoveFragmentNormalizer {}),
+        UrlNormalizerSettings::RemoveQueryParams { params } => {
+            if params.iter().any(|p| p.name.is_empty()) {
+                return Err(UrlProcessingSettingsError::EmptyQueryParamName);
+            }
+
+            Arc::new(RemoveQueryParamsNormalizer::new(params.iter()
+                .map(|p| RemoveQueryParam(p.match_type.clone(), p.name.clone()))
+                .collect()))
+        },
+        UrlNormalizerSettings::SortQueryParams => Arc::new(SortQueryParamsNormalizer {}),
+        UrlNormalizerSettings::SchemeToLowerCase => Arc::new(SchemeToLowerCaseNormalizer {}),
+        UrlNormalizerSettings::HostToLowerCase => Arc::new(HostToLowerCaseNormalizer {}),
+        UrlNormalizerSettings::RemoveDefaultPort => Arc::new(RemoveDefaultPortNormalizer {}),
+        UrlNormalizerSettings::RemoveDotSegments => Arc::new(RemoveDotSegmentsNormalizer {}),
+        UrlNormalizerSettings::RemoveDuplicateSlashes => Arc::new(RemoveDuplicateSlashesNormalizer {}),
+        UrlNormalizerSettings::PercentEncoding => Arc::new(PercentEncodingNormalizer {}),
+        UrlNormalizerSettings::TrailingSlash { policy } => Arc::new(TrailingSlashNormalizer::new(policy.clone())),
+        UrlNormalizerSettings::RemoveDirectoryIndex { names } => {
+            if let Some(name) = names.iter().find(|n| n.is_empty() || n.contains('/')) {
+                return Err(UrlProcessingSettingsError::InvalidDirectoryIndexName(name.clone()));
+            }
+
+            Arc::new(RemoveDirectoryIndexNormalizer::new(names.clone()))
+        },
+        UrlNormalizerSettings::RemoveWww => Arc::new(RemoveWwwNormalizer {}),
+    })
+}
+
+// Host name or a wildcard of its subdomains, e.g. *.example.com.
+fn is_valid_host_pattern(pattern: &str) -> bool {
+    let host = pattern.strip_prefix("*.").unwrap_or(pattern);
+    !host.is_empty() && !host.contains(['*', '/', ':']) && Host::parse(host).is_ok()
+}
+
+// scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." ), RFC 3986 section 3.1.
+fn is_valid_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+#[cfg(test)]
+mod url_processing_settings_tests {
+    use crate::indexing::UrlProcessor;
+
+    use super::*;
+
+    fn create_settings() -> UrlProcessingSettings {
+        UrlProcessingSettings {
+            allowed_schemes: vec!["http".to_string(), "HTTPS".to_string()],
+            filters: vec![
+                UrlFilterSettings::ExcludedExtensions { extensions: None },
+                UrlFilterSettings::Not {
+                    filter: Box::new(UrlFilterSettings::PathGlob {
+                        include: vec!["/private/**".to_string()],
+                        exclude: Vec::new(),
+                    }),
+                },
+            ],
+            normalizers: vec![
+                UrlNormalizerSettings::RemoveFragment,
+                UrlNormalizerSettings::RemoveQueryParams {
+                    params: vec![RemoveQueryParamSettings {
+                        match_type: QueryParamMatchType::StartWith,
+                        name: "utm_".to_string(),
+                    }],
+                },
+            ],
+            sites: vec![SiteUrlProcessingSettings {
+                origin: "https://example.com".to_string(),
+                filters: Some(vec![UrlFilterSettings::Or {
+                    filters: vec![
+                        UrlFilterSettings::PathRegex { include: vec!["^/docs/".to_string()], exclude: Vec::new() },
+                        UrlFilterSettings::Hosts { allowed: vec!["*.localhost".to_string()], denied: Vec::new() },
+                    ],
+                }]),
+                normalizers: Some(vec![UrlNormalizerSettings::RemoveFragment]),
+            }],
+        }
+    }
+
+    #[test]
+    fn should_process_urls_of_sites_with_their_settings() {
+        // Arrange
+
+        let target = build_url_processor(&create_settings()).unwrap();
+
+        // Act
+
+        let result1 = target.process_url(Url::parse("http://localhost/path?utm_source=x&a=1#top").unwrap());
+        let result2 = target.process_url(Url::parse("https://example.com/docs/path?utm_source=x#top").unwrap());
+        let result3 = target.process_url(Url::parse("http://example.com/path?utm_source=x").unwrap());
+        let result4 = target.process_url(Url::parse("ftp://localhost/file").unwrap());
+        let result5 = target.process_url(Url::parse("http://localhost/private/page").unwrap());
+        let result6 = target.process_url(Url::parse("http://localhost/image.png").unwrap());
+        let result7 = target.process_url(Url::parse("https://example.com/about").unwrap());
+
+        // Assert
+
+        assert_eq!(Some(Url::parse("http://localhost/path?a=1").unwrap()), result1);
+        assert_eq!(Some(Url::parse("https://example.com/docs/path?utm_source=x").unwrap()), result2);
+        assert_eq!(Some(Url::parse("http://example.com/path").unwrap()), result3);
+        assert_eq!(None, result4);
+        assert_eq!(None, result5);
+        assert_eq!(None, result6);
+        assert_eq!(None, result7);
+    }
+
+    #[test]
+    fn should_reject_invalid_settings() {
+        // Arrange
+
+        let mut settings1 = create_settings();
+        settings1.allowed_schemes.clear();
+        let mut settings2 = create_settings();
+        settings2.allowed_schemes.push("ht tp".to_string());
+        let mut settings3 = create_settings();
+        settings3.sites[0].origin = "example.com".to_string();
+        let mut settings4 = create_settings();
+        settings4.sites.push(settings4.sites[0].clone());
+        let mut settings5 = create_settings();
+        settings5.sites[0].normalizers = Some(vec![UrlNormalizerSettings::RemoveQueryParams {
+            params: vec![RemoveQueryParamSettings { match_type: QueryParamMatchType::Equals, name: String::new() }],
+        }]);
+
+        let mut settings6 = create_settings();
+        settings6.filters.push(UrlFilterSettings::Hosts { allowed: vec!["*.*.com".to_string()], denied: Vec::new() });
+        let invalid_regex = "(".to_string();
+        let mut settings7 = create_settings();
+        settings7.filters.push(
+            UrlFilterSettings::PathRegex { include: vec![invalid_regex.clone()], exclude: Vec::new() });
+        let mut settings8 = create_settings();
+        settings8.sites[0].filters = Some(vec![UrlFilterSettings::Or { filters: Vec::new() }]);
+        let mut settings9 = create_settings();
+        settings9.sites[0].origin = "https://example.com/docs/".to_string();
+
+        // Act
+
+        let results = [
+            settings1, settings2, settings3, settings4, settings5, settings6, settings7, settings8, settings9,
+        ].map(|s| build_url_processor(&s).err());
+
+        // Assert
+
+        assert_eq!(
+            [
+                Some(UrlProcessingSettingsError::NoAllowedSchemes),
+                Some(UrlProcessingSettingsError::InvalidScheme("ht tp".to_string())),
+                Some(UrlProcessingSettingsError::InvalidSiteOrigin("example.com".to_string())),
+                Some(UrlProcessingSettingsError::DuplicateSite("https://example.com".to_string())),
+                Some(UrlProcessingSettingsError::InvalidSite {
+                    origin: "https://example.com".to_string(),
+                    source: Box::new(UrlProcessingSettingsError::EmptyQueryParamName),
+                }),
+                Some(UrlProcessingSettingsError::InvalidHostPattern("*.*.com".to_string())),
+                Some(UrlProcessingSettingsError::InvalidPathPattern(
+                    regex::Regex::new(&invalid_regex).unwrap_err().to_string())),
+                Some(UrlProcessingSettingsError::InvalidSite {
+                    origin: "https://example.com".to_string(),
+                    source: Box::new(UrlProcessingSettingsError::EmptyOrFilter),
+                }),
+                Some(UrlProcessingSettingsError::InvalidSiteOrigin("https://example.com/docs/".to_string())),
+            ],
+            results);
+    }
+
+    #[test]
+    fn should_filter_normalized_urls() {
+        // Arrange
+
+        let target = build_url_processor(&UrlProcessingSettings {
+            allowed_schemes: vec!["https".to_string()],
+            filters: vec![UrlFilterSettings::Hosts { allowed: Vec::new(), denied: vec!["example.com".to_string()] }],
+            normalizers: vec![UrlNormalizerSettings::RemoveWww],
+            sites: Vec::new(),
+        }).unwrap();
+
+        // Act
+
+        let result1 = target.process_url(Url::parse("https://www.example.com/page").unwrap());
+        let result2 = target.process_url(Url::parse("https://www.example.org/page").unwrap());
+
+        // Assert
+
+        assert_eq!(None, result1);
+        assert_eq!(Some(Url::parse("https://example.org/page").unwrap()), result2);
+    }
+}
@@ -0,0 +1,439 @@
+use std::sync::Arc;
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use sea_query::{Table, Iden, ColumnDef, Query, SimpleExpr, Expr, OnConflict, Order, Cond};
+use url::Url;
+
+use super::{SqliteSchemaStatementBuilder, SqliteQueryStatementWriter, CrawlScope, PageDirectiveSettings, SqlitePool};
+
+#[derive(Iden)]
+pub enum WebSites {
+    Table,
+    Id,
+    Url,
+    Origin,
+    Scope,
+    PathPrefix,
+    MaxDepth,
+    MaxPages,
+    PagesCount,
+    BudgetExhausted,
+    UpdatedPagesCount,
+    UnchangedPagesCount,
+    RespectCanonical,
+    RespectMetaRobots,
+    RespectXRobotsTag,
+    RespectNofollow,
+}
+
+const WEB_SITE_COLUMNS: [WebSites; 10] = [
+    WebSites::Id,
+    WebSites::Url,
+    WebSites::Scope,
+    WebSites::PathPrefix,
+    WebSites::MaxDepth,
+    WebSites::MaxPages,
+    WebSites::RespectCanonical,
+    WebSites::RespectMetaRobots,
+    WebSites::RespectXRobotsTag,
+    WebSites::RespectNofollow,
+];
+
+struct WebSiteScope {}
+
+impl WebSiteScope {
+    const SAME_ORIGIN: i32 = 0;
+
+    const SAME_DOMAIN: i32 = 1;
+
+    const PATH_PREFIX: i32 = 2;
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CrawlLimits {
+    pub max_depth: Option<u32>,
+    pub max_pages: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebSite {
+    pub id: i64,
+    pub url: Url,
+    pub scope: CrawlScope,
+    pub limits: CrawlLimits,
+    pub directives: PageDirectiveSettings,
+}
+
+impl WebSite {
+    pub fn contains(&self, url: &Url) -> bool {
+        self.scope.contains(&self.url, url)
+    }
+
+    pub fn is_depth_allowed(&self, depth: u32) -> bool {
+        self.limits.max_depth.filter(|d| depth > *d).is_none()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WebSiteStatus {
+    pub web_site: WebSite,
+    pub pages_count: u32,
+    pub budget_exhausted: bool,
+    pub stats: CrawlStats,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CrawlStats {
+    // Count of page fetches which changed the document in the searching service.
+    pub updated_pages_count: u32,
+    // Count of page fetches which didn't change the content of an already indexed page.
+    pub unchanged_pages_count: u32,
+}
+
+pub struct WebSiteStorage {
+    pool: Arc<SqlitePool>,
+    add_sql: String,
+    get_sql: String,
+    reserve_page_sql: String,
+    set_budget_exhausted_sql: String,
+    count_updated_page_sql: String,
+    count_unchanged_page_sql: String,
+}
+
+impl WebSiteStorage {
+    pub fn new(pool: Arc<SqlitePool>) -> Result<Self, rusqlite::Error> {
+        let create_table_sql = Table::create()
+            .table(WebSites::Table)
+            .if_not_exists()
+            .col(ColumnDef::new(WebSites::Id).integer().not_null().auto_increment().primary_key())
+            .col(ColumnDef::new(WebSites::Url).text().not_null().unique_key())
+            .col(ColumnDef::new(WebSites::Origin).text().not_null())
+            .col(ColumnDef::new(WebSites::Scope).integer().not_null())
+            .col(ColumnDef::new(WebSites::PathPrefix).text().null())
+            .col(ColumnDef::new(WebSites::MaxDepth).integer().null())
+            .col(ColumnDef::new(WebSites::MaxPages).integer().null())
+            .col(ColumnDef::new(WebSites::PagesCount).integer().not_null().default(0))
+            .col(ColumnDef::new(WebSites::BudgetExhausted).boolean().not_null().default(false))
+            .col(ColumnDef::new(WebSites::UpdatedPagesCount).integer().not_null().default(0))
+            .col(ColumnDef::new(WebSites::UnchangedPagesCount).integer().not_null().default(0))
+            .col(ColumnDef::new(WebSites::RespectCanonical).boolean().not_null().default(true))
+            .col(ColumnDef::new(WebSites::RespectMetaRobots).boolean().not_null().default(true))
+            .col(ColumnDef::new(WebSites::RespectXRobotsTag).boolean().not_null().default(true))
+            .col(ColumnDef::new(WebSites::RespectNofollow).boolean().not_null().default(true))
+            .to_sqlite_string();
+        pool.run_blocking(|connection| connection.execute(&create_table_sql, ()))?;
+
+        // Indexing of an already known site updates its settings but keeps the id referenced by queued and indexed
+        // links and the count of its pages, so new limits apply to the pages which are already found.
+        let add_sql = Query::insert()
+            .into_table(WebSites::Table)
+            .columns([
+                WebSites::Url,
+                WebSites::Origin,
+                WebSites::Scope,
+                WebSites::PathPrefix,
+                WebSites::MaxDepth,
+                WebSites::MaxPages,
+                WebSites::RespectCanonical,
+                WebSites::RespectMetaRobots,
+                WebSites::RespectXRobotsTag,
+                WebSites::RespectNofollow,
+                WebSites::BudgetExhausted,
+            ])
+            .values_panic([
+                SimpleExpr::Custom("?1".to_string()),
+                SimpleExpr::Custom("?2".to_string()),
+                SimpleExpr::Custom("?3".to_string()),
+                SimpleExpr::Custom("?4".to_string()),
+                SimpleExpr::Custom("?5".to_string()),
+                SimpleExpr::Custom("?6".to_string()),
+                SimpleExpr::Custom("?7".to_string()),
+                SimpleExpr::Custom("?8".to_string()),
+                SimpleExpr::Custom("?9".to_string()),
+                SimpleExpr::Custom("?10".to_string()),
+                false.into(),
+            ])
+            .on_conflict(OnConflict::column(WebSites::Url)
+                .update_columns([
+                    WebSites::Scope,
+                    WebSites::PathPrefix,
+                    WebSites::MaxDepth,
+                    WebSites::MaxPages,
+                    WebSites::RespectCanonical,
+                    WebSites::RespectMetaRobots,
+                    WebSites::RespectXRobotsTag,
+                    WebSites::RespectNofollow,
+                    WebSites::BudgetExhausted,
+                ])
+                .to_owned())
+            .returning_col(WebSites::Id)
+            .to_sqlite_string();
+
+        let get_sql = Query::select()
+            .columns(WEB_SITE_COLUMNS)
+            .from(WebSites::Table)
+            .and_where(Expr::col(WebSites::Id).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        let reserve_page_sql = Query::update()
+            .table(WebSites::Table)
+            .value(WebSites::PagesCount, Expr::col(WebSites::PagesCount).add(1))
+            .and_where(Expr::col(WebSites::Id).eq(SimpleExpr::Custom("?1".to_string())))
+            .cond_where(Cond::any()
+                .add(Expr::col(WebSites::MaxPages).is_null())
+                .add(Expr::col(WebSites::PagesCount).lt(Expr::col(WebSites::MaxPages))))
+            .to_sqlite_string();
+
+        let set_budget_exhausted_sql = Query::update()
+            .table(WebSites::Table)
+            .value(WebSites::BudgetExhausted, true)
+            .and_where(Expr::col(WebSites::Id).eq(SimpleExpr::Custom("?1".to_string())))
+            .and_where(Expr::col(WebSites::BudgetExhausted).eq(false))
+            .to_sqlite_string();
+
+        let count_updated_page_sql = Query::update()
+            .table(WebSites::Table)
+            .value(WebSites::UpdatedPagesCount, Expr::col(WebSites::UpdatedPagesCount).add(1))
+            .and_where(Expr::col(WebSites::Id).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        let count_unchanged_page_sql = Query::update()
+            .table(WebSites::Table)
+            .value(WebSites::UnchangedPagesCount, Expr::col(WebSites::UnchangedPagesCount).add(1))
+            .and_where(Expr::col(WebSites::Id).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        Ok(Self {
+            pool,
+            add_sql,
+            get_sql,
+            reserve_page_sql,
+            set_budget_exhausted_sql,
+            count_updated_page_sql,
+            count_unchanged_page_sql,
+        })
+    }
+
+    pub async fn add(&self, url: Url, scope: CrawlScope, limits: CrawlLimits, directives: PageDirectiveSettings)
+        -> Result<WebSite, rusqlite::Error>
+    {
+        let (scope_value, path_prefix) = match &scope {
+            CrawlScope::SameOrigin => (WebSiteScope::SAME_ORIGIN, None),
+            CrawlScope::SameDomain => (WebSiteScope::SAME_DOMAIN, None),
+            CrawlScope::PathPrefix(path_prefix) => (WebSiteScope::PATH_PREFIX, Some(path_prefix.clone())),
+        };
+
+        let sql = self.add_sql.clone();
+        let params = (url.clone(), limits.clone(), directives.clone());
+        let id = self.pool
+            .run(move |connection| {
+                let (url, limits, directives) = &params;
+                connection.query_row(
+                    &sql,
+                    params![
+                        url,
+                        url.origin().ascii_serialization(),
+                        scope_value,
+                        path_prefix,
+                        limits.max_depth,
+                        limits.max_pages,
+                        directives.respect_canonical,
+                        directives.respect_meta_robots,
+                        directives.respect_x_robots_tag,
+                        directives.respect_nofollow,
+                    ],
+                    |row| row.get(0))
+            })
+            .await?;
+
+        Ok(WebSite { id, url, scope, limits, directives })
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Option<WebSite>, rusqlite::Error> {
+        let sql = self.get_sql.clone();
+        self.pool.run(move |connection| connection.query_row(&sql, [id], read_web_site).optional()).await
+    }
+
+    pub async fn get_statuses(&self, origin: Option<&str>, after_id: Option<i64>, limit: u32)
+        -> Result<Vec<WebSiteStatus>, rusqlite::Error>
+    {
+        let sql = {
+            let mut query = Query::select();
+            query
+                .columns(WEB_SITE_COLUMNS)
+                .columns([
+                    WebSites::PagesCount,
+                    WebSites::BudgetExhausted,
+                    WebSites::UpdatedPagesCount,
+                    WebSites::UnchangedPagesCount,
+                ])
+                .from(WebSites::Table);
+
+            if let Some(origin) = origin {
+                query.and_where(Expr::col(WebSites::Origin).eq(origin));
+            }
+
+            if let Some(after_id) = after_id {
+                query.and_where(Expr::col(WebSites::Id).gt(after_id));
+            }
+
+            query
+                .order_by(WebSites::Id, Order::Asc)
+                .limit(limit as u64)
+                .to_sqlite_string()
+        };
+
+        self.pool
+            .run(move |connection| {
+                let mut statement = connection.prepare(&sql)?;
+                let statuses = statement
+                    .query_map((), |row| Ok(WebSiteStatus {
+                        web_site: read_web_site(row)?,
+                        pages_count: row.get(WEB_SITE_COLUMNS.len())?,
+                        budget_exhausted: row.get(WEB_SITE_COLUMNS.len() + 1)?,
+                        stats: CrawlStats {
+                            updated_pages_count: row.get(WEB_SITE_COLUMNS.len() + 2)?,
+                            unchanged_pages_count: row.get(WEB_SITE_COLUMNS.len() + 3)?,
+                        },
+                    }))?
+                    .collect();
+                statuses
+            })
+            .await
+    }
+
+    // Counts a new page of the web site if the site's page budget allows it. It's run on the connection of the caller,
+    // so that the page is counted in the same transaction it's added in. Returns false if the budget is exhausted.
+    pub fn reserve_page(&self, connection: &Connection, id: i64) -> Result<bool, rusqlite::Error> {
+        Ok(connection.prepare_cached(&self.reserve_page_sql)?.execute([id])? > 0)
+    }
+
+    // Counts new pages of the web site while the site's page budget allows it. Returns the count of reserved pages,
+    // which is less than the requested one if the budget is exhausted.
+    pub async fn reserve_pages(&self, id: i64, count: u32) -> Result<u32, rusqlite::Error> {
+        self.execute_repeatedly(&self.reserve_page_sql, id, count).await
+    }
+
+    pub async fn set_budget_exhausted(&self, id: i64) -> Result<(), rusqlite::Error> {
+        self.execute(&self.set_budget_exhausted_sql, id).await
+    }
+
+    pub async fn count_updated_page(&self, id: i64) -> Result<(), rusqlite::Error> {
+        self.execute(&self.count_updated_page_sql, id).await
+    }
+
+    pub async fn count_unchanged_page(&self, id: i64) -> Result<(), rusqlite::Error> {
+        self.execute(&self.count_unchanged_page_sql, id).await
+    }
+
+    async fn execute(&self, sql: &str, id: i64) -> Result<(), rusqlite::Error> {
+        let sql = sql.to_string();
+        self.pool.run(move |connection| connection.execute(&sql, [id])).await?;
+        Ok(())
+    }
+
+    // Executes the statement in one transaction until it's executed the given count of times or it doesn't change
+    // the web site. Returns the count of the successful executions.
+    async fn execute_repeatedly(&self, sql: &str, id: i64, count: u32) -> Result<u32, rusqlite::Error> {
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let sql = sql.to_string();
+        self.pool
+            .run(move |connection| {
+                let transaction = connection.transaction()?;
+                let mut executed_count = 0;
+                {
+                    let mut statement = transaction.prepare_cached(&sql)?;
+                    while executed_count < count && statement.execute([id])? > 0 {
+                        executed_count += 1;
+                    }
+                }
+
+                transaction.commit()?;
+                Ok(executed_count)
+            })
+            .await
+    }
+}
+
+fn read_web_site(row: &Row) -> Result<WebSite, rusqlite::Error> {
+    let scope = match row.get::<_, i32>(2)? {
+        WebSiteScope::SAME_DOMAIN => CrawlScope::SameDomain,
+        WebSiteScope::PATH_PREFIX => CrawlScope::PathPrefix(row.get(3)?),
+        _ => CrawlScope::SameOrigin,
+    };
+
+    Ok(WebSite {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        scope,
+        limits: CrawlLimits { max_depth: row.get(4)?, max_pages: row.get(5)? },
+        directives: PageDirectiveSettings {
+            respect_canonical: row.get(6)?,
+            respect_meta_robots: row.get(7)?,
+            respect_x_robots_tag: row.get(8)?,
+            respect_nofollow: row.get(9)?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod web_site_storage_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_keep_id_when_site_is_added_again() {
+        // Arrange
+
+        let target = WebSiteStorage::new(Arc::new(SqlitePool::open_in_memory().unwrap())).unwrap();
+        let url = Url::parse("https://localhost/docs/").unwrap();
+        let site = target
+            .add(url.clone(), CrawlScope::SameOrigin, CrawlLimits::default(), PageDirectiveSettings::default())
+            .await
+            .unwrap();
+        let scope = CrawlScope::PathPrefix("/docs/".to_string());
+        let limits = CrawlLimits { max_depth: Some(2), max_pages: None };
+        let directives = PageDirectiveSettings { respect_nofollow: false, ..Default::default() };
+
+        // Act
+
+        let result1 = target.add(url.clone(), scope.clone(), limits.clone(), directives.clone()).await.unwrap();
+        let result2 = target.get(site.id).await.unwrap();
+
+        // Assert
+
+        assert_eq!(site.id, result1.id);
+        assert_eq!(Some(WebSite { id: site.id, url, scope, limits, directives }), result2);
+    }
+
+    #[tokio::test]
+    async fn should_reserve_pages_within_budget() {
+        // Arrange
+
+        let target = WebSiteStorage::new(Arc::new(SqlitePool::open_in_memory().unwrap())).unwrap();
+        let site = target.add(
+            Url::parse("https://localhost/").unwrap(), CrawlScope::SameOrigin,
+            CrawlLimits { max_depth: None, max_pages: Some(2) }, PageDirectiveSettings::default()).await.unwrap();
+
+        // Act
+
+        let result1 = target.reserve_pages(site.id, 1).await.unwrap();
+        let result2 = target.reserve_pages(site.id, 3).await.unwrap();
+        let result3 = target.reserve_pages(site.id, 1).await.unwrap();
+        target.set_budget_exhausted(site.id).await.unwrap();
+        target.count_updated_page(site.id).await.unwrap();
+        target.count_unchanged_page(site.id).await.unwrap();
+        target.count_unchanged_page(site.id).await.unwrap();
+        let result4 = target.get_statuses(Some("https://localhost"), None, 10).await.unwrap();
+
+        // Assert
+
+        assert_eq!(1, result1);
+        assert_eq!(1, result2);
+        assert_eq!(0, result3);
+        let stats = CrawlStats { updated_pages_count: 1, unchanged_pages_count: 2 };
+        assert_eq!(vec![WebSiteStatus { web_site: site, pages_count: 2, budget_exhausted: true, stats }], result4);
+    }
+}
@@ -1,11 +1,11 @@
-use std::{sync::{Arc, atomic::AtomicU64}, task::{Context, Poll}, fmt::Debug, env};
-use std::sync::Mutex;
+use std::{sync::{Arc, atomic::AtomicU64}, task::{Context, Poll}, fmt::Debug, env, time::Duration};
 use app_infrastructure::{app_config::AppConfigurationBuilder, BoxError, app_tracing, tonic::ConfigurableServer};
-use rusqlite::Connection;
+use tonic::transport::Channel;
+use wexplorer_searching_grpc_client::searching_api_client::SearchingApiClient;
 
 use api::{IndexingApiImpl, indexing_api_server::IndexingApiServer};
-use indexing::{Indexer, AllowedSchemeUrlFilter, UrlNormalizerBuilder, RemoveFragmentNormalizer, UrlProcessorImpl, UrlProcessor, RemoveQueryParamsNormalizer, RemoveQueryParam, QueryParamMatchType, SortQueryParamsNormalizer, SchemeToLowerCaseNormalizer, TextExtractor, Storage};
-use queue::IndexingQueue;
+use indexing::{Indexer, SqlitePool, SqlitePoolSettings, migrate_schema, TextExtractor, TextExtractionSettings, SqliteIndexedLinksStorage, RobotsTxtCache, SitemapLoader, RecrawlPolicy, RecrawlSettings, WebSiteStorage, DuplicateSettings, LinkGraphStorage, PageRankSettings, UrlProcessingSettings, build_url_processor, CrawlerTrapDetector, CrawlerTrapSettings, SchemeFetcher};
+use queue::{SqliteIndexingQueue, PolitenessSettings, RetrySettings};
 use tower::{Layer, Service};
 use tracing::{Instrument, instrument::Instrumented, error_span, Level};
 
@@ -57,22 +57,44 @@ async fn main() -> Result<(), BoxError> {
     let app_config = AppConfigurationBuilder::default().build()?;
     app_tracing::init_from_config(&app_config.config)?;
 
-    let url_filter = AllowedSchemeUrlFilter::new(vec!["http".to_string(), "https".to_string()]);
-    let url_normalizer = UrlNormalizerBuilder::new()
-        .add_normalizer(RemoveFragmentNormalizer {})
-        .add_normalizer(RemoveQueryParamsNormalizer::new(vec![RemoveQueryParam(QueryParamMatchType::StartWith, "utm_".to_string())]))
-        .add_normalizer(SortQueryParamsNormalizer {})
-        .add_normalizer(SchemeToLowerCaseNormalizer {})
-        .build();
-    let connection = Arc::new(Mutex::new(Connection::open("temp.db")?));
+    let crawler_trap_detector = Arc::new(
+        CrawlerTrapDetector::new(app_config.config.get::<CrawlerTrapSettings>("crawlerTraps")?));
+    let url_processing_settings = app_config.config.get::<UrlProcessingSettings>("urlProcessing")?;
+    let url_processor = build_url_processor(&url_processing_settings)?;
+    let allowed_schemes = &url_processing_settings.allowed_schemes;
+    let retry_settings = app_config.config.get::<RetrySettings>("retry")?;
+    retry_settings.validate()?;
+    let duplicate_settings = app_config.config.get::<DuplicateSettings>("duplicates")?;
+    duplicate_settings.validate()?;
+    let recrawl_settings = app_config.config.get::<RecrawlSettings>("recrawl")?;
+    recrawl_settings.validate()?;
+    let pool = Arc::new(SqlitePool::open(
+        &app_config.config.get::<String>("dbFilePath")?, &app_config.config.get::<SqlitePoolSettings>("sqlitePool")?)?);
+    migrate_schema(&pool)?;
+    let robots_txt_cache = RobotsTxtCache::new(
+        pool.clone(), Arc::new(SchemeFetcher::with_default_fetchers(Duration::from_secs(30), 5, allowed_schemes)),
+        chrono::Duration::hours(24), chrono::Duration::hours(1))?;
+    let searching_client = SearchingApiClient::new(Channel::from_static("http://localhost:8083").connect_lazy())
+        .max_decoding_message_size(usize::MAX);
     let mut indexer = Indexer::new(
-        IndexingQueue::new(connection.clone())?, Storage::new(connection)?,
-        UrlProcessorImpl::new(url_filter, url_normalizer), TextExtractor::new());
+        Arc::new(SqliteIndexingQueue::new(
+            pool.clone(), app_config.config.get::<PolitenessSettings>("politeness")?, retry_settings)?),
+        Arc::new(SqliteIndexedLinksStorage::new(pool.clone())?), WebSiteStorage::new(pool.clone())?,
+        LinkGraphStorage::new(pool)?, robots_txt_cache,
+        RecrawlPolicy::new(recrawl_settings),
+        duplicate_settings,
+        app_config.config.get::<PageRankSettings>("pageRank")?,
+        url_processor,
+        crawler_trap_detector.clone(),
+        TextExtractor::new(app_config.config.get::<TextExtractionSettings>("textExtraction")?.mode),
+        SitemapLoader::new(Arc::new(SchemeFetcher::with_default_fetchers(Duration::from_secs(60), 5, allowed_schemes))),
+        Arc::new(SchemeFetcher::with_default_fetchers(Duration::from_secs(60), 20, allowed_schemes)),
+        Arc::new(searching_client));
     indexer.start_processing(2);
 
     ConfigurableServer::builder(&app_config.config)
         .layer(LogLayer {})
-        .add_service(IndexingApiServer::new(IndexingApiImpl { indexer }))
+        .add_service(IndexingApiServer::new(IndexingApiImpl { indexer, crawler_trap_detector }))
         .serve()
         .await?;
 
@@ -1,18 +1,47 @@
-use std::{future::Future, collections::{VecDeque, HashSet}, sync::{Mutex, Arc}};
+use std::{future::Future, collections::{VecDeque, HashSet, HashMap, BTreeMap}, sync::{Mutex, Arc}, time::{Duration, Instant}};
+use chrono::{DateTime, Utc};
 use const_format::formatcp;
 
 use itertools::Itertools;
-use rusqlite::{Connection, OptionalExtension, Statement};
-use sea_query::{Table, ColumnDef, SqliteQueryBuilder, Iden, Query, Expr, Value, QueryStatementWriter, SchemaStatementBuilder, QueryStatementBuilder, Order, SimpleExpr};
-use tokio::sync::Notify;
+use rusqlite::{OptionalExtension, Statement, params, TransactionBehavior};
+use sea_query::{Table, ColumnDef, SqliteQueryBuilder, Iden, Query, Expr, Value, QueryStatementWriter, SchemaStatementBuilder, QueryStatementBuilder, Order, SimpleExpr, Cond, SelectStatement, UnionType, OnConflict};
+use serde::Deserialize;
+use tokio::{sync::Notify, select};
+use thiserror::Error;
 use tracing::{info, debug};
 use url::Url;
-use crate::indexing::{Indexer, SqliteSchemaStatementBuilder, SqliteQueryStatementWriter};
+use crate::indexing::{Indexer, SqliteSchemaStatementBuilder, SqliteQueryStatementWriter, IndexedLinks, InMemoryIndexedLinksStorage, SqlitePool, WebSiteStorage};
 
 #[derive(PartialEq)]
 pub struct QueueItem {
     pub id: i64,
-    pub url: Url
+    pub url: Url,
+    pub host: String,
+    pub site_id: i64,
+    pub depth: u32,
+    pub attempts: u32,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolitenessSettings {
+    pub min_delay_ms: u64,
+    pub max_connections_per_host: u32,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetrySettings {
+    pub max_attempts: u32,
+    pub initial_delay_secs: u64,
+    pub max_delay_secs: u64,
+}
+
+#[derive(Default)]
+struct HostState {
+    active_requests: u32,
+    next_request_time: Option<Instant>,
+    crawl_delay: Option<Duration>,
 }
 
 struct QueueItemStatus {}
@@ -21,58 +50,379 @@ impl QueueItemStatus {
     const READY: i32 = 0;
 
     const IN_PROGRESS: i32 = 1;
+
+    const FAILED: i32 = 2;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PageStatus {
+    Queued,
+    InProgress,
+    Indexed,
+    Failed,
+}
+
+impl PageStatus {
+    // Indexed pages are stored in IndexedLinks, codes of other statuses match queue item statuses.
+    const INDEXED: i32 = 3;
+
+    fn code(self) -> i32 {
+        match self {
+            PageStatus::Queued => QueueItemStatus::READY,
+            PageStatus::InProgress => QueueItemStatus::IN_PROGRESS,
+            PageStatus::Indexed => PageStatus::INDEXED,
+            PageStatus::Failed => QueueItemStatus::FAILED,
+        }
+    }
+
+    fn from_code(code: i32) -> Self {
+        match code {
+            QueueItemStatus::IN_PROGRESS => PageStatus::InProgress,
+            PageStatus::INDEXED => PageStatus::Indexed,
+            QueueItemStatus::FAILED => PageStatus::Failed,
+            _ => PageStatus::Queued,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct IndexingPage {
+    pub url: Url,
+    pub site_id: i64,
+    pub status: PageStatus,
+    pub depth: u32,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub last_indexed_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+pub struct PageFilter {
+    pub origin: Option<String>,
+    pub status: Option<PageStatus>,
+}
+
+// Pages are ordered by URL and status, a page which is indexed and queued for recrawling is listed twice.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageCursor {
+    pub url: Url,
+    pub status: PageStatus,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct OriginPageCounts {
+    pub site_id: i64,
+    pub origin: String,
+    pub queued: u64,
+    pub in_progress: u64,
+    pub indexed: u64,
+    pub failed: u64,
+}
+
+pub struct QueueItemPriority {}
+
+impl QueueItemPriority {
+    pub const NEW: i32 = 0;
+
+    pub const RECRAWL: i32 = 1;
 }
 
 #[derive(Iden)]
-enum Queue {
+pub enum Queue {
     Table,
     Id,
     Url,
+    Origin,
+    Host,
     Status,
+    Priority,
+    SiteId,
+    Depth,
+    Attempts,
+    NextAttemptTimestamp,
+    LastError,
+}
+
+#[derive(Iden)]
+enum Pages {
+    Table,
+    Url,
+    Status,
+    SiteId,
+    Origin,
+    Depth,
+    Attempts,
+    LastError,
+    LastIndexedTimestamp,
+    Count,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EnqueueBatchResult {
+    // Whether each link was added.
+    pub inserted: Vec<bool>,
+    pub is_budget_exhausted: bool,
+}
+
+pub type DynIndexingQueue = Arc<dyn IndexingQueue>;
+
+// Frontier of the crawler, it hands out queue items in order of priority while keeping politeness of hosts.
+#[tonic::async_trait]
+pub trait IndexingQueue: Send + Sync {
+    async fn enqueue(&self, url: Url, site_id: i64, depth: u32, priority: i32) -> Result<bool, rusqlite::Error>;
+
+    // Enqueues links found on a page at once, each with its own priority. Links which are already in the queue are
+    // skipped unless they failed. Each added new page takes a page of the web site budget, links after the first new
+    // page over the budget aren't added.
+    async fn enqueue_batch(
+        &self, links: &[(Url, i32)], site_id: i64, depth: u32, web_site_storage: &Arc<WebSiteStorage>)
+        -> Result<EnqueueBatchResult, rusqlite::Error>;
+
+    async fn peek(&self) -> Result<QueueItem, rusqlite::Error>;
+
+    async fn mark_processed(&self, item: &QueueItem) -> Result<(), rusqlite::Error>;
+
+    // Schedules the next attempt with an exponential backoff, or marks the item as failed if it's out of attempts.
+    // The host isn't requested again before retry_after if it's specified. Returns false if the item failed.
+    async fn retry_later(&self, item: &QueueItem, error: &str, retry_after: Option<Duration>)
+        -> Result<bool, rusqlite::Error>;
+
+    async fn mark_failed(&self, item: &QueueItem, error: &str) -> Result<(), rusqlite::Error>;
+
+    fn set_crawl_delay(&self, host: &str, crawl_delay: Option<Duration>);
+
+    // Requests which aren't made for queue items, e.g. of sitemaps, keep the politeness of their hosts too. The host
+    // must be released after the request.
+    async fn acquire_host(&self, host: &str);
+
+    fn release_host(&self, host: &str);
+
+    async fn get_indexing_origins(&self, site_ids: Vec<i64>) -> Result<Vec<OriginPageCounts>, rusqlite::Error>;
+
+    async fn get_indexing_pages(&self, filter: &PageFilter, cursor: Option<&PageCursor>, limit: u32)
+        -> Result<Vec<IndexingPage>, rusqlite::Error>;
+}
+
+// Longer delays can't be added to timestamps of next attempts.
+const MAX_RETRY_DELAY_SECS: u64 = 365 * 24 * 60 * 60;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RetrySettingsError {
+    #[error("max attempts must be greater than zero")]
+    ZeroMaxAttempts,
+    #[error("initial delay must not be greater than max delay")]
+    InitialDelayAboveMaxDelay,
+    #[error("max delay must not be greater than {} seconds", MAX_RETRY_DELAY_SECS)]
+    MaxDelayTooLong,
+}
+
+impl RetrySettings {
+    pub fn validate(&self) -> Result<(), RetrySettingsError> {
+        if self.max_attempts == 0 {
+            return Err(RetrySettingsError::ZeroMaxAttempts);
+        }
+        if self.initial_delay_secs > self.max_delay_secs {
+            return Err(RetrySettingsError::InitialDelayAboveMaxDelay);
+        }
+        if self.max_delay_secs > MAX_RETRY_DELAY_SECS {
+            return Err(RetrySettingsError::MaxDelayTooLong);
+        }
+        Ok(())
+    }
+
+    // Returns the time of the next attempt and retry_after limited by the max delay.
+    fn get_next_attempt_time(&self, attempts: u32, retry_after: Option<Duration>)
+        -> (DateTime<Utc>, Option<Duration>)
+    {
+        let max_delay = Duration::from_secs(self.max_delay_secs.min(MAX_RETRY_DELAY_SECS));
+        let retry_after = retry_after.map(|d| d.min(max_delay));
+        let backoff = Duration::from_secs(self.initial_delay_secs)
+            .saturating_mul(2u32.saturating_pow(attempts))
+            .min(max_delay);
+        let delay = chrono::Duration::from_std(retry_after.map_or(backoff, |d| d.max(backoff)))
+            .unwrap_or(chrono::Duration::seconds(MAX_RETRY_DELAY_SECS as i64));
+        (Utc::now() + delay, retry_after)
+    }
+}
+
+const HOST_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+// Politeness state of hosts, it's kept in memory by all queue implementations.
+struct HostStates {
+    settings: PolitenessSettings,
+    hosts: HashMap<String, HostState>,
+}
+
+impl HostStates {
+    fn new(settings: PolitenessSettings) -> Self {
+        Self { settings, hosts: HashMap::new() }
+    }
+
+    fn get_unavailable_hosts(&self, now: Instant) -> Vec<String> {
+        self.hosts.iter()
+            .filter(|(_, h)| !self.is_host_available(h, now))
+            .map(|(host, _)| host.clone())
+            .collect()
+    }
+
+    fn is_available(&self, host: &str, now: Instant) -> bool {
+        self.hosts.get(host).is_none_or(|h| self.is_host_available(h, now))
+    }
+
+    fn get_next_available_time(&self, now: Instant) -> Option<Instant> {
+        self.hosts.values()
+            .filter(|h| h.active_requests < self.settings.max_connections_per_host)
+            .filter_map(|h| h.next_request_time)
+            .filter(|t| *t > now)
+            .min()
+    }
+
+    fn acquire(&mut self, host: &str, now: Instant) {
+        let host_state = self.hosts.entry(host.to_string()).or_default();
+        host_state.active_requests += 1;
+        host_state.next_request_time = now.checked_add(get_host_delay(&self.settings, host_state));
+    }
+
+    // Returns the time when the host may become available if it's not available now.
+    fn try_acquire(&mut self, host: &str, now: Instant) -> Result<(), Option<Instant>> {
+        if !self.is_available(host, now) {
+            return Err(self.hosts.get(host).and_then(|h| h.next_request_time).filter(|t| *t > now));
+        }
+
+        self.acquire(host, now);
+        Ok(())
+    }
+
+    fn release(&mut self, host: &str, retry_after: Option<Duration>) {
+        if let Some(host_state) = self.hosts.get_mut(host) {
+            host_state.active_requests = host_state.active_requests.saturating_sub(1);
+            let host_delay = get_host_delay(&self.settings, host_state);
+            let delay = retry_after.map_or(host_delay, |d| d.max(host_delay));
+            host_state.next_request_time = Instant::now().checked_add(delay);
+        }
+    }
+
+    fn set_crawl_delay(&mut self, host: &str, crawl_delay: Option<Duration>) {
+        self.hosts.entry(host.to_string()).or_default().crawl_delay = crawl_delay;
+    }
+
+    fn is_host_available(&self, host_state: &HostState, now: Instant) -> bool {
+        host_state.active_requests < self.settings.max_connections_per_host
+            && host_state.next_request_time.filter(|t| *t > now).is_none()
+    }
+}
+
+fn get_host_delay(settings: &PolitenessSettings, host_state: &HostState) -> Duration {
+    let min_delay = Duration::from_millis(settings.min_delay_ms);
+    host_state.crawl_delay.map_or(min_delay, |d| d.max(min_delay))
+}
+
+async fn acquire_host(hosts: &Mutex<HostStates>, new_item_notify: &Notify, host: &str) {
+    loop {
+        let now = Instant::now();
+        let next_available_time = match hosts.lock().unwrap().try_acquire(host, now) {
+            Ok(()) => return,
+            Err(next_available_time) => next_available_time,
+        };
+
+        // A released host notifies only one waiter, which may be a worker, so a busy host is checked periodically.
+        wait_for_item(new_item_notify, Some(next_available_time.unwrap_or(now + HOST_CHECK_INTERVAL))).await;
+    }
+}
+
+async fn wait_for_item(new_item_notify: &Notify, next_available_time: Option<Instant>) {
+    match next_available_time {
+        Some(time) => select! {
+            _ = new_item_notify.notified() => {},
+            _ = tokio::time::sleep_until(time.into()) => {},
+        },
+        None => new_item_notify.notified().await,
+    }
+}
+
+// Result of a peek query which is run on the connection pool.
+enum PeekResult {
+    Item(QueueItem),
+    NextAttemptTime(Option<DateTime<Utc>>),
 }
 
-pub struct IndexingQueue {
-    connection: Arc<Mutex<Connection>>,
+pub struct SqliteIndexingQueue {
+    pool: Arc<SqlitePool>,
+    retry_settings: RetrySettings,
+    hosts: Mutex<HostStates>,
+    // Peeks are serialized, otherwise two workers could take the same item or exceed the connections of a host
+    // while their queries run on the pool.
+    peek_lock: tokio::sync::Mutex<()>,
     enqueue_item_sql: String,
-    peek_item_sql: String,
     set_in_progress_sql: String,
     remove_item_sql: String,
+    set_attempt_failed_sql: String,
+    get_next_attempt_timestamp_sql: String,
     new_item_notify: Notify,
 }
 
-impl IndexingQueue {
-    pub fn new(connection: Arc<Mutex<Connection>>) -> Result<Self, rusqlite::Error> {
+impl SqliteIndexingQueue {
+    pub fn new(
+        pool: Arc<SqlitePool>, politeness_settings: PolitenessSettings, retry_settings: RetrySettings)
+        -> Result<Self, rusqlite::Error>
+    {
         {
-            let connection_guard = connection.lock().unwrap();
             let create_table_sql = Table::create()
                 .table(Queue::Table)
                 .if_not_exists()
                 .col(ColumnDef::new(Queue::Id).integer().not_null().auto_increment().primary_key())
                 .col(ColumnDef::new(Queue::Url).text().not_null().unique_key())
+                .col(ColumnDef::new(Queue::Origin).text().not_null())
+                .col(ColumnDef::new(Queue::Host).text().not_null())
                 .col(ColumnDef::new(Queue::Status).integer().not_null())
+                .col(ColumnDef::new(Queue::Priority).integer().not_null())
+                .col(ColumnDef::new(Queue::SiteId).integer().not_null())
+                .col(ColumnDef::new(Queue::Depth).integer().not_null())
+                .col(ColumnDef::new(Queue::Attempts).integer().not_null().default(0))
+                .col(ColumnDef::new(Queue::NextAttemptTimestamp).integer().null())
+                .col(ColumnDef::new(Queue::LastError).text().null())
                 .to_sqlite_string();
             let reset_in_progress_items_sql = Query::update()
                 .table(Queue::Table)
                 .value(Queue::Status, QueueItemStatus::READY)
                 .and_where(Expr::col(Queue::Status).eq(QueueItemStatus::IN_PROGRESS))
                 .to_sqlite_string();
-            connection_guard.execute(&create_table_sql, ())?;
-            connection_guard.execute(&reset_in_progress_items_sql, ())?;
+            pool.run_blocking(|connection| {
+                connection.execute(&create_table_sql, ())?;
+                connection.execute(&reset_in_progress_items_sql, ())
+            })?;
         }
 
         let enqueue_item_sql = Query::insert()
             .into_table(Queue::Table)
-            .columns([Queue::Url, Queue::Status])
-            .values_panic([SimpleExpr::Custom("?1".to_string()), QueueItemStatus::READY.into()])
-            .to_sqlite_string()
-            .replace("INSERT", "INSERT OR IGNORE");
-
-        let peek_item_sql = Query::select()
-            .columns([Queue::Id, Queue::Url])
-            .from(Queue::Table)
-            .and_where(Expr::col(Queue::Status).eq(QueueItemStatus::READY))
-            .order_by(Queue::Id, Order::Asc)
-            .limit(1)
+            .columns([
+                Queue::Url,
+                Queue::Origin,
+                Queue::Host,
+                Queue::Status,
+                Queue::Priority,
+                Queue::SiteId,
+                Queue::Depth,
+            ])
+            .values_panic([
+                SimpleExpr::Custom("?1".to_string()),
+                SimpleExpr::Custom("?2".to_string()),
+                SimpleExpr::Custom("?3".to_string()),
+                QueueItemStatus::READY.into(),
+                SimpleExpr::Custom("?4".to_string()),
+                SimpleExpr::Custom("?5".to_string()),
+                SimpleExpr::Custom("?6".to_string()),
+            ])
+            // A failed URL is queued again with new attempts, other URLs in the queue are left as they are.
+            .on_conflict(OnConflict::column(Queue::Url)
+                .update_columns([Queue::Status, Queue::Priority, Queue::SiteId, Queue::Depth])
+                .values([
+                    (Queue::Attempts, 0.into()),
+                    (Queue::NextAttemptTimestamp, Value::String(None).into()),
+                    (Queue::LastError, Value::String(None).into()),
+                ])
+                .action_and_where(Expr::col((Queue::Table, Queue::Status)).eq(QueueItemStatus::FAILED))
+                .to_owned())
             .to_sqlite_string();
 
         let set_in_progress_sql = Query::update()
@@ -86,92 +436,996 @@ impl IndexingQueue {
             .and_where(Expr::col(Queue::Id).eq(SimpleExpr::Custom("?1".to_string())))
             .to_sqlite_string();
 
+        let set_attempt_failed_sql = Query::update()
+            .table(Queue::Table)
+            .values([
+                (Queue::Status, SimpleExpr::Custom("?2".to_string())),
+                (Queue::Attempts, SimpleExpr::Custom("?3".to_string())),
+                (Queue::NextAttemptTimestamp, SimpleExpr::Custom("?4".to_string())),
+                (Queue::LastError, SimpleExpr::Custom("?5".to_string())),
+            ])
+            .and_where(Expr::col(Queue::Id).eq(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
+        let get_next_attempt_timestamp_sql = Query::select()
+            .expr(Expr::col(Queue::NextAttemptTimestamp).min())
+            .from(Queue::Table)
+            .and_where(Expr::col(Queue::Status).eq(QueueItemStatus::READY))
+            .and_where(Expr::col(Queue::NextAttemptTimestamp).gt(SimpleExpr::Custom("?1".to_string())))
+            .to_sqlite_string();
+
         Ok(Self {
-            connection,
+            pool,
+            retry_settings,
+            hosts: Mutex::new(HostStates::new(politeness_settings)),
+            peek_lock: tokio::sync::Mutex::new(()),
             enqueue_item_sql,
-            peek_item_sql,
             set_in_progress_sql,
             remove_item_sql,
+            set_attempt_failed_sql,
+            get_next_attempt_timestamp_sql,
             new_item_notify: Notify::new(),
         })
     }
 
-    pub fn enqueue(&self, url: Url) -> Result<bool, rusqlite::Error> {
-        let inserted = self.connection.lock().unwrap().execute(&self.enqueue_item_sql, [&url])? > 0;
+    fn release(&self, host: &str, retry_after: Option<Duration>) {
+        self.hosts.lock().unwrap().release(host, retry_after);
+
+        // A worker may wait for a free connection to this host.
+        self.new_item_notify.notify_one();
+    }
+
+    // New pages are counted in the transaction they are added in, so the budget is taken only by added pages.
+    async fn enqueue_items(
+        &self, links: Vec<(Url, i32)>, site_id: i64, depth: u32, web_site_storage: Option<Arc<WebSiteStorage>>)
+        -> Result<EnqueueBatchResult, rusqlite::Error>
+    {
+        let sql = self.enqueue_item_sql.clone();
+        let result = self.pool
+            .run(move |connection| {
+                let mut transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+                let mut result = EnqueueBatchResult { inserted: vec![false; links.len()], is_budget_exhausted: false };
+                for (i, (url, priority)) in links.iter().enumerate() {
+                    // An item is rolled back with its savepoint if there is no budget for it.
+                    let savepoint = transaction.savepoint()?;
+                    let is_inserted = savepoint.prepare_cached(&sql)?.execute(params![
+                        url,
+                        url.origin().ascii_serialization(),
+                        url.host_str().unwrap_or(""),
+                        priority,
+                        site_id,
+                        depth,
+                    ])? > 0;
+                    if !is_inserted {
+                        debug!("Duplicated URL {} was not added to the indexing queue", url);
+                    }
+                    else if *priority == QueueItemPriority::NEW {
+                        let is_reserved = match &web_site_storage {
+                            Some(web_site_storage) => web_site_storage.reserve_page(&savepoint, site_id)?,
+                            None => true,
+                        };
+                        if !is_reserved {
+                            result.is_budget_exhausted = true;
+                            break;
+                        }
+                    }
 
-        if inserted {
+                    savepoint.commit()?;
+                    result.inserted[i] = is_inserted;
+                }
+
+                transaction.commit()?;
+                Ok(result)
+            })
+            .await?;
+
+        for _ in result.inserted.iter().filter(|i| **i) {
             self.new_item_notify.notify_one();
         }
-        else {
-            debug!("Duplicated URL {} was not added to the indexing queue", url);
+
+        Ok(result)
+    }
+
+    pub async fn contains_authority(&self, authority: &str) -> bool {
+        false
+    }
+}
+
+#[tonic::async_trait]
+impl IndexingQueue for SqliteIndexingQueue {
+    async fn enqueue(&self, url: Url, site_id: i64, depth: u32, priority: i32) -> Result<bool, rusqlite::Error> {
+        Ok(self.enqueue_items(vec![(url, priority)], site_id, depth, None).await?.inserted[0])
+    }
+
+    async fn enqueue_batch(
+        &self, links: &[(Url, i32)], site_id: i64, depth: u32, web_site_storage: &Arc<WebSiteStorage>)
+        -> Result<EnqueueBatchResult, rusqlite::Error>
+    {
+        self.enqueue_items(links.to_vec(), site_id, depth, Some(web_site_storage.clone())).await
+    }
+
+    async fn peek(&self) -> Result<QueueItem, rusqlite::Error> {
+        let _peek_guard = self.peek_lock.lock().await;
+
+        loop {
+            let now = Instant::now();
+            let utc_now = Utc::now();
+            let unavailable_hosts = self.hosts.lock().unwrap().get_unavailable_hosts(now);
+
+            let peek_item_sql = create_peek_item_sql(unavailable_hosts);
+            let set_in_progress_sql = self.set_in_progress_sql.clone();
+            let get_next_attempt_timestamp_sql = self.get_next_attempt_timestamp_sql.clone();
+            let peek_result = self.pool
+                .run(move |connection| {
+                    let item = connection.query_row(
+                        &peek_item_sql, [utc_now],
+                        |row| Ok(QueueItem {
+                            id: row.get(0)?,
+                            url: row.get::<_, Url>(1)?,
+                            host: row.get(2)?,
+                            site_id: row.get(3)?,
+                            depth: row.get(4)?,
+                            attempts: row.get(5)?,
+                        })).optional()?;
+
+                    if let Some(item) = item {
+                        connection.execute(&set_in_progress_sql, [item.id])?;
+                        return Ok(PeekResult::Item(item));
+                    }
+
+                    let next_attempt_time = connection.query_row(
+                        &get_next_attempt_timestamp_sql, [utc_now],
+                        |row| row.get::<_, Option<DateTime<Utc>>>(0))?;
+                    Ok(PeekResult::NextAttemptTime(next_attempt_time))
+                })
+                .await?;
+
+            let next_available_time = {
+                let mut hosts = self.hosts.lock().unwrap();
+                match peek_result {
+                    PeekResult::Item(item) => {
+                        hosts.acquire(&item.host, now);
+                        return Ok(item);
+                    },
+                    PeekResult::NextAttemptTime(next_attempt_time) => {
+                        let next_attempt_time =
+                            next_attempt_time.map(|t| now + (t - utc_now).to_std().unwrap_or_default());
+                        hosts.get_next_available_time(now).into_iter().chain(next_attempt_time).min()
+                    },
+                }
+            };
+
+            wait_for_item(&self.new_item_notify, next_available_time).await;
+        }
+    }
+
+    async fn mark_processed(&self, item: &QueueItem) -> Result<(), rusqlite::Error> {
+        let (sql, id) = (self.remove_item_sql.clone(), item.id);
+        self.pool.run(move |connection| connection.execute(&sql, [id])).await?;
+        self.release(&item.host, None);
+        Ok(())
+    }
+
+    async fn retry_later(&self, item: &QueueItem, error: &str, retry_after: Option<Duration>)
+        -> Result<bool, rusqlite::Error>
+    {
+        let attempts = item.attempts + 1;
+        if attempts >= self.retry_settings.max_attempts {
+            self.mark_failed(item, error).await?;
+            return Ok(false);
+        }
+
+        let (next_attempt_time, retry_after) = self.retry_settings.get_next_attempt_time(item.attempts, retry_after);
+        debug!("Attempt {} of {} failed, next attempt at {}", attempts, item.url, next_attempt_time);
+
+        let (sql, id, error) = (self.set_attempt_failed_sql.clone(), item.id, error.to_string());
+        self.pool
+            .run(move |connection| {
+                connection.execute(&sql, params![id, QueueItemStatus::READY, attempts, next_attempt_time, error])
+            })
+            .await?;
+        self.release(&item.host, retry_after);
+        Ok(true)
+    }
+
+    async fn mark_failed(&self, item: &QueueItem, error: &str) -> Result<(), rusqlite::Error> {
+        info!("Indexing of {} failed after {} attempts", item.url, item.attempts + 1);
+
+        let (sql, id, attempts) = (self.set_attempt_failed_sql.clone(), item.id, item.attempts + 1);
+        let error = error.to_string();
+        self.pool
+            .run(move |connection| connection.execute(
+                &sql, params![id, QueueItemStatus::FAILED, attempts, Option::<DateTime<Utc>>::None, error]))
+            .await?;
+        self.release(&item.host, None);
+        Ok(())
+    }
+
+    fn set_crawl_delay(&self, host: &str, crawl_delay: Option<Duration>) {
+        self.hosts.lock().unwrap().set_crawl_delay(host, crawl_delay);
+    }
+
+    async fn acquire_host(&self, host: &str) {
+        acquire_host(&self.hosts, &self.new_item_notify, host).await;
+    }
+
+    fn release_host(&self, host: &str) {
+        self.release(host, None);
+    }
+
+    async fn get_indexing_origins(&self, site_ids: Vec<i64>) -> Result<Vec<OriginPageCounts>, rusqlite::Error> {
+        let sql = Query::select()
+            .columns([Pages::SiteId, Pages::Origin, Pages::Status])
+            .expr_as(Expr::col(Pages::Url).count(), Pages::Count)
+            .from_subquery(create_pages_query(), Pages::Table)
+            .and_where(Expr::col(Pages::SiteId).is_in(site_ids))
+            .group_by_columns([Pages::SiteId, Pages::Origin, Pages::Status])
+            .order_by(Pages::SiteId, Order::Asc)
+            .order_by(Pages::Origin, Order::Asc)
+            .to_sqlite_string();
+
+        self.pool
+            .run(move |connection| {
+                let mut statement = connection.prepare(&sql)?;
+                let mut rows = statement.query(())?;
+                let mut origins: Vec<OriginPageCounts> = Vec::new();
+
+                while let Some(row) = rows.next()? {
+                    let site_id = row.get(0)?;
+                    let origin = row.get::<_, String>(1)?;
+                    let count = row.get(3)?;
+
+                    let origin_counts = match origins.last_mut() {
+                        Some(last) if last.site_id == site_id && last.origin == origin => last,
+                        _ => {
+                            origins.push(OriginPageCounts { site_id, origin, ..Default::default() });
+                            origins.last_mut().unwrap()
+                        },
+                    };
+
+                    match PageStatus::from_code(row.get(2)?) {
+                        PageStatus::Queued => origin_counts.queued = count,
+                        PageStatus::InProgress => origin_counts.in_progress = count,
+                        PageStatus::Indexed => origin_counts.indexed = count,
+                        PageStatus::Failed => origin_counts.failed = count,
+                    }
+                }
+
+                Ok(origins)
+            })
+            .await
+    }
+
+    async fn get_indexing_pages(&self, filter: &PageFilter, cursor: Option<&PageCursor>, limit: u32)
+        -> Result<Vec<IndexingPage>, rusqlite::Error>
+    {
+        let sql = {
+            let mut query = Query::select();
+            query
+                .columns([
+                    Pages::Url,
+                    Pages::SiteId,
+                    Pages::Status,
+                    Pages::Depth,
+                    Pages::Attempts,
+                    Pages::LastError,
+                    Pages::LastIndexedTimestamp,
+                ])
+                .from_subquery(create_pages_query(), Pages::Table);
+
+            if let Some(origin) = &filter.origin {
+                query.and_where(Expr::col(Pages::Origin).eq(origin));
+            }
+
+            if let Some(status) = filter.status {
+                query.and_where(Expr::col(Pages::Status).eq(status.code()));
+            }
+
+            if let Some(cursor) = cursor {
+                query.cond_where(Cond::any()
+                    .add(Expr::col(Pages::Url).gt(cursor.url.as_str()))
+                    .add(Cond::all()
+                        .add(Expr::col(Pages::Url).eq(cursor.url.as_str()))
+                        .add(Expr::col(Pages::Status).gt(cursor.status.code()))));
+            }
+
+            query
+                .order_by(Pages::Url, Order::Asc)
+                .order_by(Pages::Status, Order::Asc)
+                .limit(limit as u64)
+                .to_sqlite_string()
+        };
+
+        self.pool
+            .run(move |connection| {
+                let mut statement = connection.prepare(&sql)?;
+                let pages = statement
+                    .query_map((), |row| Ok(IndexingPage {
+                        url: row.get(0)?,
+                        site_id: row.get(1)?,
+                        status: PageStatus::from_code(row.get(2)?),
+                        depth: row.get(3)?,
+                        attempts: row.get(4)?,
+                        last_error: row.get(5)?,
+                        last_indexed_time: row.get(6)?,
+                    }))?
+                    .collect();
+                pages
+            })
+            .await
+    }
+}
+
+struct InMemoryQueueItem {
+    url: Url,
+    host: String,
+    status: i32,
+    priority: i32,
+    site_id: i64,
+    depth: u32,
+    attempts: u32,
+    next_attempt_time: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct InMemoryQueueItems {
+    // Items are ordered by id, which is the order they were enqueued in.
+    items: BTreeMap<i64, InMemoryQueueItem>,
+    urls: HashSet<Url>,
+    last_id: i64,
+}
+
+// Keeps queue items in memory, so that indexing can be run without a database. Indexed pages are listed from the
+// in-memory indexed links storage.
+#[allow(dead_code)]
+pub struct InMemoryIndexingQueue {
+    indexed_links_storage: Arc<InMemoryIndexedLinksStorage>,
+    retry_settings: RetrySettings,
+    hosts: Mutex<HostStates>,
+    items: Mutex<InMemoryQueueItems>,
+    // Enqueuing is serialized, so that URLs of a batch can't be added by others between their check and insert.
+    enqueue_lock: tokio::sync::Mutex<()>,
+    new_item_notify: Notify,
+}
+
+#[allow(dead_code)]
+impl InMemoryIndexingQueue {
+    pub fn new(
+        indexed_links_storage: Arc<InMemoryIndexedLinksStorage>, politeness_settings: PolitenessSettings,
+        retry_settings: RetrySettings)
+        -> Self
+    {
+        Self {
+            indexed_links_storage,
+            retry_settings,
+            hosts: Mutex::new(HostStates::new(politeness_settings)),
+            items: Mutex::new(InMemoryQueueItems::default()),
+            enqueue_lock: tokio::sync::Mutex::new(()),
+            new_item_notify: Notify::new(),
+        }
+    }
+
+    fn set_attempt_failed(
+        &self, item: &QueueItem, status: i32, next_attempt_time: Option<DateTime<Utc>>, error: &str,
+        retry_after: Option<Duration>)
+    {
+        if let Some(queue_item) = self.items.lock().unwrap().items.get_mut(&item.id) {
+            queue_item.status = status;
+            queue_item.attempts = item.attempts + 1;
+            queue_item.next_attempt_time = next_attempt_time;
+            queue_item.last_error = Some(error.to_string());
         }
 
-        Ok(inserted)
+        self.release(&item.host, retry_after);
     }
 
-    pub async fn peek(&self) -> Result<QueueItem, rusqlite::Error> {
+    fn release(&self, host: &str, retry_after: Option<Duration>) {
+        self.hosts.lock().unwrap().release(host, retry_after);
+
+        // A worker may wait for a free connection to this host.
+        self.new_item_notify.notify_one();
+    }
+
+    // Adds the URL or queues its failed item again. It's called under the enqueue lock.
+    fn insert(&self, url: Url, site_id: i64, depth: u32, priority: i32) -> bool {
+        {
+            let mut items = self.items.lock().unwrap();
+            if items.urls.insert(url.clone()) {
+                items.last_id += 1;
+                let id = items.last_id;
+                items.items.insert(id, InMemoryQueueItem {
+                    host: url.host_str().unwrap_or("").to_string(),
+                    url,
+                    status: QueueItemStatus::READY,
+                    priority,
+                    site_id,
+                    depth,
+                    attempts: 0,
+                    next_attempt_time: None,
+                    last_error: None,
+                });
+            } else {
+                // A failed URL is queued again with new attempts like in the SQLite queue.
+                let failed_item = items.items.values_mut()
+                    .find(|i| i.url == url && i.status == QueueItemStatus::FAILED);
+                let Some(failed_item) = failed_item else {
+                    debug!("Duplicated URL {} was not added to the indexing queue", url);
+                    return false;
+                };
+
+                failed_item.status = QueueItemStatus::READY;
+                failed_item.priority = priority;
+                failed_item.site_id = site_id;
+                failed_item.depth = depth;
+                failed_item.attempts = 0;
+                failed_item.next_attempt_time = None;
+                failed_item.last_error = None;
+            }
+        }
+
+        self.new_item_notify.notify_one();
+        true
+    }
+
+    // URLs which aren't in the queue yet and failed URLs are enqueued.
+    fn can_enqueue(&self, url: &Url) -> bool {
+        let items = self.items.lock().unwrap();
+        !items.urls.contains(url) || items.items.values().any(|i| i.url == *url && i.status == QueueItemStatus::FAILED)
+    }
+
+    // Pages are ordered by URL and status like pages of the SQLite queue.
+    fn get_pages(&self) -> Vec<IndexingPage> {
+        let queued_pages = self.items.lock().unwrap().items.values()
+            .map(|i| IndexingPage {
+                url: i.url.clone(),
+                site_id: i.site_id,
+                status: PageStatus::from_code(i.status),
+                depth: i.depth,
+                attempts: i.attempts,
+                last_error: i.last_error.clone(),
+                last_indexed_time: None,
+            })
+            .collect::<Vec<_>>();
+
+        queued_pages.into_iter()
+            .chain(self.indexed_links_storage.get_indexed_pages())
+            .sorted_by(|a, b| (a.url.as_str(), a.status.code()).cmp(&(b.url.as_str(), b.status.code())))
+            .collect()
+    }
+}
+
+#[tonic::async_trait]
+impl IndexingQueue for InMemoryIndexingQueue {
+    async fn enqueue(&self, url: Url, site_id: i64, depth: u32, priority: i32) -> Result<bool, rusqlite::Error> {
+        let _enqueue_guard = self.enqueue_lock.lock().await;
+        Ok(self.insert(url, site_id, depth, priority))
+    }
+
+    // The budget is reserved before a new page is added, as the page can't be rolled back like in the SQLite queue.
+    async fn enqueue_batch(
+        &self, links: &[(Url, i32)], site_id: i64, depth: u32, web_site_storage: &Arc<WebSiteStorage>)
+        -> Result<EnqueueBatchResult, rusqlite::Error>
+    {
+        let _enqueue_guard = self.enqueue_lock.lock().await;
+        let mut result = EnqueueBatchResult { inserted: vec![false; links.len()], is_budget_exhausted: false };
+        for (i, (url, priority)) in links.iter().enumerate() {
+            let is_new_page = *priority == QueueItemPriority::NEW && self.can_enqueue(url);
+            if is_new_page && web_site_storage.reserve_pages(site_id, 1).await? == 0 {
+                result.is_budget_exhausted = true;
+                break;
+            }
+
+            result.inserted[i] = self.insert(url.clone(), site_id, depth, *priority);
+        }
+
+        Ok(result)
+    }
+
+    async fn peek(&self) -> Result<QueueItem, rusqlite::Error> {
         loop {
-            {
-                let connection_guard = self.connection.lock().unwrap();
-                let peek_result = connection_guard.query_row(
-                    &self.peek_item_sql, (),
-                    |row| Ok(QueueItem {
-                        id: row.get(0)?,
-                        url: row.get::<_, Url>(1)?,
-                    })).optional()?;
-
-                if let Some(item) = peek_result {
-                    connection_guard.execute(&self.set_in_progress_sql, [item.id])?;
-                    return Ok(item);
+            let next_available_time = {
+                let mut hosts = self.hosts.lock().unwrap();
+                let mut items = self.items.lock().unwrap();
+                let now = Instant::now();
+                let utc_now = Utc::now();
+
+                let next_item = items.items.iter_mut()
+                    .filter(|(_, i)| i.status == QueueItemStatus::READY)
+                    .filter(|(_, i)| i.next_attempt_time.is_none_or(|t| t <= utc_now))
+                    .filter(|(_, i)| hosts.is_available(&i.host, now))
+                    .min_by_key(|(id, i)| (i.priority, **id));
+
+                if let Some((id, item)) = next_item {
+                    item.status = QueueItemStatus::IN_PROGRESS;
+                    hosts.acquire(&item.host, now);
+                    return Ok(QueueItem {
+                        id: *id,
+                        url: item.url.clone(),
+                        host: item.host.clone(),
+                        site_id: item.site_id,
+                        depth: item.depth,
+                        attempts: item.attempts,
+                    });
                 }
+
+                let next_attempt_time = items.items.values()
+                    .filter(|i| i.status == QueueItemStatus::READY)
+                    .filter_map(|i| i.next_attempt_time)
+                    .filter(|t| *t > utc_now)
+                    .min()
+                    .map(|t| now + (t - utc_now).to_std().unwrap_or_default());
+
+                hosts.get_next_available_time(now).into_iter().chain(next_attempt_time).min()
+            };
+
+            wait_for_item(&self.new_item_notify, next_available_time).await;
+        }
+    }
+
+    async fn mark_processed(&self, item: &QueueItem) -> Result<(), rusqlite::Error> {
+        {
+            let mut items = self.items.lock().unwrap();
+            if let Some(queue_item) = items.items.remove(&item.id) {
+                items.urls.remove(&queue_item.url);
             }
+        }
 
-            self.new_item_notify.notified().await;
+        self.release(&item.host, None);
+        Ok(())
+    }
+
+    async fn retry_later(&self, item: &QueueItem, error: &str, retry_after: Option<Duration>)
+        -> Result<bool, rusqlite::Error>
+    {
+        let attempts = item.attempts + 1;
+        if attempts >= self.retry_settings.max_attempts {
+            self.mark_failed(item, error).await?;
+            return Ok(false);
         }
+
+        let (next_attempt_time, retry_after) = self.retry_settings.get_next_attempt_time(item.attempts, retry_after);
+        debug!("Attempt {} of {} failed, next attempt at {}", attempts, item.url, next_attempt_time);
+
+        self.set_attempt_failed(item, QueueItemStatus::READY, Some(next_attempt_time), error, retry_after);
+        Ok(true)
     }
 
-    pub fn mark_processed(&self, id: i64) -> Result<(), rusqlite::Error> {
-        self.connection.lock().unwrap().execute(&self.remove_item_sql, [id])?;
+    async fn mark_failed(&self, item: &QueueItem, error: &str) -> Result<(), rusqlite::Error> {
+        info!("Indexing of {} failed after {} attempts", item.url, item.attempts + 1);
+
+        self.set_attempt_failed(item, QueueItemStatus::FAILED, None, error, None);
         Ok(())
     }
 
-    pub async fn contains_authority(&self, authority: &str) -> bool {
-        false
+    fn set_crawl_delay(&self, host: &str, crawl_delay: Option<Duration>) {
+        self.hosts.lock().unwrap().set_crawl_delay(host, crawl_delay);
+    }
+
+    async fn acquire_host(&self, host: &str) {
+        acquire_host(&self.hosts, &self.new_item_notify, host).await;
+    }
+
+    fn release_host(&self, host: &str) {
+        self.release(host, None);
     }
 
-    pub async fn get_indexing_origins(&self) -> Vec<String> {
-        vec![]
+    async fn get_indexing_origins(&self, site_ids: Vec<i64>) -> Result<Vec<OriginPageCounts>, rusqlite::Error> {
+        let mut origins: BTreeMap<(i64, String), OriginPageCounts> = BTreeMap::new();
+        for page in self.get_pages().into_iter().filter(|p| site_ids.contains(&p.site_id)) {
+            let origin = page.url.origin().ascii_serialization();
+            let origin_counts = origins
+                .entry((page.site_id, origin.clone()))
+                .or_insert_with(|| OriginPageCounts { site_id: page.site_id, origin, ..Default::default() });
+
+            match page.status {
+                PageStatus::Queued => origin_counts.queued += 1,
+                PageStatus::InProgress => origin_counts.in_progress += 1,
+                PageStatus::Indexed => origin_counts.indexed += 1,
+                PageStatus::Failed => origin_counts.failed += 1,
+            }
+        }
+
+        Ok(origins.into_values().collect())
     }
 
-    pub async fn get_indexing_pages(&self) -> Vec<String> {
-        vec![]
+    async fn get_indexing_pages(&self, filter: &PageFilter, cursor: Option<&PageCursor>, limit: u32)
+        -> Result<Vec<IndexingPage>, rusqlite::Error>
+    {
+        let pages = self.get_pages().into_iter()
+            .filter(|p| filter.origin.as_ref().is_none_or(|o| p.url.origin().ascii_serialization() == *o))
+            .filter(|p| filter.status.is_none_or(|s| p.status == s))
+            .filter(|p| cursor.is_none_or(|c| (p.url.as_str(), p.status.code()) > (c.url.as_str(), c.status.code())))
+            .take(limit as usize)
+            .collect();
+        Ok(pages)
     }
 }
 
+// Union of queued pages and indexed pages with the columns of Pages.
+fn create_pages_query() -> SelectStatement {
+    let mut indexed_links_query = Query::select();
+    indexed_links_query
+        .column(IndexedLinks::Url)
+        .expr_as(Expr::val(PageStatus::INDEXED), Pages::Status)
+        .columns([IndexedLinks::SiteId, IndexedLinks::Origin, IndexedLinks::Depth])
+        .expr_as(Expr::val(0), Pages::Attempts)
+        .expr_as(Expr::val(Option::<String>::None), Pages::LastError)
+        .column(IndexedLinks::LastIndexedTimestamp)
+        .from(IndexedLinks::Table);
+
+    Query::select()
+        .columns([
+            Queue::Url,
+            Queue::Status,
+            Queue::SiteId,
+            Queue::Origin,
+            Queue::Depth,
+            Queue::Attempts,
+            Queue::LastError,
+        ])
+        .expr_as(Expr::val(Option::<String>::None), Pages::LastIndexedTimestamp)
+        .from(Queue::Table)
+        .union(UnionType::All, indexed_links_query)
+        .to_owned()
+}
+
+fn create_peek_item_sql(unavailable_hosts: Vec<String>) -> String {
+    let mut query = Query::select();
+    query
+        .columns([Queue::Id, Queue::Url, Queue::Host, Queue::SiteId, Queue::Depth, Queue::Attempts])
+        .from(Queue::Table)
+        .and_where(Expr::col(Queue::Status).eq(QueueItemStatus::READY))
+        .cond_where(Cond::any()
+            .add(Expr::col(Queue::NextAttemptTimestamp).is_null())
+            .add(Expr::col(Queue::NextAttemptTimestamp).lte(SimpleExpr::Custom("?1".to_string()))));
+
+    if !unavailable_hosts.is_empty() {
+        query.and_where(Expr::col(Queue::Host).is_not_in(unavailable_hosts));
+    }
+
+    query
+        .order_by(Queue::Priority, Order::Asc)
+        .order_by(Queue::Id, Order::Asc)
+        .limit(1)
+        .to_sqlite_string()
+}
+
 #[cfg(test)]
 mod tests {
-    use std::sync::{Arc, Mutex};
+    use std::{sync::Arc, time::Duration};
 
-    use rusqlite::Connection;
-    use tokio::runtime::Handle;
-    use tokio::task::block_in_place;
+    use chrono::Utc;
     use url::Url;
-    use crate::indexing::Indexer;
-    use crate::queue::IndexingQueue;
-
-    #[test]
-    fn test() {
-        tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .unwrap()
-            .block_on(async {
-                let connection = Connection::open("test.db").unwrap();
-                let queue = IndexingQueue::new(Arc::new(Mutex::new(connection))).unwrap();
-                queue.enqueue(Url::parse("http://localhost").unwrap()).unwrap();
-                let item = queue.peek().await.unwrap();
-                queue.mark_processed(item.id).unwrap();
-                println!("123");
-            });
-    }
-}
\ No newline at end of file
+    use crate::indexing::{
+        DynIndexedLinksStorage, SqliteIndexedLinksStorage, InMemoryIndexedLinksStorage, CacheValidators, SqlitePool,
+        WebSiteStorage, CrawlScope, CrawlLimits, PageDirectiveSettings};
+    use crate::queue::{
+        DynIndexingQueue, SqliteIndexingQueue, InMemoryIndexingQueue, PolitenessSettings, RetrySettings,
+        QueueItemPriority, PageFilter, PageCursor, PageStatus, OriginPageCounts, EnqueueBatchResult};
+
+    // Both implementations are checked with the same expectations, each in its own test.
+    type Targets = (DynIndexingQueue, DynIndexedLinksStorage, Arc<WebSiteStorage>);
+    type CreateTargets = fn(PolitenessSettings, RetrySettings) -> Targets;
+
+    fn create_sqlite_targets(politeness_settings: PolitenessSettings, retry_settings: RetrySettings) -> Targets {
+        let pool = Arc::new(SqlitePool::open_in_memory().unwrap());
+        let indexed_links_storage = Arc::new(SqliteIndexedLinksStorage::new(pool.clone()).unwrap());
+        let web_site_storage = Arc::new(WebSiteStorage::new(pool.clone()).unwrap());
+        let queue = SqliteIndexingQueue::new(pool, politeness_settings, retry_settings).unwrap();
+        (Arc::new(queue), indexed_links_storage, web_site_storage)
+    }
+
+    fn create_in_memory_targets(politeness_settings: PolitenessSettings, retry_settings: RetrySettings) -> Targets {
+        let indexed_links_storage = Arc::new(InMemoryIndexedLinksStorage::default());
+        let web_site_storage = Arc::new(WebSiteStorage::new(Arc::new(SqlitePool::open_in_memory().unwrap())).unwrap());
+        let queue = InMemoryIndexingQueue::new(indexed_links_storage.clone(), politeness_settings, retry_settings);
+        (Arc::new(queue), indexed_links_storage, web_site_storage)
+    }
+
+    async fn check_remove_processed_items(create_targets: CreateTargets) {
+        // Arrange
+
+        let (target, _, _) = create_targets(
+            PolitenessSettings { min_delay_ms: 0, max_connections_per_host: 1 },
+            RetrySettings { max_attempts: 3, initial_delay_secs: 60, max_delay_secs: 3600 });
+        let url = Url::parse("http://localhost").unwrap();
+        target.enqueue(url.clone(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+
+        // Act
+
+        let item = target.peek().await.unwrap();
+        target.mark_processed(&item).await.unwrap();
+        let pages = target.get_indexing_pages(&PageFilter::default(), None, 10).await.unwrap();
+        let enqueued = target.enqueue(url.clone(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+
+        // Assert
+
+        assert_eq!(url, item.url);
+        assert!(pages.is_empty());
+        assert!(enqueued);
+    }
+
+    #[tokio::test]
+    async fn should_remove_processed_items_with_sqlite() {
+        check_remove_processed_items(create_sqlite_targets).await;
+    }
+
+    #[tokio::test]
+    async fn should_remove_processed_items_in_memory() {
+        check_remove_processed_items(create_in_memory_targets).await;
+    }
+
+    async fn check_enqueue_batch_without_duplicates(create_targets: CreateTargets) {
+        // Arrange
+
+        let (target, _, web_site_storage) = create_targets(
+            PolitenessSettings { min_delay_ms: 0, max_connections_per_host: 3 },
+            RetrySettings { max_attempts: 3, initial_delay_secs: 60, max_delay_secs: 3600 });
+        let url = |path| Url::parse("http://localhost/").unwrap().join(path).unwrap();
+        let limits = CrawlLimits { max_depth: None, max_pages: Some(1) };
+        let site = web_site_storage
+            .add(url("/"), CrawlScope::SameOrigin, limits, PageDirectiveSettings::default()).await.unwrap();
+        target.enqueue(url("/a"), site.id, 0, QueueItemPriority::NEW).await.unwrap();
+        let links = [
+            (url("/a"), QueueItemPriority::NEW),
+            (url("/b"), QueueItemPriority::RECRAWL),
+            (url("/c"), QueueItemPriority::NEW),
+            (url("/b"), QueueItemPriority::NEW),
+            (url("/d"), QueueItemPriority::NEW),
+        ];
+
+        // Act
+
+        let result = target.enqueue_batch(&links, site.id, 1, &web_site_storage).await.unwrap();
+        let item1 = target.peek().await.unwrap();
+        let item2 = target.peek().await.unwrap();
+        let item3 = target.peek().await.unwrap();
+
+        // Assert
+
+        assert_eq!(
+            EnqueueBatchResult { inserted: vec![false, true, true, false, false], is_budget_exhausted: true },
+            result);
+        assert_eq!(
+            vec![(url("/a"), 0), (url("/c"), 1), (url("/b"), 1)],
+            [item1, item2, item3].into_iter().map(|i| (i.url, i.depth)).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn should_enqueue_batch_without_duplicates_with_sqlite() {
+        check_enqueue_batch_without_duplicates(create_sqlite_targets).await;
+    }
+
+    #[tokio::test]
+    async fn should_enqueue_batch_without_duplicates_in_memory() {
+        check_enqueue_batch_without_duplicates(create_in_memory_targets).await;
+    }
+
+    async fn check_skip_items_of_hosts_in_cooldown(create_targets: CreateTargets) {
+        // Arrange
+
+        let (target, _, _) = create_targets(
+            PolitenessSettings { min_delay_ms: 60_000, max_connections_per_host: 1 },
+            RetrySettings { max_attempts: 3, initial_delay_secs: 60, max_delay_secs: 3600 });
+        target.enqueue(Url::parse("http://host1/page1").unwrap(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+        target.enqueue(Url::parse("http://host1/page2").unwrap(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+        target.enqueue(Url::parse("http://host2/page1").unwrap(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+
+        // Act
+
+        let item1 = target.peek().await.unwrap();
+        let item2 = target.peek().await.unwrap();
+        target.mark_processed(&item1).await.unwrap();
+        let item3 = tokio::time::timeout(Duration::from_millis(100), target.peek()).await;
+
+        // Assert
+
+        assert_eq!("http://host1/page1", item1.url.as_str());
+        assert_eq!("http://host2/page1", item2.url.as_str());
+        assert!(item3.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_skip_items_of_hosts_in_cooldown_with_sqlite() {
+        check_skip_items_of_hosts_in_cooldown(create_sqlite_targets).await;
+    }
+
+    #[tokio::test]
+    async fn should_skip_items_of_hosts_in_cooldown_in_memory() {
+        check_skip_items_of_hosts_in_cooldown(create_in_memory_targets).await;
+    }
+
+    async fn check_skip_items_of_hosts_acquired_for_other_requests(create_targets: CreateTargets) {
+        // Arrange
+
+        let (target, _, _) = create_targets(
+            PolitenessSettings { min_delay_ms: 60_000, max_connections_per_host: 1 },
+            RetrySettings { max_attempts: 3, initial_delay_secs: 60, max_delay_secs: 3600 });
+        target.enqueue(Url::parse("http://host1/page1").unwrap(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+        target.enqueue(Url::parse("http://host2/page1").unwrap(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+
+        // Act
+
+        target.acquire_host("host1").await;
+        let item1 = target.peek().await.unwrap();
+        target.release_host("host1");
+        let item2 = tokio::time::timeout(Duration::from_millis(100), target.peek()).await;
+        let acquired = tokio::time::timeout(Duration::from_millis(100), target.acquire_host("host1")).await;
+
+        // Assert
+
+        assert_eq!("http://host2/page1", item1.url.as_str());
+        assert!(item2.is_err());
+        assert!(acquired.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_skip_items_of_hosts_acquired_for_other_requests_with_sqlite() {
+        check_skip_items_of_hosts_acquired_for_other_requests(create_sqlite_targets).await;
+    }
+
+    #[tokio::test]
+    async fn should_skip_items_of_hosts_acquired_for_other_requests_in_memory() {
+        check_skip_items_of_hosts_acquired_for_other_requests(create_in_memory_targets).await;
+    }
+
+    async fn check_peek_new_items_before_recrawled(create_targets: CreateTargets) {
+        // Arrange
+
+        let (target, _, _) = create_targets(
+            PolitenessSettings { min_delay_ms: 0, max_connections_per_host: 2 },
+            RetrySettings { max_attempts: 3, initial_delay_secs: 60, max_delay_secs: 3600 });
+        let recrawled_url = Url::parse("http://localhost/recrawled").unwrap();
+        target.enqueue(recrawled_url, 1, 0, QueueItemPriority::RECRAWL).await.unwrap();
+        target.enqueue(Url::parse("http://localhost/new").unwrap(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+
+        // Act
+
+        let item1 = target.peek().await.unwrap();
+        let item2 = target.peek().await.unwrap();
+
+        // Assert
+
+        assert_eq!("http://localhost/new", item1.url.as_str());
+        assert_eq!("http://localhost/recrawled", item2.url.as_str());
+    }
+
+    #[tokio::test]
+    async fn should_peek_new_items_before_recrawled_with_sqlite() {
+        check_peek_new_items_before_recrawled(create_sqlite_targets).await;
+    }
+
+    #[tokio::test]
+    async fn should_peek_new_items_before_recrawled_in_memory() {
+        check_peek_new_items_before_recrawled(create_in_memory_targets).await;
+    }
+
+    async fn check_postpone_retried_items(create_targets: CreateTargets) {
+        // Arrange
+
+        let (target, _, _) = create_targets(
+            PolitenessSettings { min_delay_ms: 0, max_connections_per_host: 2 },
+            RetrySettings { max_attempts: 3, initial_delay_secs: 60, max_delay_secs: 3600 });
+        target.enqueue(Url::parse("http://localhost/page1").unwrap(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+        target.enqueue(Url::parse("http://localhost/page2").unwrap(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+
+        // Act
+
+        let item1 = target.peek().await.unwrap();
+        target.retry_later(&item1, "timeout", None).await.unwrap();
+        let item2 = target.peek().await.unwrap();
+        let item3 = tokio::time::timeout(Duration::from_millis(100), target.peek()).await;
+
+        // Assert
+
+        assert_eq!("http://localhost/page1", item1.url.as_str());
+        assert_eq!("http://localhost/page2", item2.url.as_str());
+        assert!(item3.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_postpone_retried_items_with_sqlite() {
+        check_postpone_retried_items(create_sqlite_targets).await;
+    }
+
+    #[tokio::test]
+    async fn should_postpone_retried_items_in_memory() {
+        check_postpone_retried_items(create_in_memory_targets).await;
+    }
+
+    async fn check_retry_items_until_out_of_attempts(create_targets: CreateTargets) {
+        // Arrange
+
+        let (target, _, _) = create_targets(
+            PolitenessSettings { min_delay_ms: 0, max_connections_per_host: 1 },
+            RetrySettings { max_attempts: 2, initial_delay_secs: 0, max_delay_secs: 0 });
+        let url = Url::parse("http://localhost/page").unwrap();
+        target.enqueue(url.clone(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+
+        // Act
+
+        let item1 = target.peek().await.unwrap();
+        target.retry_later(&item1, "timeout", None).await.unwrap();
+        let item2 = target.peek().await.unwrap();
+        target.retry_later(&item2, "timeout", None).await.unwrap();
+        let item3 = tokio::time::timeout(Duration::from_millis(100), target.peek()).await;
+        let enqueued1 = target.enqueue(url.clone(), 1, 0, QueueItemPriority::NEW).await.unwrap();
+        let enqueued2 = target.enqueue(url, 1, 0, QueueItemPriority::NEW).await.unwrap();
+        let item4 = target.peek().await.unwrap();
+
+        // Assert
+
+        assert_eq!(0, item1.attempts);
+        assert_eq!(1, item2.attempts);
+        assert!(item3.is_err());
+        assert!(enqueued1);
+        assert!(!enqueued2);
+        assert_eq!(0, item4.attempts);
+    }
+
+    #[tokio::test]
+    async fn should_retry_items_until_out_of_attempts_with_sqlite() {
+        check_retry_items_until_out_of_attempts(create_sqlite_targets).await;
+    }
+
+    #[tokio::test]
+    async fn should_retry_items_until_out_of_attempts_in_memory() {
+        check_retry_items_until_out_of_attempts(create_in_memory_targets).await;
+    }
+
+    async fn check_list_queued_and_indexed_pages(create_targets: CreateTargets) {
+        // Arrange
+
+        let (target, indexed_links_storage, _) = create_targets(
+            PolitenessSettings { min_delay_ms: 0, max_connections_per_host: 2 },
+            RetrySettings { max_attempts: 3, initial_delay_secs: 60, max_delay_secs: 3600 });
+        let indexed_url = Url::parse("http://localhost/a").unwrap();
+        indexed_links_storage
+            .add(&indexed_url, 1, 0, Utc::now(), &CacheValidators::default(), None, None)
+            .await
+            .unwrap();
+        target.enqueue(Url::parse("http://localhost/c").unwrap(), 1, 1, QueueItemPriority::NEW).await.unwrap();
+        target.enqueue(Url::parse("http://localhost/b").unwrap(), 1, 1, QueueItemPriority::NEW).await.unwrap();
+        target.enqueue(Url::parse("http://other/a").unwrap(), 2, 0, QueueItemPriority::NEW).await.unwrap();
+        target.peek().await.unwrap();
+
+        // Act
+
+        let filter = PageFilter { origin: Some("http://localhost".to_string()), status: None };
+        let result1 = target.get_indexing_pages(&filter, None, 2).await.unwrap();
+        let cursor = PageCursor { url: result1[1].url.clone(), status: result1[1].status };
+        let result2 = target.get_indexing_pages(&filter, Some(&cursor), 2).await.unwrap();
+        let filter = PageFilter { origin: None, status: Some(PageStatus::InProgress) };
+        let result3 = target.get_indexing_pages(&filter, None, 10).await.unwrap();
+        let result4 = target.get_indexing_origins(vec![1]).await.unwrap();
+
+        // Assert
+
+        assert_eq!(
+            vec![("http://localhost/a", PageStatus::Indexed), ("http://localhost/b", PageStatus::Queued)],
+            result1.iter().map(|p| (p.url.as_str(), p.status)).collect::<Vec<_>>());
+        assert_eq!(vec!["http://localhost/c"], result2.iter().map(|p| p.url.as_str()).collect::<Vec<_>>());
+        assert_eq!(vec!["http://localhost/c"], result3.iter().map(|p| p.url.as_str()).collect::<Vec<_>>());
+        assert_eq!(
+            vec![OriginPageCounts {
+                site_id: 1,
+                origin: "http://localhost".to_string(),
+                queued: 1,
+                in_progress: 1,
+                indexed: 1,
+                failed: 0,
+            }],
+            result4);
+    }
+
+    #[tokio::test]
+    async fn should_list_queued_and_indexed_pages_with_sqlite() {
+        check_list_queued_and_indexed_pages(create_sqlite_targets).await;
+    }
+
+    #[tokio::test]
+    async fn should_list_queued_and_indexed_pages_in_memory() {
+        check_list_queued_and_indexed_pages(create_in_memory_targets).await;
+    }
+}
@@ -1,22 +1,35 @@
 use std::sync::Mutex;
 
 use num::clamp;
-use opensearch::{OpenSearch, http::transport::Transport, IndexParts, SearchParts};
+use opensearch::{
+    OpenSearch, http::{transport::Transport, request::JsonBody}, IndexParts, SearchParts, BulkParts, DeleteParts};
+use serde::Deserialize;
 use serde_json::{json, Value};
 use tonic::{Request, Response, Status};
-use tracing::info;
+use tracing::{info, warn};
 
 use self::{searching_api_server::SearchingApi, search_response::result::FoundEntry};
 
 tonic::include_proto! {"searching"}
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchSettings {
+    // Weight of texts of links to a page relative to its body text.
+    pub anchor_text_boost: f64,
+}
+
 pub struct SearchingApiImpl {
     open_search_client: OpenSearch,
+    settings: SearchSettings,
 }
 
 impl SearchingApiImpl {
-    pub fn new() -> Self {
-        Self { open_search_client: OpenSearch::new(Transport::single_node("http://127.0.0.1:9200").unwrap()) }
+    pub fn new(settings: SearchSettings) -> Self {
+        Self {
+            open_search_client: OpenSearch::new(Transport::single_node("http://127.0.0.1:9200").unwrap()),
+            settings,
+        }
     }
 }
 
@@ -26,10 +39,17 @@ impl SearchingApi for SearchingApiImpl {
         let request = request.into_inner();
         let body = json!({
             "url": request.url,
+            "title": request.title,
+            "description": request.description,
+            "headings": request.headings.iter().map(|h| &h.text).collect::<Vec<_>>(),
             "text": request.text,
+            "anchor_texts": request.anchor_texts,
+            "fetch_timestamp": request.fetch_timestamp,
+            "page_rank": request.page_rank,
         });
+        // The URL is used as the document id, so a recrawled page replaces its previous version.
         self.open_search_client
-            .index(IndexParts::Index("search_index"))
+            .index(IndexParts::IndexId("search_index", &request.url))
             .body(body)
             .send()
             .await
@@ -39,15 +59,44 @@ impl SearchingApi for SearchingApiImpl {
         Ok(Response::new(()))
     }
 
+    async fn delete_page(&self, request: Request<DeletePageRequest>) -> Result<Response<()>, Status> {
+        let request = request.into_inner();
+        // A page which wasn't added is missing in the index, the "not found" response is ignored then.
+        self.open_search_client
+            .delete(DeleteParts::IndexId("search_index", &request.url))
+            .send()
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+        Ok(Response::new(()))
+    }
+
     async fn search(&self, request: Request<SearchRequest>) -> Result<Response<SearchResponse>, Status> {
         let request = request.into_inner();
         let search_response = self.open_search_client
             .search(SearchParts::Index(&["search_index"]))
             .body(json!({
-                "_source": ["url"],
+                "_source": ["url", "title"],
                 "query": {
-                    "match": {
-                        "text": request.text
+                    "function_score": {
+                        "query": {
+                            "multi_match": {
+                                "query": request.text,
+                                "fields": [
+                                    "title^3",
+                                    "headings^2",
+                                    "description^2",
+                                    "text",
+                                    format!("anchor_texts^{}", self.settings.anchor_text_boost),
+                                ]
+                            }
+                        },
+                        // Pages with more authoritative inbound links rank higher, ln(2 + x) keeps the boost moderate.
+                        "field_value_factor": {
+                            "field": "page_rank",
+                            "modifier": "ln2p",
+                            "missing": 1
+                        },
+                        "boost_mode": "multiply"
                     }
                 },
                 "highlight": {
@@ -68,15 +117,44 @@ impl SearchingApi for SearchingApiImpl {
             .map(|hit| {
                 search_response::Result {
                     url: hit["_source"]["url"].as_str().unwrap().to_string(),
-                    entries: hit["highlight"]["text"].as_array().unwrap().iter()
+                    // Pages may match only by their title, headings or description and have no highlighted text.
+                    entries: hit["highlight"]["text"].as_array().map(|h| h.as_slice()).unwrap_or_default().iter()
                         .map(|h| FoundEntry {
                             text: h.as_str().unwrap().to_string(),
                         })
                         .collect(),
+                    title: hit["_source"]["title"].as_str().unwrap_or_default().to_string(),
                 }
             })
             .collect();
 
         Ok(Response::new(SearchResponse { results }))
     }
+
+    async fn set_page_ranks(&self, request: Request<SetPageRanksRequest>) -> Result<Response<()>, Status> {
+        let request = request.into_inner();
+        let body = request.page_ranks.iter()
+            .flat_map(|p| [
+                JsonBody::new(json!({ "update": { "_id": p.url } })),
+                JsonBody::new(json!({ "doc": { "page_rank": p.score } })),
+            ])
+            .collect::<Vec<_>>();
+        let bulk_response = self.open_search_client
+            .bulk(BulkParts::Index("search_index"))
+            .body(body)
+            .send().await
+            .map_err(|err| Status::internal(err.to_string()))?
+            .json::<Value>().await
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        // Pages which were ranked but not added yet fail with "document missing" errors.
+        let failed_count = bulk_response["items"].as_array().map(|i| i.as_slice()).unwrap_or_default().iter()
+            .filter(|i| !i["update"]["error"].is_null())
+            .count();
+        if failed_count > 0 {
+            warn!("Page ranks of {} pages were not updated", failed_count);
+        }
+
+        Ok(Response::new(()))
+    }
 }
\ No newline at end of file
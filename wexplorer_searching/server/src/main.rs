@@ -1,4 +1,4 @@
-use api::{searching_api_server::SearchingApiServer, SearchingApiImpl};
+use api::{searching_api_server::SearchingApiServer, SearchingApiImpl, SearchSettings};
 use app_infrastructure::{app_config::AppConfigurationBuilder, app_tracing, BoxError, tonic::ConfigurableServer};
 
 mod api;
@@ -9,7 +9,7 @@ async fn main() -> Result<(), BoxError> {
     app_tracing::init_from_config(&app_config.config)?;
 
     ConfigurableServer::builder(&app_config.config)
-        .add_service(SearchingApiServer::new(SearchingApiImpl::new()))
+        .add_service(SearchingApiServer::new(SearchingApiImpl::new(app_config.config.get::<SearchSettings>("search")?)))
         .serve()
         .await?;
 
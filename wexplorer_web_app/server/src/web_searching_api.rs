@@ -43,6 +43,7 @@ impl From<SearchResponse> for WebSearchResponse {
                     entries: r.entries.into_iter()
                         .map(|e| web_search_response::result::FoundEntry { text: e.text })
                         .collect(),
+                    title: r.title,
                 })
                 .collect(),
         }
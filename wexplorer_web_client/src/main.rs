@@ -27,7 +27,7 @@ fn main() {
                 {move || search_results.get().unwrap_or(vec![]).iter()
                     .map(|r| view! {
                         {r.entries.iter().map(|e| view! { <div>{&e.text}</div> }).collect_view()}
-                        <div><a href=&r.url>{&r.url}</a></div>
+                        <div><a href=&r.url>{if r.title.is_empty() { &r.url } else { &r.title }}</a></div>
                     })
                     .collect_view()}
             </div>